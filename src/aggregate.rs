@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::ChainDBError;
+use crate::record::FieldLayout;
+use crate::table::compare_ordering;
+
+/// Aggregation functions available for `Table::aggregate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    /// Number of records (or of records where `field` is present and not null)
+    Count,
+    /// Sum of the numeric values of `field`
+    Sum,
+    /// Average of the numeric values of `field`
+    Avg,
+    /// Smallest value of `field` (numbers, strings or RFC 3339 dates)
+    Min,
+    /// Largest value of `field` (numbers, strings or RFC 3339 dates)
+    Max,
+    /// Number of distinct values of `field`
+    CountDistinct,
+}
+
+/// A single aggregation to compute for every group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aggregation {
+    pub function: AggregateFunction,
+    /// Field path the function is applied to. Optional only for `Count`
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Name of the result. Defaults to `count` or `<function>_<field>`, e.g. `sum_total`
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+impl Aggregation {
+    pub fn new(function: AggregateFunction, field: Option<&str>) -> Self {
+        Self {
+            function,
+            field: field.map(String::from),
+            alias: None,
+        }
+    }
+
+    /// Name under which the result of this aggregation is returned
+    pub fn name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+
+        let function = match self.function {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::CountDistinct => "count_distinct",
+        };
+
+        match &self.field {
+            Some(field) => format!("{}_{}", function, field),
+            None => function.to_string(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ChainDBError> {
+        if self.field.is_none() && self.function != AggregateFunction::Count {
+            return Err(ChainDBError::ValidationError(format!(
+                "Aggregation {:?} requires a field",
+                self.function
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Result of the aggregations for one group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateGroup {
+    /// Value of each `group_by` field for this group (`null` when missing). Empty without grouping
+    pub key: serde_json::Map<String, serde_json::Value>,
+    /// Result of each aggregation, by name
+    pub values: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Running state of one aggregation
+#[derive(Debug)]
+enum Accumulator {
+    Count(u64),
+    Sum {
+        int: Option<i64>,
+        float: f64,
+        seen: bool,
+    },
+    Avg {
+        sum: f64,
+        count: u64,
+    },
+    Extreme {
+        value: Option<serde_json::Value>,
+        keep: Ordering,
+    },
+    Distinct(HashSet<String>),
+}
+
+impl Accumulator {
+    fn new(function: AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum {
+                int: Some(0),
+                float: 0.0,
+                seen: false,
+            },
+            AggregateFunction::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggregateFunction::Min => Accumulator::Extreme {
+                value: None,
+                keep: Ordering::Less,
+            },
+            AggregateFunction::Max => Accumulator::Extreme {
+                value: None,
+                keep: Ordering::Greater,
+            },
+            AggregateFunction::CountDistinct => Accumulator::Distinct(HashSet::new()),
+        }
+    }
+
+    /// Adds a value to the aggregation. `counts_record` is used by a `Count` without field
+    fn add(&mut self, value: Option<&serde_json::Value>, counts_record: bool) {
+        let value = value.filter(|value| !value.is_null());
+
+        match self {
+            Accumulator::Count(count) => {
+                if counts_record || value.is_some() {
+                    *count += 1;
+                }
+            }
+            Accumulator::Sum { int, float, seen } => {
+                if let Some(serde_json::Value::Number(number)) = value {
+                    *seen = true;
+                    *int = match (*int, number.as_i64()) {
+                        (Some(sum), Some(n)) => sum.checked_add(n),
+                        _ => None,
+                    };
+                    *float += number.as_f64().unwrap_or(0.0);
+                }
+            }
+            Accumulator::Avg { sum, count } => {
+                if let Some(n) = value.and_then(serde_json::Value::as_f64) {
+                    *sum += n;
+                    *count += 1;
+                }
+            }
+            Accumulator::Extreme {
+                value: current,
+                keep,
+            } => {
+                if let Some(value) = value {
+                    let replace = match current {
+                        None => value.is_number() || value.is_string(),
+                        Some(current) => compare_ordering(value, current) == Some(*keep),
+                    };
+                    if replace {
+                        *current = Some(value.clone());
+                    }
+                }
+            }
+            Accumulator::Distinct(values) => {
+                if let Some(value) = value {
+                    values.insert(value.to_string());
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> serde_json::Value {
+        match self {
+            Accumulator::Count(count) => serde_json::json!(count),
+            Accumulator::Sum { seen: false, .. } => serde_json::Value::Null,
+            Accumulator::Sum { int: Some(sum), .. } => serde_json::json!(sum),
+            Accumulator::Sum { float, .. } => serde_json::json!(float),
+            Accumulator::Avg { count: 0, .. } => serde_json::Value::Null,
+            Accumulator::Avg { sum, count } => serde_json::json!(sum / count as f64),
+            Accumulator::Extreme { value, .. } => value.unwrap_or(serde_json::Value::Null),
+            Accumulator::Distinct(values) => serde_json::json!(values.len()),
+        }
+    }
+}
+
+/// Computes aggregations over a stream of records, one record at a time
+#[derive(Debug)]
+pub(crate) struct Aggregator {
+    /// Where the fields of the aggregated records are
+    layout: FieldLayout,
+    group_by: Vec<String>,
+    aggregations: Vec<Aggregation>,
+    /// Groups in the order they were first seen
+    groups: Vec<(serde_json::Map<String, serde_json::Value>, Vec<Accumulator>)>,
+    group_index: HashMap<String, usize>,
+}
+
+impl Aggregator {
+    pub(crate) fn new(
+        layout: FieldLayout,
+        group_by: &[String],
+        aggregations: &[Aggregation],
+    ) -> Result<Self, ChainDBError> {
+        if aggregations.is_empty() {
+            return Err(ChainDBError::ValidationError(
+                "At least one aggregation is required".to_string(),
+            ));
+        }
+        for aggregation in aggregations {
+            aggregation.validate()?;
+        }
+
+        Ok(Self {
+            layout,
+            group_by: group_by.to_vec(),
+            aggregations: aggregations.to_vec(),
+            groups: Vec::new(),
+            group_index: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn add(&mut self, record: &serde_json::Value) {
+        let Some(record_obj) = record.as_object() else {
+            return;
+        };
+
+        let key: serde_json::Map<String, serde_json::Value> = self
+            .group_by
+            .iter()
+            .map(|field| {
+                let value = self
+                    .layout
+                    .resolve(record_obj, field)
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null);
+                (field.clone(), value)
+            })
+            .collect();
+        let key_string = serde_json::Value::Object(key.clone()).to_string();
+
+        let index = match self.group_index.get(&key_string) {
+            Some(index) => *index,
+            None => {
+                let accumulators = self
+                    .aggregations
+                    .iter()
+                    .map(|aggregation| Accumulator::new(aggregation.function))
+                    .collect();
+                self.groups.push((key, accumulators));
+                self.group_index.insert(key_string, self.groups.len() - 1);
+                self.groups.len() - 1
+            }
+        };
+
+        let accumulators = &mut self.groups[index].1;
+        for (aggregation, accumulator) in self.aggregations.iter().zip(accumulators.iter_mut()) {
+            match &aggregation.field {
+                Some(field) => accumulator.add(self.layout.resolve(record_obj, field), false),
+                None => accumulator.add(None, true),
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<AggregateGroup> {
+        let names: Vec<String> = self.aggregations.iter().map(Aggregation::name).collect();
+
+        let mut groups: Vec<AggregateGroup> = self
+            .groups
+            .into_iter()
+            .map(|(key, accumulators)| AggregateGroup {
+                key,
+                values: names
+                    .iter()
+                    .cloned()
+                    .zip(accumulators.into_iter().map(Accumulator::finish))
+                    .collect(),
+            })
+            .collect();
+
+        // Without grouping, an empty table still returns a single group (e.g. count = 0)
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push(AggregateGroup {
+                key: serde_json::Map::new(),
+                values: names
+                    .iter()
+                    .cloned()
+                    .zip(
+                        self.aggregations
+                            .iter()
+                            .map(|aggregation| Accumulator::new(aggregation.function).finish()),
+                    )
+                    .collect(),
+            });
+        }
+
+        groups
+    }
+}
@@ -22,7 +22,7 @@ pub struct AuthError {
 impl<'r> Responder<'r, 'static> for AuthError {
     fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
         let response = ApiResponse::<()>::error(self.message);
-        Ok(Json(response).respond_to(request)?)
+        Json(response).respond_to(request)
     }
 }
 
@@ -0,0 +1,80 @@
+use crate::api::models::ApiResponse;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{Responder, Response};
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+/// Revision required by the `If-Match` header, if any.
+///
+/// Accepts `"3"`, `3` and weak tags (`W/"3"`). `*` (any revision) and a missing header
+/// are both `None`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IfMatch(pub Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(value) = request.headers().get_one("If-Match") else {
+            return Outcome::Success(IfMatch(None));
+        };
+
+        let value = value.trim();
+        if value == "*" {
+            return Outcome::Success(IfMatch(None));
+        }
+
+        let tag = value.trim_start_matches("W/").trim_matches('"');
+        match tag.parse::<u64>() {
+            Ok(revision) => Outcome::Success(IfMatch(Some(revision))),
+            Err(_) => Outcome::Error((
+                Status::BadRequest,
+                format!("Invalid If-Match header: {}", value),
+            )),
+        }
+    }
+}
+
+/// Formats a revision as an `ETag` value
+pub fn etag(revision: u64) -> String {
+    format!("\"{}\"", revision)
+}
+
+/// JSON API response with a status code and, when the document revision is known, an `ETag`
+#[derive(Debug)]
+pub struct Versioned<T> {
+    pub status: Status,
+    pub body: ApiResponse<T>,
+    pub revision: Option<u64>,
+}
+
+impl<T> Versioned<T> {
+    pub fn ok(body: ApiResponse<T>, revision: u64) -> Self {
+        Self {
+            status: Status::Ok,
+            body,
+            revision: Some(revision),
+        }
+    }
+
+    pub fn error(status: Status, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ApiResponse::error(message),
+            revision: None,
+        }
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Versioned<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
+        let mut response = Json(self.body).respond_to(request)?;
+        response.set_status(self.status);
+        if let Some(revision) = self.revision {
+            response.set_raw_header("ETag", etag(revision));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,136 @@
+//! Health, readiness and server information, for orchestrators and operators.
+//!
+//! `/health` only tells that the process answers requests. `/ready` checks that the data
+//! directory can be read and written and that its disk has at least
+//! `CHAIN_DB_MIN_FREE_SPACE_MB` megabytes free (100 by default).
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::chaindb::get_available_space;
+
+pub const MIN_FREE_SPACE_ENV: &str = "CHAIN_DB_MIN_FREE_SPACE_MB";
+pub const DEFAULT_MIN_FREE_SPACE_MB: u64 = 100;
+
+// Momento em que as rotas foram montadas, para o uptime de /info
+lazy_static::lazy_static! {
+    static ref STARTED: (Instant, DateTime<Utc>) = (Instant::now(), Utc::now());
+}
+
+/// Starts counting the uptime, if not started yet. Called when the routes are mounted
+pub fn start_clock() {
+    lazy_static::initialize(&STARTED);
+}
+
+/// When the server started
+pub fn started_at() -> DateTime<Utc> {
+    STARTED.1
+}
+
+/// Time since the server started
+pub fn uptime() -> Duration {
+    STARTED.0.elapsed()
+}
+
+/// Minimum free disk space for `/ready`, in bytes: `CHAIN_DB_MIN_FREE_SPACE_MB` if set,
+/// otherwise 100 MB
+pub fn min_free_space() -> u64 {
+    let megabytes = std::env::var(MIN_FREE_SPACE_ENV)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_FREE_SPACE_MB);
+    megabytes.saturating_mul(1024 * 1024)
+}
+
+/// Result of one readiness check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadinessCheck {
+    /// `readable`, `writable` or `free_space`
+    pub name: String,
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReadinessCheck {
+    fn new(name: &str, result: Result<(), String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+/// Outcome of the readiness checks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Whether every check passed
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+    /// Space available to the server on the disk of the data directory, if it could be read
+    pub free_space_bytes: Option<u64>,
+    pub min_free_space_bytes: u64,
+}
+
+/// Checks that `data_dir` exists and can be listed and written, and that its disk has at
+/// least `min_free_space` bytes available. Nothing is created besides a short-lived probe
+/// file, so a missing directory fails the `readable` and `writable` checks
+pub fn check_readiness(data_dir: &Path, min_free_space: u64) -> Readiness {
+    let readable = fs::read_dir(data_dir)
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+
+    // Cada verificação usa seu próprio arquivo, para que verificações simultâneas não se
+    // atrapalhem. Arquivos começando com ponto não são listados como bancos
+    let probe = data_dir.join(format!(".ready-{}", Uuid::new_v4()));
+    let writable = fs::write(&probe, b"ok")
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| e.to_string());
+
+    let free_space_bytes = get_available_space(data_dir);
+    let free_space = match &free_space_bytes {
+        Ok(available) if *available >= min_free_space => Ok(()),
+        Ok(available) => Err(format!(
+            "{} bytes available, at least {} required",
+            available, min_free_space
+        )),
+        Err(e) => Err(e.to_string()),
+    };
+
+    let checks = vec![
+        ReadinessCheck::new("readable", readable),
+        ReadinessCheck::new("writable", writable),
+        ReadinessCheck::new("free_space", free_space),
+    ];
+    Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+        free_space_bytes: free_space_bytes.ok(),
+        min_free_space_bytes: min_free_space,
+    }
+}
+
+/// What `/info` reports about the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Version of the chain-db package
+    pub version: String,
+    /// When the server started (RFC 3339)
+    pub started_at: String,
+    pub uptime_secs: u64,
+    /// Absolute path of the data directory
+    pub data_dir: String,
+    /// Database of the credentials used for the request
+    pub database: String,
+    /// Tables of that database kept open by the server, in alphabetical order
+    pub open_tables: Vec<String>,
+    /// Version of the on-disk format of the tables
+    pub storage_format_version: u32,
+}
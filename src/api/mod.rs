@@ -6,8 +6,12 @@ use rocket::{Request, Response};
 // use rocket_cors::{AllowedHeaders, AllowedOrigins};
 
 pub mod auth;
+pub mod etag;
+pub mod health;
 pub mod models;
+pub mod registry;
 mod routes;
+pub mod stream;
 
 pub struct CORS;
 
@@ -29,8 +33,9 @@ impl Fairing for CORS {
         ));
         response.set_header(Header::new(
             "Access-Control-Allow-Headers",
-            "Content-Type, Authorization, Accept, X-Requested-With, Origin",
+            "Content-Type, Authorization, Accept, X-Requested-With, Origin, If-Match",
         ));
+        response.set_header(Header::new("Access-Control-Expose-Headers", "ETag"));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
         response.set_header(Header::new("Access-Control-Max-Age", "86400")); // 24 horas
 
@@ -51,19 +56,41 @@ impl Fairing for CORS {
 
 #[allow(dead_code)]
 pub fn routes() -> Vec<rocket::Route> {
+    // O uptime de /info conta a partir da montagem das rotas
+    health::start_clock();
+
     routes![
         routes::cors::options_handler,
+        routes::health::health,
+        routes::health::ready,
+        routes::health::server_info,
         routes::database::create_database,
         routes::database::connect_database,
         routes::database::change_password,
+        routes::database::create_backup,
+        routes::database::list_backups,
+        routes::database::restore_backup,
+        routes::database::check_database,
+        routes::database::repair_database,
         routes::table::list_tables,
         routes::table::get_table_data,
         routes::table::get_document_by_id,
+        routes::table::patch_document,
         routes::table::update_table,
         routes::table::persist_table,
+        routes::table::upsert,
+        routes::table::persist_batch,
+        routes::table::update_batch,
         routes::table::get_history,
         routes::table::find_where,
         routes::table::find_where_advanced,
+        routes::table::aggregate,
+        routes::table::get_retention,
+        routes::table::set_retention,
+        routes::query::run_query,
+        routes::transaction::run_transaction,
+        routes::transfer::export_table,
+        routes::transfer::import_table,
         routes::events::events_ws,
         routes::events::event_types
     ]
@@ -1,4 +1,8 @@
+use crate::aggregate::Aggregation;
+use crate::explain::Explain;
+use crate::query::SortKey;
 use crate::table::ComparisonOperator;
+use rocket::FromForm;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -6,6 +10,23 @@ use std::collections::HashMap;
 pub struct TableData {
     pub data: HashMap<String, serde_json::Value>,
     pub doc_id: Option<String>,
+    /// System fields, filled in when the record is read back and ignored when it is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+}
+
+impl Default for TableData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TableData {
@@ -13,6 +34,11 @@ impl TableData {
         Self {
             data: HashMap::new(),
             doc_id: None,
+            revision: None,
+            created_at: None,
+            updated_at: None,
+            created_by: None,
+            updated_by: None,
         }
     }
 
@@ -26,7 +52,11 @@ impl TableData {
                 let data: HashMap<String, serde_json::Value> =
                     map.into_iter().filter(|(k, _)| k != "doc_id").collect();
 
-                Self { data, doc_id }
+                Self {
+                    data,
+                    doc_id,
+                    ..Self::new()
+                }
             }
             _ => Self::new(),
         }
@@ -44,6 +74,22 @@ impl TableData {
             );
         }
 
+        // Campos de sistema, somente leitura
+        if let Some(revision) = self.revision {
+            map.insert("revision".to_string(), serde_json::Value::from(revision));
+        }
+        let audit_fields = [
+            ("created_at", &self.created_at),
+            ("updated_at", &self.updated_at),
+            ("created_by", &self.created_by),
+            ("updated_by", &self.updated_by),
+        ];
+        for (field, value) in audit_fields {
+            if let Some(value) = value {
+                map.insert(field.to_string(), serde_json::Value::String(value.clone()));
+            }
+        }
+
         serde_json::Value::Object(map)
     }
 }
@@ -73,16 +119,92 @@ pub struct PersistTableRequest {
     pub data: serde_json::Value,
 }
 
+/// Body of `/upsert`: the fields identifying the document and its new content
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpsertRequest {
+    pub criteria: HashMap<String, serde_json::Value>,
+    pub data: serde_json::Value,
+}
+
+/// Body of `/persist-batch`: one `PersistTableRequest` per record
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistBatchRequest {
+    pub records: Vec<PersistTableRequest>,
+}
+
+/// Body of `/update-batch`: one `UpdateTableRequest` per record
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateBatchRequest {
+    pub records: Vec<UpdateTableRequest>,
+}
+
+/// Outcome of one record of a batch request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    /// Position of the record in the request
+    pub index: usize,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchItemResult {
+    pub fn ok(index: usize, doc_id: String, revision: u64) -> Self {
+        Self {
+            index,
+            success: true,
+            doc_id: Some(doc_id),
+            revision: Some(revision),
+            error: None,
+        }
+    }
+
+    pub fn error(index: usize, doc_id: Option<String>, error: impl ToString) -> Self {
+        Self {
+            index,
+            success: false,
+            doc_id,
+            revision: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetHistoryRequest {
     pub limit: usize,
 }
 
+/// Query string of the history route
+#[derive(Debug, FromForm)]
+pub struct HistoryParams {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    /// Comma separated sort keys, e.g. `total:desc,name`
+    pub sort: Option<String>,
+    /// Comma separated fields to keep, e.g. `name,total`
+    pub fields: Option<String>,
+    /// Count every matching record in `page.total` (reads the whole table)
+    pub count_total: Option<bool>,
+    pub explain: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: Option<String>,
     pub data: Option<T>,
+    /// Pagination details, only present on paginated query responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<PageInfo>,
+    /// Query plan and execution statistics, only present when `explain` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<Explain>,
 }
 
 impl<T> ApiResponse<T> {
@@ -91,29 +213,70 @@ impl<T> ApiResponse<T> {
             success: true,
             message: None,
             data: Some(data),
+            page: None,
+            explain: None,
+        }
+    }
+
+    pub fn paginated(data: T, total: Option<usize>, next_cursor: Option<String>) -> Self {
+        Self {
+            success: true,
+            message: None,
+            data: Some(data),
+            page: Some(PageInfo { total, next_cursor }),
+            explain: None,
         }
     }
 
+    pub fn with_explain(mut self, explain: Option<Explain>) -> Self {
+        self.explain = explain;
+        self
+    }
+
     pub fn error(message: impl Into<String>) -> Self {
         Self {
             success: false,
             message: Some(message.into()),
             data: None,
+            page: None,
+            explain: None,
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageInfo {
+    /// Total number of records matching the query, only present when it was counted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// Cursor to request the next page, `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FindWhereRequest {
     pub criteria: HashMap<String, serde_json::Value>,
     pub limit: Option<usize>,
     pub reverse: Option<bool>,
+    #[serde(default)]
+    pub sort: Vec<SortKey>,
+    pub fields: Option<Vec<String>>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    /// Count every matching record in `page.total` (reads the whole table)
+    #[serde(default)]
+    pub count_total: bool,
+    /// Return the query plan and execution statistics with the results
+    #[serde(default)]
+    pub explain: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FindWhereAdvancedCriteria {
     pub field: String,
     pub operator: ComparisonOperator,
+    /// Operand for the comparison. May be omitted for `Exists`, `NotExists` and `IsNull`
+    #[serde(default)]
     pub value: serde_json::Value,
 }
 
@@ -122,4 +285,62 @@ pub struct FindWhereAdvancedRequest {
     pub criteria: Vec<FindWhereAdvancedCriteria>,
     pub limit: Option<usize>,
     pub reverse: Option<bool>,
+    #[serde(default)]
+    pub sort: Vec<SortKey>,
+    pub fields: Option<Vec<String>>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    /// Count every matching record in `page.total` (reads the whole table)
+    #[serde(default)]
+    pub count_total: bool,
+    /// Return the query plan and execution statistics with the results
+    #[serde(default)]
+    pub explain: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateRequest {
+    /// Filter applied before aggregating (empty for every record)
+    #[serde(default)]
+    pub criteria: Vec<FindWhereAdvancedCriteria>,
+    /// Fields used to group the records (empty for a single group)
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    pub aggregations: Vec<Aggregation>,
+    /// Return the query plan and execution statistics with the results
+    #[serde(default)]
+    pub explain: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryRequest {
+    /// Query text, e.g. `SELECT name FROM orders WHERE total > 100 LIMIT 20`
+    pub query: String,
+    /// Same as prefixing the query with `EXPLAIN`
+    #[serde(default)]
+    pub explain: bool,
+}
+
+/// An operation of `POST /transaction`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransactionOperationRequest {
+    Persist {
+        table: String,
+        data: serde_json::Value,
+    },
+    Update {
+        table: String,
+        doc_id: String,
+        data: serde_json::Value,
+        /// Fails the transaction if the document is not at this revision
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionRequest {
+    /// Operations applied all-or-nothing, in order
+    pub operations: Vec<TransactionOperationRequest>,
 }
@@ -0,0 +1,226 @@
+//! Databases and tables kept open by the server between requests.
+//!
+//! Opening a database decrypts `config.cdb` and opening a table reads `metadata.cdb`, so the
+//! routes get both from a registry instead of reopening them on every request. Each table sits
+//! behind a `RwLock`: reads share it, while writes to the same table are serialized. Both still
+//! see changes made by other processes, because a `Table` reloads its metadata when
+//! `metadata.cdb` changes on disk.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::api::models::TableData;
+use crate::chaindb::{ChainDB, ConfigStamp};
+use crate::errors::ChainDBError;
+use crate::retention::{sweep_interval, SweepStats};
+use crate::table::Table;
+
+/// A table shared by all requests
+pub type SharedTable = Arc<RwLock<Table<TableData>>>;
+
+/// An open database and the tables already opened in it
+#[derive(Debug)]
+pub struct SharedDatabase {
+    db: ChainDB,
+    /// `config.cdb` as it was before the database was opened
+    config_stamp: Option<ConfigStamp>,
+    tables: RwLock<HashMap<String, SharedTable>>,
+}
+
+impl SharedDatabase {
+    fn new(db: ChainDB, config_stamp: Option<ConfigStamp>) -> Self {
+        Self {
+            db,
+            config_stamp,
+            tables: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn db(&self) -> &ChainDB {
+        &self.db
+    }
+
+    /// Returns the open table, opening (and creating, if needed) it on first use
+    pub fn table(&self, name: &str) -> Result<SharedTable, ChainDBError> {
+        if let Some(table) = self.tables.read().unwrap().get(name) {
+            return Ok(table.clone());
+        }
+
+        let mut tables = self.tables.write().unwrap();
+        // Outra requisição pode ter aberto a tabela enquanto esperávamos o lock de escrita
+        if let Some(table) = tables.get(name) {
+            return Ok(table.clone());
+        }
+
+        let table = Arc::new(RwLock::new(self.db.create_table::<TableData>(name)?));
+        tables.insert(name.to_string(), table.clone());
+        Ok(table)
+    }
+
+    /// Like `table`, but fails with `ChainDBError::TableNotFound` instead of creating the table
+    pub fn existing_table(&self, name: &str) -> Result<SharedTable, ChainDBError> {
+        if !self.tables.read().unwrap().contains_key(name)
+            && !self.db.list_tables()?.iter().any(|table| table == name)
+        {
+            return Err(ChainDBError::TableNotFound(name.to_string()));
+        }
+        self.table(name)
+    }
+
+    /// Names of the tables currently open, in alphabetical order
+    pub fn open_tables(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Reloads the metadata of the given tables, if open. Used after writes that bypass the
+    /// shared `Table`s, such as transactions
+    pub fn refresh_tables<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), ChainDBError> {
+        let names: BTreeSet<&str> = names.into_iter().collect();
+        let tables = self.tables.read().unwrap();
+        for name in names {
+            if let Some(table) = tables.get(name) {
+                table.write().unwrap().refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sweeps the open tables that have a retention policy, returning the totals. A failure in
+    /// one table is logged and doesn't stop the others
+    pub fn sweep(&self) -> SweepStats {
+        let tables: Vec<(String, SharedTable)> = self
+            .tables
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, table)| (name.clone(), table.clone()))
+            .collect();
+        let mut stats = SweepStats::default();
+        for (name, table) in tables {
+            let mut table = table.write().unwrap();
+            if table.retention().is_some() {
+                match table.sweep() {
+                    Ok(table_stats) => {
+                        stats.expired += table_stats.expired;
+                        stats.files_dropped += table_stats.files_dropped;
+                    }
+                    Err(e) => println!("Falha na limpeza da tabela {}: {}", name, e),
+                }
+            }
+        }
+        stats
+    }
+}
+
+/// Registry of the databases opened by the server
+#[derive(Debug, Default)]
+pub struct Registry {
+    databases: RwLock<HashMap<String, Arc<SharedDatabase>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the open database, connecting to it on first use. Credentials are checked on
+    /// every call, against the configuration decrypted when the database was opened. When
+    /// `config.cdb` changed since then (the password was changed or a backup restored, maybe
+    /// by another process), the database is opened again
+    pub fn open(
+        &self,
+        name: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Arc<SharedDatabase>, ChainDBError> {
+        let config_stamp = ChainDB::config_stamp(name);
+        let cached = self.databases.read().unwrap().get(name).cloned();
+        if let Some(database) = cached {
+            if database.config_stamp == config_stamp {
+                return if database.db.verify_credentials(user, password) {
+                    Ok(database)
+                } else {
+                    Err(ChainDBError::InvalidCredentials(
+                        "Invalid credentials".to_string(),
+                    ))
+                };
+            }
+
+            // A instância aberta usa a senha e a chave antigas
+            let mut databases = self.databases.write().unwrap();
+            if databases
+                .get(name)
+                .is_some_and(|current| Arc::ptr_eq(current, &database))
+            {
+                databases.remove(name);
+            }
+        }
+
+        let connection = ChainDB::connect(name, user, password)?;
+        let mut databases = self.databases.write().unwrap();
+        // Se outra requisição abriu o banco primeiro, a instância dela é mantida
+        let database = databases
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(SharedDatabase::new(connection.db, config_stamp)));
+        Ok(database.clone())
+    }
+
+    /// Forgets an open database, e.g. after its password changed. Requests still holding it
+    /// finish normally; the next `open` connects again
+    pub fn close(&self, name: &str) -> bool {
+        self.databases.write().unwrap().remove(name).is_some()
+    }
+
+    /// Names of the databases currently open, in alphabetical order
+    pub fn open_databases(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.databases.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Sweeps every open database. A failure in one database doesn't stop the others
+    pub fn sweep(&self) {
+        let databases: Vec<(String, Arc<SharedDatabase>)> = self
+            .databases
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, database)| (name.clone(), database.clone()))
+            .collect();
+
+        for (name, database) in databases {
+            let stats = database.sweep();
+            if stats.expired > 0 || stats.files_dropped > 0 {
+                println!(
+                    "Limpeza do banco {}: {} documentos expirados, {} arquivos removidos",
+                    name, stats.expired, stats.files_dropped
+                );
+            }
+        }
+    }
+}
+
+// Registro único compartilhado por todas as rotas
+lazy_static::lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+}
+
+/// Gets the global registry used by the API routes
+pub fn get_registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Starts the background thread that periodically sweeps expired documents from the open
+/// tables, every `CHAIN_DB_SWEEP_INTERVAL_SECS` seconds (60 by default)
+pub fn spawn_sweeper() -> std::thread::JoinHandle<()> {
+    let interval = sweep_interval();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        get_registry().sweep();
+    })
+}
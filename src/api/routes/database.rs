@@ -1,7 +1,11 @@
+use crate::api::auth::DatabaseAuth;
 use crate::api::models::{ApiResponse, ConnectDatabaseRequest, CreateDatabaseRequest};
+use crate::api::registry::get_registry;
+use crate::backup::{self, BackupInfo, BackupManifest};
 use crate::chaindb::ChainDB;
-use rocket::post;
+use crate::check::CheckReport;
 use rocket::serde::json::Json;
+use rocket::{get, post};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -12,6 +16,12 @@ pub struct ChangePasswordRequest {
     pub new_password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    /// File name of a backup listed by `GET /database/backups`
+    pub backup: String,
+}
+
 #[post("/database/create", data = "<request>")]
 pub fn create_database(request: Json<CreateDatabaseRequest>) -> Json<ApiResponse<String>> {
     match ChainDB::create_database(&request.name, &request.user, &request.password) {
@@ -30,7 +40,7 @@ pub fn create_database(request: Json<CreateDatabaseRequest>) -> Json<ApiResponse
 pub fn connect_database(request: Json<ConnectDatabaseRequest>) -> Json<ApiResponse<String>> {
     match ChainDB::connect(&request.name, &request.user, &request.password) {
         Ok(connection) => Json(ApiResponse::success(connection.auth_token)),
-        Err(e) => Json(ApiResponse::error(format!(
+        Err(_) => Json(ApiResponse::error(format!(
             "Failed to connect to database: {}",
             "Table not found or wrong user/password" // e // "Table not found or wrong user/password"
         ))),
@@ -43,18 +53,162 @@ pub fn change_password(request: Json<ChangePasswordRequest>) -> Json<ApiResponse
         Ok(connection) => {
             let mut db = connection.db;
             match db.change_password(&request.new_password) {
-                Ok(_) => Json(ApiResponse::success(
-                    "Password changed successfully".to_string(),
-                )),
+                Ok(_) => {
+                    // A instância aberta ainda usa a chave antiga
+                    get_registry().close(&request.name);
+                    Json(ApiResponse::success(
+                        "Password changed successfully".to_string(),
+                    ))
+                }
                 Err(e) => Json(ApiResponse::error(format!(
                     "Failed to change password: {}",
                     e
                 ))),
             }
         }
-        Err(e) => Json(ApiResponse::error(format!(
+        Err(_) => Json(ApiResponse::error(format!(
             "Failed to connect to database: {}",
             "Table not found or wrong user/password" // e // "Table not found"
         ))),
     }
 }
+
+/// Backs the database up into its backups directory, pruning the oldest backups. With
+/// `?incremental=true`, only what changed since the most recent backup is stored
+#[post("/database/backup?<incremental>")]
+pub fn create_backup(
+    auth: DatabaseAuth,
+    incremental: Option<bool>,
+) -> Json<ApiResponse<BackupInfo>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            let result = if incremental.unwrap_or(false) {
+                database.db().create_incremental_backup()
+            } else {
+                database.db().create_backup()
+            };
+            match result {
+                Ok(backup) => {
+                    println!("Backup de {} criado: {}", auth.db_name, backup.file_name);
+                    Json(ApiResponse::success(backup))
+                }
+                Err(e) => Json(ApiResponse::error(format!(
+                    "Failed to create backup: {}",
+                    e
+                ))),
+            }
+        }
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Lists the backups of the database, oldest first
+#[get("/database/backups")]
+pub fn list_backups(auth: DatabaseAuth) -> Json<ApiResponse<Vec<BackupInfo>>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.db().list_backups() {
+            Ok(backups) => Json(ApiResponse::success(backups)),
+            Err(e) => Json(ApiResponse::error(format!("Failed to list backups: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Replaces the database with one of its backups. An incremental backup is restored together
+/// with the backups it is based on. The credentials must be valid both for the current database
+/// and for the backup
+#[post("/database/restore", data = "<request>")]
+pub fn restore_backup(
+    auth: DatabaseAuth,
+    request: Json<RestoreRequest>,
+) -> Json<ApiResponse<BackupManifest>> {
+    if get_registry()
+        .open(&auth.db_name, &auth.username, &auth.password)
+        .is_err()
+    {
+        return Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        )));
+    }
+
+    // Só aceita nomes de arquivos do diretório de backups do banco
+    if request.backup.contains(['/', '\\']) || request.backup.starts_with('.') {
+        return Json(ApiResponse::error(format!(
+            "Invalid backup name: {}",
+            request.backup
+        )));
+    }
+    let archive = backup::backups_dir(&auth.db_name).join(&request.backup);
+    if !archive.is_file() {
+        return Json(ApiResponse::error(format!(
+            "Backup not found: {}",
+            request.backup
+        )));
+    }
+
+    // As tabelas abertas apontam para os arquivos que serão substituídos
+    get_registry().close(&auth.db_name);
+    match ChainDB::restore(&archive, &auth.db_name, &auth.username, &auth.password) {
+        Ok(manifest) => {
+            println!("Banco {} restaurado de {}", auth.db_name, request.backup);
+            Json(ApiResponse::success(manifest))
+        }
+        Err(e) => Json(ApiResponse::error(format!(
+            "Failed to restore backup: {}",
+            e
+        ))),
+    }
+}
+
+/// Checks the integrity of every table of the database, without changing anything
+#[get("/database/check")]
+pub fn check_database(auth: DatabaseAuth) -> Json<ApiResponse<CheckReport>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.db().check() {
+            Ok(report) => Json(ApiResponse::success(report)),
+            Err(e) => Json(ApiResponse::error(format!(
+                "Failed to check database: {}",
+                e
+            ))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Checks every table, quarantining unreadable lines and rewriting inconsistent metadata
+#[post("/database/repair")]
+pub fn repair_database(auth: DatabaseAuth) -> Json<ApiResponse<CheckReport>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.db().repair() {
+            Ok(report) => {
+                // As tabelas abertas ainda têm os metadados antigos
+                let tables = report.tables.iter().map(|table| table.table.as_str());
+                if let Err(e) = database.refresh_tables(tables) {
+                    return Json(ApiResponse::error(format!(
+                        "Failed to reload repaired tables: {}",
+                        e
+                    )));
+                }
+                Json(ApiResponse::success(report))
+            }
+            Err(e) => Json(ApiResponse::error(format!(
+                "Failed to repair database: {}",
+                e
+            ))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
@@ -156,9 +156,5 @@ pub fn event_types() -> Json<ApiResponse<Vec<EventType>>> {
         EventType::TablePersist,
     ];
     
-    Json(ApiResponse {
-        success: true,
-        message: None,
-        data: Some(event_types),
-    })
+    Json(ApiResponse::success(event_types))
 } 
\ No newline at end of file
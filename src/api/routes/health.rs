@@ -0,0 +1,62 @@
+use crate::api::auth::DatabaseAuth;
+use crate::api::health::{
+    check_readiness, min_free_space, started_at, uptime, Readiness, ServerInfo,
+};
+use crate::api::models::ApiResponse;
+use crate::api::registry::get_registry;
+use crate::chaindb::DATA_DIR;
+use crate::table::STORAGE_FORMAT_VERSION;
+use rocket::get;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use std::path::Path;
+
+/// Liveness probe: answers as long as the server is running. No authentication
+#[get("/health")]
+pub fn health() -> Json<ApiResponse<String>> {
+    Json(ApiResponse::success("ok".to_string()))
+}
+
+/// Readiness probe: `503 Service Unavailable` when the data directory can't be read or
+/// written, or its disk is almost full. No authentication
+#[get("/ready")]
+pub fn ready() -> (Status, Json<ApiResponse<Readiness>>) {
+    let readiness = check_readiness(Path::new(DATA_DIR), min_free_space());
+    if readiness.ready {
+        return (Status::Ok, Json(ApiResponse::success(readiness)));
+    }
+
+    let failed: Vec<&str> = readiness
+        .checks
+        .iter()
+        .filter(|check| !check.ok)
+        .map(|check| check.name.as_str())
+        .collect();
+    println!("Servidor não está pronto: {}", failed.join(", "));
+    let mut body = ApiResponse::error(format!("Not ready: {}", failed.join(", ")));
+    body.data = Some(readiness);
+    (Status::ServiceUnavailable, Json(body))
+}
+
+#[get("/info")]
+pub fn server_info(auth: DatabaseAuth) -> Json<ApiResponse<ServerInfo>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            let data_dir = std::fs::canonicalize(DATA_DIR)
+                .unwrap_or_else(|_| Path::new(DATA_DIR).to_path_buf());
+            Json(ApiResponse::success(ServerInfo {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                started_at: started_at().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                uptime_secs: uptime().as_secs(),
+                data_dir: data_dir.display().to_string(),
+                database: auth.db_name.clone(),
+                open_tables: database.open_tables(),
+                storage_format_version: STORAGE_FORMAT_VERSION,
+            }))
+        }
+        Err(e) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            e
+        ))),
+    }
+}
@@ -1,4 +1,8 @@
 pub mod cors;
 pub mod database;
 pub mod events;
+pub mod health;
+pub mod query;
 pub mod table;
+pub mod transaction;
+pub mod transfer;
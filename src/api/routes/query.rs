@@ -0,0 +1,37 @@
+use crate::api::auth::DatabaseAuth;
+use crate::api::models::{ApiResponse, QueryRequest};
+use crate::api::registry::get_registry;
+use crate::sql;
+use rocket::post;
+use rocket::serde::json::Json;
+
+/// Runs a text query, e.g. `SELECT name, total FROM orders WHERE total > 100 LIMIT 20`
+#[post("/query", data = "<request>")]
+pub fn run_query(
+    auth: DatabaseAuth,
+    request: Json<QueryRequest>,
+) -> Json<ApiResponse<Vec<serde_json::Value>>> {
+    println!("Recebida consulta: {}", request.query);
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            let result = sql::parse(&request.query).and_then(|mut query| {
+                query.explain |= request.explain;
+                let table = database.existing_table(&query.table)?;
+                let table = table.read().unwrap();
+                query.execute(&*table)
+            });
+            match result {
+                Ok(result) => Json(
+                    ApiResponse::paginated(result.rows, Some(result.total), None)
+                        .with_explain(result.explain),
+                ),
+                Err(e) => Json(ApiResponse::error(format!("Failed to run query: {}", e))),
+            }
+        }
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
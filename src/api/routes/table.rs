@@ -1,11 +1,22 @@
+use crate::aggregate::AggregateGroup;
 use crate::api::auth::DatabaseAuth;
+use crate::api::etag::{IfMatch, Versioned};
 use crate::api::models::{
-    ApiResponse, FindWhereAdvancedRequest, FindWhereRequest, PersistTableRequest, TableData,
-    UpdateTableRequest,
+    AggregateRequest, ApiResponse, BatchItemResult, FindWhereAdvancedCriteria,
+    FindWhereAdvancedRequest, FindWhereRequest, HistoryParams, PersistBatchRequest,
+    PersistTableRequest, TableData, UpdateBatchRequest, UpdateTableRequest, UpsertRequest,
 };
-use crate::chaindb::ChainDB;
+use crate::api::registry::get_registry;
+use crate::api::stream::{AcceptNdjson, RecordsResponse};
+use crate::errors::ChainDBError;
+use crate::patch::Patch;
+use crate::query::{QueryOptions, QueryPage, SortKey};
+use crate::retention::RetentionPolicy;
+use crate::table::{ComparisonOperator, Table, UpsertResult};
+use rocket::data::{Data, Limits};
+use rocket::http::{ContentType, Status};
 use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{get, patch, post, put};
 use std::collections::HashMap;
 
 #[get("/table/<table_name>")]
@@ -13,22 +24,19 @@ pub fn get_table_data(
     auth: DatabaseAuth,
     table_name: &str,
 ) -> Json<ApiResponse<serde_json::Value>> {
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
-                Ok(table) => match table.get_table() {
-                    Ok(data) => {
-                        println!("Data before to_json: {:?}", data);
-                        let json_data = data.to_json();
-                        println!("Data after to_json: {:?}", json_data);
-                        Json(ApiResponse::success(json_data))
-                    }
-                    Err(e) => Json(ApiResponse::error(format!("Failed to get data: {}", e))),
-                },
-                Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
-            }
-        }
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => match table.read().unwrap().get_table() {
+                Ok(data) => {
+                    println!("Data before to_json: {:?}", data);
+                    let json_data = data.to_json();
+                    println!("Data after to_json: {:?}", json_data);
+                    Json(ApiResponse::success(json_data))
+                }
+                Err(e) => Json(ApiResponse::error(format!("Failed to get data: {}", e))),
+            },
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
         Err(e) => Json(ApiResponse::error(format!(
             "Failed to connect to database: {}",
             e
@@ -39,45 +47,48 @@ pub fn get_table_data(
 #[post("/table/<table_name>/update", data = "<request>")]
 pub fn update_table(
     auth: DatabaseAuth,
+    if_match: IfMatch,
     table_name: &str,
     request: Json<UpdateTableRequest>,
-) -> Json<ApiResponse<serde_json::Value>> {
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
-                Ok(mut table) => {
+) -> Versioned<serde_json::Value> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            match database.table(table_name) {
+                Ok(table) => {
+                    let mut table = table.write().unwrap();
                     let data = TableData::from_json(request.data.clone());
-                    match table.update(&data, &request.doc_id) {
-                        Ok(_) => {
-                            // Buscar o registro atualizado pelo doc_id
-                            let criteria = HashMap::from([(
-                                "doc_id".to_string(),
-                                serde_json::Value::String(request.doc_id.clone()),
-                            )]);
-                            match table.find_where(criteria, Some(1), true) {
-                                Ok(records) if !records.is_empty() => {
-                                    Json(ApiResponse::success(records[0].to_json()))
-                                }
-                                Ok(_) => Json(ApiResponse::error(
-                                    "Record updated but not found when retrieving".to_string(),
-                                )),
-                                Err(e) => Json(ApiResponse::error(format!(
-                                    "Record updated but failed to retrieve: {}",
-                                    e
-                                ))),
+                    match table.update(&data, &request.doc_id, if_match.0) {
+                        // Buscar o registro atualizado pelo doc_id
+                        Ok(_) => match table.get_by_doc_id(&request.doc_id) {
+                            Ok(Some((record, revision))) => {
+                                Versioned::ok(ApiResponse::success(record.to_json()), revision)
                             }
+                            Ok(None) => Versioned::error(
+                                Status::Ok,
+                                "Record updated but not found when retrieving",
+                            ),
+                            Err(e) => Versioned::error(
+                                Status::Ok,
+                                format!("Record updated but failed to retrieve: {}", e),
+                            ),
+                        },
+                        Err(e @ ChainDBError::RevisionConflict { .. }) => Versioned::error(
+                            Status::PreconditionFailed,
+                            format!("Failed to update data: {}", e),
+                        ),
+                        Err(e @ ChainDBError::RecordNotFound(_)) => Versioned::error(
+                            Status::NotFound,
+                            format!("Failed to update data: {}", e),
+                        ),
+                        Err(e) => {
+                            Versioned::error(Status::Ok, format!("Failed to update data: {}", e))
                         }
-                        Err(e) => Json(ApiResponse::error(format!("Failed to update data: {}", e))),
                     }
                 }
-                Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+                Err(e) => Versioned::error(Status::Ok, format!("Failed to create table: {}", e)),
             }
         }
-        Err(e) => Json(ApiResponse::error(format!(
-            "Failed to connect to database: {}",
-            e
-        ))),
+        Err(e) => Versioned::error(Status::Ok, format!("Failed to connect to database: {}", e)),
     }
 }
 
@@ -87,28 +98,24 @@ pub fn persist_table(
     table_name: &str,
     request: Json<PersistTableRequest>,
 ) -> Json<ApiResponse<serde_json::Value>> {
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
-                Ok(mut table) => {
-                    let data = TableData::from_json(request.data.clone());
-                    match table.persist(&data) {
-                        Ok(_) => match table.get_table() {
-                            Ok(latest) => Json(ApiResponse::success(latest.to_json())),
-                            Err(e) => Json(ApiResponse::error(format!(
-                                "Failed to get latest data: {}",
-                                e
-                            ))),
-                        },
-                        Err(e) => {
-                            Json(ApiResponse::error(format!("Failed to persist data: {}", e)))
-                        }
-                    }
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => {
+                let mut table = table.write().unwrap();
+                let data = TableData::from_json(request.data.clone());
+                match table.persist(&data) {
+                    Ok(_) => match table.get_table() {
+                        Ok(latest) => Json(ApiResponse::success(latest.to_json())),
+                        Err(e) => Json(ApiResponse::error(format!(
+                            "Failed to get latest data: {}",
+                            e
+                        ))),
+                    },
+                    Err(e) => Json(ApiResponse::error(format!("Failed to persist data: {}", e))),
                 }
-                Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
             }
-        }
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
         Err(e) => Json(ApiResponse::error(format!(
             "Failed to connect to database: {}",
             e
@@ -116,48 +123,183 @@ pub fn persist_table(
     }
 }
 
-#[get("/table/<table_name>/history?<limit>")]
+/// Replaces the document matching `criteria`, or persists a new one if none matches
+#[post("/table/<table_name>/upsert", data = "<request>")]
+pub fn upsert(
+    auth: DatabaseAuth,
+    table_name: &str,
+    request: Json<UpsertRequest>,
+) -> Json<ApiResponse<UpsertResult>> {
+    println!("Recebido upsert para a tabela: {}", table_name);
+    println!("Critérios: {:?}", request.criteria);
+
+    let data = TableData::from_json(request.data.clone());
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => match table
+                .write()
+                .unwrap()
+                .upsert(request.criteria.clone(), &data)
+            {
+                Ok(result) => Json(ApiResponse::success(result)),
+                Err(e) => Json(ApiResponse::error(format!("Failed to upsert data: {}", e))),
+            },
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Persists several records with a single write per data file
+#[post("/table/<table_name>/persist-batch", data = "<request>")]
+pub fn persist_batch(
+    auth: DatabaseAuth,
+    table_name: &str,
+    request: Json<PersistBatchRequest>,
+) -> Json<ApiResponse<Vec<BatchItemResult>>> {
+    println!(
+        "Recebido lote de {} registros para a tabela: {}",
+        request.records.len(),
+        table_name
+    );
+    let records: Vec<TableData> = request
+        .records
+        .iter()
+        .map(|record| TableData::from_json(record.data.clone()))
+        .collect();
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => match table.write().unwrap().persist_many(&records) {
+                Ok(results) => Json(ApiResponse::success(
+                    results
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, result)| match result {
+                            Ok(doc_id) => BatchItemResult::ok(index, doc_id, 1),
+                            Err(e) => BatchItemResult::error(index, None, e),
+                        })
+                        .collect(),
+                )),
+                Err(e) => Json(ApiResponse::error(format!(
+                    "Failed to persist records: {}",
+                    e
+                ))),
+            },
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Updates several records, rewriting each affected data file once
+#[post("/table/<table_name>/update-batch", data = "<request>")]
+pub fn update_batch(
+    auth: DatabaseAuth,
+    table_name: &str,
+    request: Json<UpdateBatchRequest>,
+) -> Json<ApiResponse<Vec<BatchItemResult>>> {
+    println!(
+        "Recebido lote de {} atualizações para a tabela: {}",
+        request.records.len(),
+        table_name
+    );
+    let updates: Vec<(String, TableData)> = request
+        .records
+        .iter()
+        .map(|record| {
+            (
+                record.doc_id.clone(),
+                TableData::from_json(record.data.clone()),
+            )
+        })
+        .collect();
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => match table.write().unwrap().update_many(&updates) {
+                Ok(results) => Json(ApiResponse::success(
+                    results
+                        .into_iter()
+                        .zip(updates)
+                        .enumerate()
+                        .map(|(index, (result, (doc_id, _)))| match result {
+                            Ok(revision) => BatchItemResult::ok(index, doc_id, revision),
+                            Err(e) => BatchItemResult::error(index, Some(doc_id), e),
+                        })
+                        .collect(),
+                )),
+                Err(e) => Json(ApiResponse::error(format!(
+                    "Failed to update records: {}",
+                    e
+                ))),
+            },
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+#[get("/table/<table_name>/history?<params..>")]
 pub fn get_history(
     auth: DatabaseAuth,
+    ndjson: AcceptNdjson,
     table_name: &str,
-    limit: Option<usize>,
-) -> Json<ApiResponse<Vec<serde_json::Value>>> {
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let limit = limit.unwrap_or(50);
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
-                Ok(table) => match table.get_history(limit) {
-                    Ok(records) => {
-                        println!("Records before to_json: {:?}", records);
-                        let history: Vec<serde_json::Value> = records
-                            .into_iter()
-                            .map(|record| {
-                                let json = record.to_json();
-                                println!("Record after to_json: {:?}", json);
-                                json
-                            })
-                            .collect();
-                        Json(ApiResponse::success(history))
-                    }
-                    Err(e) => Json(ApiResponse::error(format!("Failed to get history: {}", e))),
-                },
-                Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+    params: HistoryParams,
+) -> RecordsResponse {
+    let sort = match params.sort.as_deref().map(SortKey::parse_list).transpose() {
+        Ok(sort) => sort.unwrap_or_default(),
+        Err(e) => return RecordsResponse::error(format!("Failed to get history: {}", e)),
+    };
+    let options = QueryOptions {
+        sort,
+        fields: params.fields.as_deref().map(parse_fields),
+        // Em NDJSON o histórico completo é enviado, a menos que um limite seja pedido
+        limit: if ndjson.0 {
+            params.limit
+        } else {
+            Some(params.limit.unwrap_or(50))
+        },
+        offset: params.offset,
+        cursor: params.cursor,
+        reverse: true,
+        count_total: params.count_total.unwrap_or(false),
+        explain: params.explain.unwrap_or(false),
+    };
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => {
+                match query_response(&table.read().unwrap(), HashMap::new(), &options, ndjson) {
+                    Ok(response) => response,
+                    Err(e) => RecordsResponse::error(format!("Failed to get history: {}", e)),
+                }
             }
-        }
-        Err(e) => Json(ApiResponse::error(format!(
+            Err(e) => RecordsResponse::error(format!("Failed to create table: {}", e)),
+        },
+        Err(_) => RecordsResponse::error(format!(
             "Failed to connect to database: {}",
             "Table not found or wrong Authorization token" // e
-        ))),
+        )),
     }
 }
 
 #[post("/table/<table_name>/find", data = "<request>")]
 pub fn find_where(
     auth: DatabaseAuth,
+    ndjson: AcceptNdjson,
     table_name: &str,
     request: Json<FindWhereRequest>,
-) -> Json<ApiResponse<Vec<serde_json::Value>>> {
+) -> RecordsResponse {
     println!("Recebida requisição findWhere para tabela: {}", table_name);
     println!("Critérios: {:?}", request.criteria);
     println!(
@@ -165,37 +307,47 @@ pub fn find_where(
         request.limit, request.reverse
     );
 
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            match database.table(table_name) {
                 Ok(table) => {
-                    let reverse = request.reverse.unwrap_or(true);
-                    match table.find_where(request.criteria.clone(), request.limit, reverse) {
-                        Ok(records) => {
-                            println!("Encontrados {} registros", records.len());
-                            let results: Vec<serde_json::Value> =
-                                records.into_iter().map(|record| record.to_json()).collect();
-                            Json(ApiResponse::success(results))
-                        }
+                    let table = table.read().unwrap();
+                    // A busca simples é uma busca avançada em que todos os critérios usam Eq
+                    let criteria = request
+                        .criteria
+                        .iter()
+                        .map(|(field, value)| {
+                            (field.clone(), (ComparisonOperator::Eq, value.clone()))
+                        })
+                        .collect();
+
+                    let options = QueryOptions {
+                        sort: request.sort.clone(),
+                        fields: request.fields.clone(),
+                        limit: request.limit,
+                        offset: request.offset,
+                        cursor: request.cursor.clone(),
+                        reverse: request.reverse.unwrap_or(true),
+                        count_total: request.count_total,
+                        explain: request.explain,
+                    };
+                    match query_response(&table, criteria, &options, ndjson) {
+                        Ok(response) => response,
                         Err(e) => {
                             println!("Erro ao buscar registros: {}", e);
-                            Json(ApiResponse::error(format!("Failed to find records: {}", e)))
+                            RecordsResponse::error(format!("Failed to find records: {}", e))
                         }
                     }
                 }
                 Err(e) => {
                     println!("Erro ao criar tabela: {}", e);
-                    Json(ApiResponse::error(format!("Failed to create table: {}", e)))
+                    RecordsResponse::error(format!("Failed to create table: {}", e))
                 }
             }
         }
         Err(e) => {
             println!("Erro ao conectar ao banco de dados: {}", e);
-            Json(ApiResponse::error(format!(
-                "Failed to connect to database: {}",
-                e
-            )))
+            RecordsResponse::error(format!("Failed to connect to database: {}", e))
         }
     }
 }
@@ -203,9 +355,10 @@ pub fn find_where(
 #[post("/table/<table_name>/find-advanced", data = "<request>")]
 pub fn find_where_advanced(
     auth: DatabaseAuth,
+    ndjson: AcceptNdjson,
     table_name: &str,
     request: Json<FindWhereAdvancedRequest>,
-) -> Json<ApiResponse<Vec<serde_json::Value>>> {
+) -> RecordsResponse {
     println!(
         "Recebida requisição findWhereAdvanced para tabela: {}",
         table_name
@@ -216,63 +369,137 @@ pub fn find_where_advanced(
         request.limit, request.reverse
     );
 
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            match database.table(table_name) {
                 Ok(table) => {
+                    let table = table.read().unwrap();
                     // Converter o formato da requisição para o formato esperado pela função findWhereAdvanced
-                    let mut criteria = HashMap::new();
-                    for criterion in &request.criteria {
-                        criteria.insert(
-                            criterion.field.clone(),
-                            (criterion.operator.clone(), criterion.value.clone()),
-                        );
-                    }
+                    let criteria = advanced_criteria(&request.criteria);
                     println!("Critérios convertidos: {:?}", criteria);
 
-                    let reverse = request.reverse.unwrap_or(true);
-                    match table.find_where_advanced(criteria, request.limit, reverse) {
-                        Ok(records) => {
-                            println!("Encontrados {} registros", records.len());
-                            let results: Vec<serde_json::Value> =
-                                records.into_iter().map(|record| record.to_json()).collect();
-                            Json(ApiResponse::success(results))
-                        }
+                    let options = QueryOptions {
+                        sort: request.sort.clone(),
+                        fields: request.fields.clone(),
+                        limit: request.limit,
+                        offset: request.offset,
+                        cursor: request.cursor.clone(),
+                        reverse: request.reverse.unwrap_or(true),
+                        count_total: request.count_total,
+                        explain: request.explain,
+                    };
+                    match query_response(&table, criteria, &options, ndjson) {
+                        Ok(response) => response,
                         Err(e) => {
                             println!("Erro ao buscar registros: {}", e);
-                            Json(ApiResponse::error(format!("Failed to find records: {}", e)))
+                            RecordsResponse::error(format!("Failed to find records: {}", e))
                         }
                     }
                 }
                 Err(e) => {
                     println!("Erro ao criar tabela: {}", e);
-                    Json(ApiResponse::error(format!("Failed to create table: {}", e)))
+                    RecordsResponse::error(format!("Failed to create table: {}", e))
                 }
             }
         }
         Err(e) => {
             println!("Erro ao conectar ao banco de dados: {}", e);
-            Json(ApiResponse::error(format!(
-                "Failed to connect to database: {}",
-                e
-            )))
+            RecordsResponse::error(format!("Failed to connect to database: {}", e))
         }
     }
 }
 
+#[post("/table/<table_name>/aggregate", data = "<request>")]
+pub fn aggregate(
+    auth: DatabaseAuth,
+    table_name: &str,
+    request: Json<AggregateRequest>,
+) -> Json<ApiResponse<Vec<AggregateGroup>>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => {
+                let table = table.read().unwrap();
+                let criteria = advanced_criteria(&request.criteria);
+                match table.aggregate_explain(criteria, &request.group_by, &request.aggregations) {
+                    Ok((groups, explain)) => Json(
+                        ApiResponse::success(groups)
+                            .with_explain(request.explain.then_some(explain)),
+                    ),
+                    Err(e) => Json(ApiResponse::error(format!(
+                        "Failed to aggregate records: {}",
+                        e
+                    ))),
+                }
+            }
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Returns the retention policy of a table (`null` if its documents never expire)
+#[get("/table/<table_name>/retention")]
+pub fn get_retention(
+    auth: DatabaseAuth,
+    table_name: &str,
+) -> Json<ApiResponse<Option<RetentionPolicy>>> {
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.existing_table(table_name) {
+            Ok(table) => Json(ApiResponse::success(table.read().unwrap().retention())),
+            Err(e) => Json(ApiResponse::error(format!("Failed to get table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
+/// Sets the retention policy of a table. An empty policy (`{}`) removes it
+#[put("/table/<table_name>/retention", data = "<request>")]
+pub fn set_retention(
+    auth: DatabaseAuth,
+    table_name: &str,
+    request: Json<RetentionPolicy>,
+) -> Json<ApiResponse<Option<RetentionPolicy>>> {
+    println!(
+        "Nova política de retenção para a tabela {}: {:?}",
+        table_name, request
+    );
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => {
+                let mut table = table.write().unwrap();
+                match table.set_retention(Some(request.into_inner())) {
+                    Ok(()) => Json(ApiResponse::success(table.retention())),
+                    Err(e) => Json(ApiResponse::error(format!(
+                        "Failed to set retention policy: {}",
+                        e
+                    ))),
+                }
+            }
+            Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
+
 /// Lists all tables in the connected database
 #[get("/tables")]
 pub fn list_tables(auth: DatabaseAuth) -> Json<ApiResponse<Vec<String>>> {
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.list_tables() {
-                Ok(tables) => Json(ApiResponse::success(tables)),
-                Err(e) => Json(ApiResponse::error(format!("Failed to list tables: {}", e))),
-            }
-        }
-        Err(e) => Json(ApiResponse::error(format!(
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.db().list_tables() {
+            Ok(tables) => Json(ApiResponse::success(tables)),
+            Err(e) => Json(ApiResponse::error(format!("Failed to list tables: {}", e))),
+        },
+        Err(_) => Json(ApiResponse::error(format!(
             "Failed to connect to database: {}",
             "Table not found or wrong Authorization token" // e
         ))),
@@ -284,48 +511,166 @@ pub fn get_document_by_id(
     auth: DatabaseAuth,
     table_name: &str,
     doc_id: &str,
-) -> Json<ApiResponse<serde_json::Value>> {
+) -> Versioned<serde_json::Value> {
     println!(
         "Recebida requisição para buscar documento com doc_id: {} na tabela: {}",
         doc_id, table_name
     );
 
-    match ChainDB::connect(&auth.db_name, &auth.username, &auth.password) {
-        Ok(connection) => {
-            let db = connection.db;
-            match db.create_table::<TableData>(&table_name) {
-                Ok(table) => {
-                    // Criar critério de busca pelo doc_id
-                    let criteria = HashMap::from([(
-                        "doc_id".to_string(),
-                        serde_json::Value::String(doc_id.to_string()),
-                    )]);
-
-                    // Buscar o documento usando a função find_where existente
-                    match table.find_where(criteria, Some(1), true) {
-                        Ok(records) if !records.is_empty() => {
-                            // Retornar o primeiro (e único) registro encontrado
-                            Json(ApiResponse::success(records[0].to_json()))
-                        }
-                        Ok(_) => {
-                            // Documento não encontrado
-                            Json(ApiResponse::error(format!(
-                                "Document with doc_id {} not found",
-                                doc_id
-                            )))
-                        }
-                        Err(e) => Json(ApiResponse::error(format!(
-                            "Failed to find document: {}",
-                            e
-                        ))),
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            match database.table(table_name) {
+                Ok(table) => match table.read().unwrap().get_by_doc_id(doc_id) {
+                    // A revisão do documento é exposta no cabeçalho ETag
+                    Ok(Some((record, revision))) => {
+                        Versioned::ok(ApiResponse::success(record.to_json()), revision)
                     }
-                }
-                Err(e) => Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+                    Ok(None) => Versioned::error(
+                        Status::NotFound,
+                        format!("Document with doc_id {} not found", doc_id),
+                    ),
+                    Err(e) => {
+                        Versioned::error(Status::Ok, format!("Failed to find document: {}", e))
+                    }
+                },
+                Err(e) => Versioned::error(Status::Ok, format!("Failed to create table: {}", e)),
             }
         }
-        Err(e) => Json(ApiResponse::error(format!(
-            "Failed to connect to database: {}",
-            "Table not found or wrong Authorization token" // e
-        ))),
+        Err(_) => Versioned::error(
+            Status::Ok,
+            format!(
+                "Failed to connect to database: {}",
+                "Table not found or wrong Authorization token" // e
+            ),
+        ),
+    }
+}
+
+/// Applies a partial update to a document. `application/merge-patch+json` bodies are RFC 7396
+/// merge patches and `application/json-patch+json` bodies are RFC 6902 JSON Patches; with
+/// other content types, arrays are taken as JSON Patches and objects as merge patches
+#[patch("/table/<table_name>/doc/<doc_id>", data = "<body>")]
+pub async fn patch_document(
+    auth: DatabaseAuth,
+    if_match: IfMatch,
+    content_type: Option<&ContentType>,
+    limits: &Limits,
+    table_name: &str,
+    doc_id: &str,
+    body: Data<'_>,
+) -> Versioned<serde_json::Value> {
+    println!(
+        "Recebido patch para o documento {} na tabela: {}",
+        doc_id, table_name
+    );
+
+    let body = match body
+        .open(limits.get("json").unwrap_or(Limits::JSON))
+        .into_string()
+        .await
+    {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        Ok(_) => return Versioned::error(Status::PayloadTooLarge, "Patch is too large"),
+        Err(e) => {
+            return Versioned::error(Status::BadRequest, format!("Failed to read patch: {}", e))
+        }
+    };
+    let patch = serde_json::from_str::<serde_json::Value>(&body)
+        .map_err(ChainDBError::from)
+        .and_then(
+            |value| match content_type.map(|ct| (ct.top().as_str(), ct.sub().as_str())) {
+                Some(("application", "json-patch+json")) => Patch::json_patch(value),
+                Some(("application", "merge-patch+json")) => Ok(Patch::merge(value)),
+                _ => Patch::detect(value),
+            },
+        );
+    let patch = match patch {
+        Ok(patch) => patch,
+        Err(e) => return Versioned::error(Status::BadRequest, format!("Invalid patch: {}", e)),
+    };
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => match table.write().unwrap().patch(doc_id, &patch, if_match.0) {
+                Ok((record, revision)) => {
+                    Versioned::ok(ApiResponse::success(record.to_json()), revision)
+                }
+                Err(e @ ChainDBError::RevisionConflict { .. }) => Versioned::error(
+                    Status::PreconditionFailed,
+                    format!("Failed to patch document: {}", e),
+                ),
+                Err(e @ ChainDBError::PatchError(_)) => Versioned::error(
+                    Status::UnprocessableEntity,
+                    format!("Failed to patch document: {}", e),
+                ),
+                Err(e @ ChainDBError::RecordNotFound(_)) => {
+                    Versioned::error(Status::NotFound, format!("Failed to patch document: {}", e))
+                }
+                Err(e) => Versioned::error(Status::Ok, format!("Failed to patch document: {}", e)),
+            },
+            Err(e) => Versioned::error(Status::Ok, format!("Failed to create table: {}", e)),
+        },
+        Err(_) => Versioned::error(
+            Status::Ok,
+            format!(
+                "Failed to connect to database: {}",
+                "Table not found or wrong Authorization token" // e
+            ),
+        ),
+    }
+}
+
+/// Converts the advanced criteria of a request to the format expected by `Table`
+fn advanced_criteria(
+    criteria: &[FindWhereAdvancedCriteria],
+) -> HashMap<String, (ComparisonOperator, serde_json::Value)> {
+    criteria
+        .iter()
+        .map(|criterion| {
+            (
+                criterion.field.clone(),
+                (criterion.operator.clone(), criterion.value.clone()),
+            )
+        })
+        .collect()
+}
+
+/// Runs a find or history query, streaming the records as NDJSON if the client asked for it
+fn query_response(
+    table: &Table<TableData>,
+    criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+    options: &QueryOptions,
+    ndjson: AcceptNdjson,
+) -> Result<RecordsResponse, ChainDBError> {
+    if ndjson.0 {
+        return table
+            .query_iter(criteria, options)
+            .map(RecordsResponse::Ndjson);
     }
+
+    let page = table.query(criteria, options)?;
+    println!("Encontrados {} registros", page.records.len());
+    Ok(page_response(page))
+}
+
+/// Converts a page of records to the paginated API response
+fn page_response(page: QueryPage<TableData>) -> RecordsResponse {
+    let results: Vec<serde_json::Value> = page
+        .records
+        .into_iter()
+        .map(|record| record.to_json())
+        .collect();
+    RecordsResponse::Json(Json(
+        ApiResponse::paginated(results, page.total, page.next_cursor).with_explain(page.explain),
+    ))
+}
+
+/// Parses a comma separated list of fields, e.g. `name,total`
+fn parse_fields(fields: &str) -> Vec<String> {
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(String::from)
+        .collect()
 }
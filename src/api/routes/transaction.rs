@@ -0,0 +1,62 @@
+use crate::api::auth::DatabaseAuth;
+use crate::api::models::{ApiResponse, TableData, TransactionOperationRequest, TransactionRequest};
+use crate::api::registry::get_registry;
+use crate::transaction::OperationResult;
+use rocket::post;
+use rocket::serde::json::Json;
+
+/// Applies a list of persist/update operations across tables, all-or-nothing
+#[post("/transaction", data = "<request>")]
+pub fn run_transaction(
+    auth: DatabaseAuth,
+    request: Json<TransactionRequest>,
+) -> Json<ApiResponse<Vec<OperationResult>>> {
+    println!(
+        "Recebida transação com {} operações",
+        request.operations.len()
+    );
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => {
+            let mut transaction = database.db().transaction();
+
+            for operation in &request.operations {
+                let buffered = match operation {
+                    TransactionOperationRequest::Persist { table, data } => transaction
+                        .persist(table, &TableData::from_json(data.clone()))
+                        .map(|_| ()),
+                    TransactionOperationRequest::Update {
+                        table,
+                        doc_id,
+                        data,
+                        expected_revision,
+                    } => transaction.update(
+                        table,
+                        doc_id,
+                        &TableData::from_json(data.clone()),
+                        *expected_revision,
+                    ),
+                };
+
+                if let Err(e) = buffered {
+                    return Json(ApiResponse::error(format!("Transaction failed: {}", e)));
+                }
+            }
+
+            // A transação grava os arquivos diretamente; as tabelas abertas precisam recarregar
+            // seus metadados
+            let result = transaction.commit().and_then(|results| {
+                database.refresh_tables(results.iter().map(|result| result.table.as_str()))?;
+                Ok(results)
+            });
+            match result {
+                Ok(results) => Json(ApiResponse::success(results)),
+                Err(e) => Json(ApiResponse::error(format!("Transaction failed: {}", e))),
+            }
+        }
+        Err(_) => Json(ApiResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        ))),
+    }
+}
@@ -0,0 +1,172 @@
+use crate::api::auth::DatabaseAuth;
+use crate::api::models::ApiResponse;
+use crate::api::registry::get_registry;
+use crate::api::stream::{ChannelReader, ExportResponse, ExportStream, CHUNK_SIZE};
+use crate::errors::ChainDBError;
+use crate::transfer::{
+    import_records, CsvColumn, DocIdMode, ExportOptions, ImportOptions, ImportReport,
+    TransferFormat,
+};
+use futures::SinkExt;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::serde::json::Json;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::{get, post};
+
+/// Default size limit of an import body, in MiB. The `import` entry of Rocket's `limits`
+/// overrides it
+const DEFAULT_IMPORT_LIMIT: u64 = 64;
+
+/// Exports every record of a table as NDJSON (default), a JSON array or CSV, e.g.
+/// `/table/orders/export?format=csv&columns=id=doc_id,name,total`
+#[get("/table/<table_name>/export?<format>&<columns>")]
+pub fn export_table(
+    auth: DatabaseAuth,
+    table_name: &str,
+    format: Option<&str>,
+    columns: Option<&str>,
+) -> ExportResponse {
+    println!("Recebida exportação da tabela: {}", table_name);
+
+    let format = match format.map(str::parse::<TransferFormat>).transpose() {
+        Ok(format) => format.unwrap_or_default(),
+        Err(e) => return ExportResponse::error(format!("Failed to export: {}", e)),
+    };
+    let options = ExportOptions {
+        format,
+        columns: columns.map(CsvColumn::parse_list),
+    };
+
+    match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.existing_table(table_name) {
+            // Os registros são lidos enquanto a resposta é enviada, sem manter a tabela bloqueada
+            Ok(table) => ExportResponse::Stream(ExportStream {
+                file_name: format!("{}.{}", table_name, extension(format)),
+                records: Box::new(table.read().unwrap().records(false)),
+                options,
+            }),
+            Err(e) => ExportResponse::error(format!("Failed to export: {}", e)),
+        },
+        Err(_) => ExportResponse::error(format!(
+            "Failed to connect to database: {}",
+            "Table not found or wrong Authorization token" // e
+        )),
+    }
+}
+
+/// Imports records from the request body into a table, writing them while the body is read.
+/// The format and column mapping are given as in `export_table`; `doc_ids` is `preserve`
+/// (default) or `regenerate`
+#[post(
+    "/table/<table_name>/import?<format>&<columns>&<doc_ids>",
+    data = "<body>"
+)]
+pub async fn import_table(
+    auth: DatabaseAuth,
+    limits: &Limits,
+    table_name: &str,
+    format: Option<&str>,
+    columns: Option<&str>,
+    doc_ids: Option<&str>,
+    body: Data<'_>,
+) -> Json<ApiResponse<ImportReport>> {
+    println!("Recebida importação para a tabela: {}", table_name);
+
+    let options = format
+        .map(str::parse::<TransferFormat>)
+        .transpose()
+        .and_then(|format| {
+            Ok(ImportOptions {
+                format: format.unwrap_or_default(),
+                columns: columns.map(CsvColumn::parse_list),
+                doc_ids: doc_ids
+                    .map(str::parse::<DocIdMode>)
+                    .transpose()?
+                    .unwrap_or_default(),
+            })
+        });
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => return Json(ApiResponse::error(format!("Failed to import: {}", e))),
+    };
+
+    let table = match get_registry().open(&auth.db_name, &auth.username, &auth.password) {
+        Ok(database) => match database.table(table_name) {
+            Ok(table) => table,
+            Err(e) => return Json(ApiResponse::error(format!("Failed to create table: {}", e))),
+        },
+        Err(_) => {
+            return Json(ApiResponse::error(format!(
+                "Failed to connect to database: {}",
+                "Table not found or wrong Authorization token" // e
+            )));
+        }
+    };
+
+    // O corpo é lido aqui e consumido por uma tarefa bloqueante, que grava um lote por vez e só
+    // bloqueia a tabela durante a gravação de cada lote
+    let (mut sender, reader) = ChannelReader::channel();
+    let import = rocket::tokio::task::spawn_blocking(move || {
+        let mut known_doc_ids = table.read().unwrap().import_doc_ids(options.doc_ids)?;
+        import_records(reader, &options, |batch, report| {
+            table
+                .write()
+                .unwrap()
+                .import_batch(batch, options.doc_ids, &mut known_doc_ids, report)
+        })
+    });
+
+    let limit = limits
+        .get("import")
+        .unwrap_or(DEFAULT_IMPORT_LIMIT.mebibytes());
+    // Um byte além do limite é lido só para saber se o corpo foi cortado; ele não é importado
+    let mut stream = body.open(limit + 1.bytes());
+    let mut remaining = limit.as_u64();
+    let mut truncated = false;
+    let mut read_error = None;
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        match stream.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(read) => {
+                truncated = read as u64 > remaining;
+                chunk.truncate(read.min(remaining as usize));
+                remaining -= chunk.len() as u64;
+                // Falha somente se a importação já terminou (por exemplo, por um erro)
+                if sender.send(chunk).await.is_err() || truncated {
+                    break;
+                }
+            }
+            Err(e) => {
+                read_error = Some(e);
+                break;
+            }
+        }
+    }
+    drop(sender);
+
+    let result = match import.await {
+        Ok(result) => result,
+        Err(e) => Err(ChainDBError::SerializationError(e.to_string())),
+    };
+    match (result, read_error) {
+        (Ok(report), None) if !truncated => Json(ApiResponse::success(report)),
+        (Ok(report), None) => Json(ApiResponse::error(format!(
+            "Import stopped at the size limit of {}: {} records were imported",
+            limit, report.imported
+        ))),
+        (Ok(report), Some(e)) => Json(ApiResponse::error(format!(
+            "Failed to read the request body: {}. {} records were imported",
+            e, report.imported
+        ))),
+        (Err(e), _) => Json(ApiResponse::error(format!("Failed to import: {}", e))),
+    }
+}
+
+fn extension(format: TransferFormat) -> &'static str {
+    match format {
+        TransferFormat::Ndjson => "ndjson",
+        TransferFormat::Json => "json",
+        TransferFormat::Csv => "csv",
+    }
+}
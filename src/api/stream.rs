@@ -0,0 +1,188 @@
+use crate::api::models::{ApiResponse, TableData};
+use crate::iter::{blocking_stream, RecordIter};
+use crate::record::Record;
+use crate::transfer::{export_records, ExportOptions};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use rocket::http::{ContentType, Header, MediaType};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::stream::{ByteStream, TextStream};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use std::io::{self, BufWriter, Read, Write};
+
+/// Chunks of an export or import kept in flight between the network and the table
+const CHUNK_BUFFER: usize = 16;
+/// Size of the chunks sent in exports and read from import bodies
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Whether the client asked for newline-delimited JSON (`Accept: application/x-ndjson`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AcceptNdjson(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptNdjson {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ndjson = request
+            .accept()
+            .map(|accept| accept.media_types().any(is_ndjson))
+            .unwrap_or(false);
+        Outcome::Success(AcceptNdjson(ndjson))
+    }
+}
+
+fn is_ndjson(media_type: &MediaType) -> bool {
+    media_type.top() == "application" && media_type.sub() == "x-ndjson"
+}
+
+/// Records returned by the find and history routes: a JSON page built in memory, or a stream
+/// with one JSON record per line, read from the table while the response is being sent
+pub enum RecordsResponse {
+    Json(Json<ApiResponse<Vec<serde_json::Value>>>),
+    Ndjson(RecordIter<TableData>),
+}
+
+impl RecordsResponse {
+    pub fn error(message: impl Into<String>) -> Self {
+        RecordsResponse::Json(Json(ApiResponse::error(message)))
+    }
+}
+
+impl<'r> Responder<'r, 'r> for RecordsResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            RecordsResponse::Json(json) => json.respond_to(request),
+            RecordsResponse::Ndjson(records) => {
+                // Um erro de leitura encerra o stream, com uma última linha descrevendo a falha
+                let mut failed = false;
+                let lines = records.map_while(move |record| {
+                    if failed {
+                        return None;
+                    }
+                    let line = match record {
+                        Ok(record) => record.to_json(),
+                        Err(e) => {
+                            failed = true;
+                            serde_json::json!({
+                                "success": false,
+                                "message": format!("Failed to read records: {}", e),
+                            })
+                        }
+                    };
+                    Some(format!("{}\n", line))
+                });
+
+                let mut response = TextStream(blocking_stream(lines)).respond_to(request)?;
+                response.set_header(ContentType::new("application", "x-ndjson"));
+                Ok(response)
+            }
+        }
+    }
+}
+
+/// Records of a table written in an export format while the response is being sent
+pub struct ExportStream {
+    pub file_name: String,
+    pub records: RecordIter<serde_json::Value>,
+    pub options: ExportOptions,
+}
+
+impl<'r> Responder<'r, 'r> for ExportStream {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let (sender, receiver) = mpsc::channel(CHUNK_BUFFER);
+        let content_type = self.options.format.content_type();
+        let file_name = self.file_name.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(sender));
+            // A resposta já começou: um erro só pode interromper o envio. As tabelas da API
+            // guardam `TableData`
+            let layout = TableData::LAYOUT;
+            if let Err(e) = export_records(self.records, layout, writer, &self.options) {
+                println!("Exportação de {} interrompida: {}", self.file_name, e);
+            }
+        });
+
+        let mut response = ByteStream(receiver).respond_to(request)?;
+        if let Some(content_type) = ContentType::parse_flexible(content_type) {
+            response.set_header(content_type);
+        }
+        response.set_header(Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file_name),
+        ));
+        Ok(response)
+    }
+}
+
+/// Response of the export route: the exported records, or the error that prevented the export
+pub enum ExportResponse {
+    Stream(ExportStream),
+    Json(Json<ApiResponse<()>>),
+}
+
+impl ExportResponse {
+    pub fn error(message: impl Into<String>) -> Self {
+        ExportResponse::Json(Json(ApiResponse::error(message)))
+    }
+}
+
+impl<'r> Responder<'r, 'r> for ExportResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            ExportResponse::Stream(stream) => stream.respond_to(request),
+            ExportResponse::Json(json) => json.respond_to(request),
+        }
+    }
+}
+
+/// Sends everything written to it through a channel, waiting while the channel is full
+struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        futures::executor::block_on(self.0.send(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the chunks received through a channel, waiting for the next one when needed. The
+/// input ends when the sender is dropped
+pub struct ChannelReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: io::Cursor<Vec<u8>>,
+}
+
+impl ChannelReader {
+    /// A reader and the sender that feeds it
+    pub fn channel() -> (mpsc::Sender<Vec<u8>>, Self) {
+        let (sender, receiver) = mpsc::channel(CHUNK_BUFFER);
+        let reader = Self {
+            receiver,
+            chunk: io::Cursor::new(Vec::new()),
+        };
+        (sender, reader)
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match futures::executor::block_on(self.receiver.next()) {
+                Some(chunk) => self.chunk = io::Cursor::new(chunk),
+                None => return Ok(0),
+            }
+        }
+    }
+}
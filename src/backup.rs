@@ -0,0 +1,764 @@
+//! Online backups of whole databases as single encrypted archive files.
+//!
+//! A full archive holds every file of the database (`config.cdb` and, for each table,
+//! `metadata.cdb` and the `data_N.cdb` segments), each encrypted on its own with the database
+//! key, followed by an encrypted manifest listing the files with their SHA-256 checksums:
+//!
+//! ```text
+//! CHAINDB-BACKUP-1\n | file 1 | file 2 | ... | manifest | manifest length (u64, little endian)
+//! ```
+//!
+//! An incremental archive has the same layout but stores only the files whose checksum changed
+//! since the backup it is based on. Its manifest still lists the checksum of every file of the
+//! database, so a restore replays a full backup and then each incremental of the chain, deleting
+//! the files that no longer exist, and ends with exactly the files of the last backup.
+//!
+//! Backups run while the database is in use. The database lock is held in shared mode for the
+//! whole backup, so no transaction commits halfway through it, and each table is copied under
+//! its shared lock, so every table is captured between two writes.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::chaindb::DATA_DIR;
+use crate::config::Config;
+use crate::encryption::DataEncryption;
+use crate::errors::ChainDBError;
+use crate::lock::{FileLock, LockMode, LOCK_FILE};
+use crate::table::{TableMetadata, METADATA_FILE};
+use crate::transaction::JOURNAL_FILE;
+
+const MAGIC: &[u8] = b"CHAINDB-BACKUP-1\n";
+pub const FORMAT_VERSION: u32 = 1;
+pub const BACKUP_EXTENSION: &str = "cdbak";
+/// Directory inside the data directory that holds the backups made by `ChainDB::create_backup`
+pub const BACKUPS_DIR: &str = ".backups";
+/// Environment variable with how many backups `ChainDB::create_backup` keeps per database
+pub const BACKUP_KEEP_ENV: &str = "CHAIN_DB_BACKUP_KEEP";
+const DEFAULT_BACKUP_KEEP: usize = 7;
+const CONFIG_FILE: &str = "config.cdb";
+
+/// A file stored in a backup archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Path relative to the database directory, with `/` separators, e.g. `orders/data_0.cdb`
+    pub path: String,
+    /// Position and length of the encrypted contents in the archive
+    pub offset: u64,
+    pub length: u64,
+    /// Size and SHA-256 checksum (hex) of the original file
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// State of a table when it was backed up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    /// Sequence number of the table: records persisted to it so far. A restore checks that the
+    /// replayed table ends at this sequence
+    pub total_records: u64,
+    pub current_file: u32,
+}
+
+/// Whether an archive holds the whole database or only what changed since another backup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    #[default]
+    Full,
+    Incremental,
+}
+
+/// Contents of a backup archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub id: String,
+    pub database: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub kind: BackupKind,
+    /// Id of the backup an incremental backup is based on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    pub tables: BTreeMap<String, TableSnapshot>,
+    /// Files stored in this archive
+    pub files: Vec<BackupEntry>,
+    /// SHA-256 checksum of every file of the database at backup time, stored in this archive or
+    /// not, by path
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+}
+
+/// A backup file kept in the backups directory of a database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub size: u64,
+    pub modified_at: String,
+}
+
+/// How many backups `ChainDB::create_backup` keeps: `CHAIN_DB_BACKUP_KEEP` if set, otherwise 7
+pub fn backup_keep() -> usize {
+    std::env::var(BACKUP_KEEP_ENV)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_BACKUP_KEEP)
+}
+
+/// Directory with the backups of the database `name`
+pub fn backups_dir(name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(BACKUPS_DIR).join(name)
+}
+
+/// Name of a new backup file of the database `name`, ordered by creation time. Incremental
+/// backups end in `.inc.cdbak`
+pub(crate) fn backup_file_name(name: &str, kind: BackupKind) -> String {
+    let suffix = match kind {
+        BackupKind::Full => "",
+        BackupKind::Incremental => ".inc",
+    };
+    format!(
+        "{}_{}{}.{}",
+        name,
+        chrono::Utc::now().format("%Y%m%d_%H%M%S_%3f"),
+        suffix,
+        BACKUP_EXTENSION
+    )
+}
+
+/// Backups of the database `name`, oldest first
+pub fn list_backups(name: &str) -> Result<Vec<BackupInfo>, ChainDBError> {
+    let dir = backups_dir(name);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file()
+            || path.extension().and_then(|ext| ext.to_str()) != Some(BACKUP_EXTENSION)
+        {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let modified_at: chrono::DateTime<chrono::Utc> = metadata.modified()?.into();
+        backups.push(BackupInfo {
+            file_name: entry.file_name().to_string_lossy().to_string(),
+            size: metadata.len(),
+            modified_at: modified_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        });
+    }
+    backups.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(backups)
+}
+
+/// Deletes the oldest backups of the database `name`, keeping the `keep` most recent ones and
+/// the backups they are based on. Returns the names of the deleted files
+pub(crate) fn prune_backups(
+    name: &str,
+    keep: usize,
+    encryption: &DataEncryption,
+) -> Result<Vec<String>, ChainDBError> {
+    let dir = backups_dir(name);
+    let backups = list_backups(name)?;
+    let excess = backups.len().saturating_sub(keep);
+    if excess == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Bases das cadeias incrementais mantidas não podem ser removidas
+    let manifests = read_manifests(&dir, encryption)?;
+    let mut needed = HashSet::new();
+    for backup in &backups[excess..] {
+        let mut base = manifests
+            .values()
+            .find(|(path, _)| path.file_name() == Some(backup.file_name.as_ref()))
+            .and_then(|(_, manifest)| manifest.base.clone());
+        while let Some(id) = base {
+            if !needed.insert(id.clone()) {
+                break;
+            }
+            base = manifests
+                .get(&id)
+                .and_then(|(_, manifest)| manifest.base.clone());
+        }
+    }
+    let needed_files: HashSet<&str> = needed
+        .iter()
+        .filter_map(|id| manifests.get(id))
+        .filter_map(|(path, _)| path.file_name().and_then(|name| name.to_str()))
+        .collect();
+
+    let mut removed = Vec::with_capacity(excess);
+    for backup in backups.into_iter().take(excess) {
+        if needed_files.contains(backup.file_name.as_str()) {
+            continue;
+        }
+        fs::remove_file(dir.join(&backup.file_name))?;
+        removed.push(backup.file_name);
+    }
+    Ok(removed)
+}
+
+/// Archives to replay to restore `archive`: the full backup it descends from, then every
+/// incremental backup up to `archive` itself. The bases are looked up in the directory of
+/// `archive`
+pub(crate) fn backup_chain(
+    archive: &Path,
+    encryption: &DataEncryption,
+) -> Result<Vec<PathBuf>, ChainDBError> {
+    let manifest = read_manifest(archive, encryption)?;
+    if manifest.kind == BackupKind::Full {
+        return Ok(vec![archive.to_path_buf()]);
+    }
+
+    let dir = archive.parent().unwrap_or(Path::new("."));
+    let manifests = read_manifests(dir, encryption)?;
+    let mut chain = vec![archive.to_path_buf()];
+    let mut base = manifest.base;
+    while let Some(id) = base {
+        let Some((path, manifest)) = manifests.get(&id) else {
+            return Err(ChainDBError::ValidationError(format!(
+                "Base backup {} of {} not found",
+                id,
+                archive.display()
+            )));
+        };
+        if chain.len() > manifests.len() {
+            return Err(ChainDBError::ValidationError(
+                "Backup chain has a cycle".to_string(),
+            ));
+        }
+        chain.push(path.clone());
+        base = manifest.base.clone();
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Manifests of the archives in `dir` that can be read with the given key, by backup id
+fn read_manifests(
+    dir: &Path,
+    encryption: &DataEncryption,
+) -> Result<HashMap<String, (PathBuf, BackupManifest)>, ChainDBError> {
+    let mut manifests = HashMap::new();
+    if !dir.exists() {
+        return Ok(manifests);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(BACKUP_EXTENSION) {
+            continue;
+        }
+        // Backups feitos com outra senha ou corrompidos não fazem parte das cadeias
+        if let Ok(manifest) = read_manifest(&path, encryption) {
+            manifests.insert(manifest.id.clone(), (path, manifest));
+        }
+    }
+    Ok(manifests)
+}
+
+/// Writes a backup of the database stored in `base_path` to `dest`: a full backup, or an
+/// incremental one with the files that changed since `base`. The archive is written next to
+/// `dest` and renamed when complete, so `dest` is never left half written
+pub(crate) fn write_backup(
+    base_path: &Path,
+    database: &str,
+    encryption: &DataEncryption,
+    lock_timeout: Duration,
+    dest: &Path,
+    base: Option<&BackupManifest>,
+) -> Result<BackupManifest, ChainDBError> {
+    if let Some(parent) = dest
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = dest.with_extension(format!("{}.tmp", BACKUP_EXTENSION));
+    let mut writer = ArchiveWriter::create(&temp_path, encryption)?;
+
+    let mut manifest = BackupManifest {
+        format_version: FORMAT_VERSION,
+        id: Uuid::new_v4().to_string(),
+        database: database.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        kind: match base {
+            Some(_) => BackupKind::Incremental,
+            None => BackupKind::Full,
+        },
+        base: base.map(|base| base.id.clone()),
+        tables: BTreeMap::new(),
+        files: Vec::new(),
+        checksums: BTreeMap::new(),
+    };
+
+    let result = (|| {
+        // Impede que uma transação seja aplicada pela metade durante o backup
+        let _db_lock = FileLock::database(base_path, LockMode::Shared, lock_timeout)?;
+
+        for file_name in [CONFIG_FILE, JOURNAL_FILE] {
+            let path = base_path.join(file_name);
+            if path.exists() {
+                writer.store(&mut manifest, base, file_name.to_string(), fs::read(&path)?)?;
+            }
+        }
+
+        for table_name in table_dirs(base_path)? {
+            let table_path = base_path.join(&table_name);
+            let _lock = FileLock::table(&table_path, LockMode::Shared, lock_timeout)?;
+
+            let Some(metadata) = TableMetadata::load(&table_path, encryption)? else {
+                continue;
+            };
+            manifest.tables.insert(
+                table_name.clone(),
+                TableSnapshot {
+                    total_records: metadata.total_records,
+                    current_file: metadata.current_file,
+                },
+            );
+
+            for file_name in table_files(&table_path)? {
+                let bytes = fs::read(table_path.join(&file_name))?;
+                let path = format!("{}/{}", table_name, file_name);
+                writer.store(&mut manifest, base, path, bytes)?;
+            }
+        }
+
+        writer.finish(&manifest)
+    })();
+
+    match result {
+        Ok(()) => {
+            fs::rename(&temp_path, dest)?;
+            Ok(manifest)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Reads the manifest of a backup archive
+pub(crate) fn read_manifest(
+    archive: &Path,
+    encryption: &DataEncryption,
+) -> Result<BackupManifest, ChainDBError> {
+    let mut file = File::open(archive)?;
+    read_manifest_from(&mut file, encryption)
+}
+
+/// Restores a chain of archives into `target`, replacing the database stored there, if any.
+/// The chain starts with a full backup, followed by incremental backups each based on the
+/// previous one (see `backup_chain`).
+///
+/// The credentials must match the ones stored in the last archive. Every file is decrypted and
+/// checked against its checksum in a staging directory before the current database is touched.
+/// The database configuration is renamed to `name`, so a backup can be restored under another
+/// name. Returns the manifest of the last archive
+pub(crate) fn restore_backup(
+    archives: &[PathBuf],
+    target: &Path,
+    name: &str,
+    user: &str,
+    password: &str,
+    encryption: &DataEncryption,
+    lock_timeout: Duration,
+) -> Result<BackupManifest, ChainDBError> {
+    let data_dir = target.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(data_dir)?;
+    let staging = data_dir.join(format!(".restore_{}_{}", name, Uuid::new_v4()));
+
+    let result = (|| {
+        let mut previous: Option<BackupManifest> = None;
+        for archive in archives {
+            let mut file = File::open(archive)?;
+            let manifest = read_manifest_from(&mut file, encryption)?;
+            check_chain_link(previous.as_ref(), &manifest, archive)?;
+            replay_archive(&mut file, &manifest, &staging, encryption)?;
+            previous = Some(manifest);
+        }
+        let Some(manifest) = previous else {
+            return Err(ChainDBError::ValidationError(
+                "No backup to restore".to_string(),
+            ));
+        };
+        verify_restored(&staging, &manifest, encryption)?;
+
+        let config_path = staging.join(CONFIG_FILE);
+        if !config_path.exists() {
+            return Err(ChainDBError::ValidationError(
+                "Backup has no database configuration".to_string(),
+            ));
+        }
+        let mut config: Config =
+            serde_json::from_slice(&encryption.decrypt(&fs::read(&config_path)?)?)?;
+        if !config.matches(user, password) {
+            return Err(ChainDBError::InvalidCredentials(
+                "Invalid credentials".to_string(),
+            ));
+        }
+        if config.name != name {
+            config.name = name.to_string();
+            fs::write(
+                &config_path,
+                encryption.encrypt(&serde_json::to_vec(&config)?)?,
+            )?;
+        }
+        Ok(manifest)
+    })();
+    let manifest = match result {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    // Troca o banco atual pelo restaurado. Transações em andamento terminam antes da troca, e
+    // os locks de cada tabela impedem que uma escrita avulsa (que só bloqueia a sua tabela)
+    // caia no diretório que está sendo substituído
+    if target.exists() {
+        let old = data_dir.join(format!(".replaced_{}_{}", name, Uuid::new_v4()));
+        let swap = (|| {
+            let _db_lock = FileLock::database(target, LockMode::Exclusive, lock_timeout)?;
+            let _table_locks = table_dirs(target)?
+                .iter()
+                .map(|table| {
+                    FileLock::table(&target.join(table), LockMode::Exclusive, lock_timeout)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            fs::rename(target, &old)?;
+            Ok::<_, ChainDBError>(())
+        })();
+        if let Err(e) = swap {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        if let Err(e) = fs::rename(&staging, target) {
+            // Devolve o banco original ao lugar
+            let _ = fs::rename(&old, target);
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e.into());
+        }
+        fs::remove_dir_all(&old)?;
+    } else {
+        fs::rename(&staging, target)?;
+    }
+
+    Ok(manifest)
+}
+
+/// Checks that `manifest` comes right after `previous` in a chain of backups
+fn check_chain_link(
+    previous: Option<&BackupManifest>,
+    manifest: &BackupManifest,
+    archive: &Path,
+) -> Result<(), ChainDBError> {
+    match (previous, manifest.kind) {
+        (None, BackupKind::Full) => Ok(()),
+        (None, BackupKind::Incremental) => Err(ChainDBError::ValidationError(format!(
+            "{} is an incremental backup; the chain must start with a full backup",
+            archive.display()
+        ))),
+        (Some(_), BackupKind::Full) => Err(ChainDBError::ValidationError(format!(
+            "{} is a full backup; only incremental backups can follow the first one",
+            archive.display()
+        ))),
+        (Some(previous), BackupKind::Incremental) => {
+            if manifest.base.as_deref() == Some(previous.id.as_str()) {
+                Ok(())
+            } else {
+                Err(ChainDBError::ValidationError(format!(
+                    "{} is not based on the previous backup of the chain",
+                    archive.display()
+                )))
+            }
+        }
+    }
+}
+
+/// Writes the files stored in an archive to `staging` and deletes the files the database no
+/// longer had when the archive was made
+fn replay_archive(
+    file: &mut File,
+    manifest: &BackupManifest,
+    staging: &Path,
+    encryption: &DataEncryption,
+) -> Result<(), ChainDBError> {
+    for entry in &manifest.files {
+        let bytes = read_entry(file, entry, encryption)?;
+        let path = staging.join(safe_relative_path(&entry.path)?);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &bytes)?;
+    }
+
+    // Arquivos de versões anteriores do formato não têm a lista completa de checksums
+    if manifest.kind == BackupKind::Full && manifest.checksums.is_empty() {
+        return Ok(());
+    }
+    for path in staged_files(staging)? {
+        if !manifest.checksums.contains_key(&path) {
+            fs::remove_file(staging.join(&path))?;
+        }
+    }
+    for entry in fs::read_dir(staging)? {
+        let path = entry?.path();
+        if path.is_dir() && fs::read_dir(&path)?.next().is_none() {
+            fs::remove_dir(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the result of a restore: every file of the last backup with its checksum, and every
+/// table at the sequence number it had
+fn verify_restored(
+    staging: &Path,
+    manifest: &BackupManifest,
+    encryption: &DataEncryption,
+) -> Result<(), ChainDBError> {
+    for (path, sha256) in &manifest.checksums {
+        let file_path = staging.join(safe_relative_path(path)?);
+        if !file_path.exists() {
+            return Err(ChainDBError::ValidationError(format!(
+                "Backup chain is missing {}",
+                path
+            )));
+        }
+        if &sha256_hex(&fs::read(&file_path)?) != sha256 {
+            return Err(ChainDBError::ValidationError(format!(
+                "Checksum mismatch for {}",
+                path
+            )));
+        }
+    }
+
+    for (table_name, snapshot) in &manifest.tables {
+        let metadata = TableMetadata::load(&staging.join(table_name), encryption)?;
+        if metadata.map(|metadata| metadata.total_records) != Some(snapshot.total_records) {
+            return Err(ChainDBError::ValidationError(format!(
+                "Table {} was not restored to record {}",
+                table_name, snapshot.total_records
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Files in a staging directory, as archive paths (`config.cdb`, `orders/data_0.cdb`)
+fn staged_files(staging: &Path) -> Result<Vec<String>, ChainDBError> {
+    let mut files = Vec::new();
+    if !staging.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() {
+            for table_entry in fs::read_dir(entry.path())? {
+                let table_entry = table_entry?;
+                files.push(format!(
+                    "{}/{}",
+                    name,
+                    table_entry.file_name().to_string_lossy()
+                ));
+            }
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(files)
+}
+
+/// Writes the files of an archive one after the other
+struct ArchiveWriter<'a> {
+    writer: BufWriter<File>,
+    offset: u64,
+    encryption: &'a DataEncryption,
+}
+
+impl<'a> ArchiveWriter<'a> {
+    fn create(path: &Path, encryption: &'a DataEncryption) -> Result<Self, ChainDBError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            offset: MAGIC.len() as u64,
+            encryption,
+        })
+    }
+
+    /// Records a file of the database in the manifest, storing it in the archive unless `base`
+    /// already has it with the same checksum
+    fn store(
+        &mut self,
+        manifest: &mut BackupManifest,
+        base: Option<&BackupManifest>,
+        path: String,
+        bytes: Vec<u8>,
+    ) -> Result<(), ChainDBError> {
+        let sha256 = sha256_hex(&bytes);
+        let unchanged = base.is_some_and(|base| base.checksums.get(&path) == Some(&sha256));
+        if !unchanged {
+            let encrypted = self.encryption.encrypt(&bytes)?;
+            self.writer.write_all(&encrypted)?;
+            manifest.files.push(BackupEntry {
+                path: path.clone(),
+                offset: self.offset,
+                length: encrypted.len() as u64,
+                size: bytes.len() as u64,
+                sha256: sha256.clone(),
+            });
+            self.offset += encrypted.len() as u64;
+        }
+        manifest.checksums.insert(path, sha256);
+        Ok(())
+    }
+
+    fn finish(mut self, manifest: &BackupManifest) -> Result<(), ChainDBError> {
+        let encrypted = self.encryption.encrypt(&serde_json::to_vec(manifest)?)?;
+        self.writer.write_all(&encrypted)?;
+        self.writer
+            .write_all(&(encrypted.len() as u64).to_le_bytes())?;
+        self.writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        Ok(())
+    }
+}
+
+fn read_manifest_from(
+    file: &mut File,
+    encryption: &DataEncryption,
+) -> Result<BackupManifest, ChainDBError> {
+    let invalid = || ChainDBError::ValidationError("Not a ChainDB backup archive".to_string());
+
+    let mut magic = vec![0; MAGIC.len()];
+    file.read_exact(&mut magic).map_err(|_| invalid())?;
+    if magic != MAGIC {
+        return Err(invalid());
+    }
+
+    let archive_len = file.seek(SeekFrom::End(0))?;
+    if archive_len < MAGIC.len() as u64 + 8 {
+        return Err(invalid());
+    }
+    let mut length = [0; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    if length > archive_len - MAGIC.len() as u64 - 8 {
+        return Err(invalid());
+    }
+
+    let mut encrypted = vec![0; length as usize];
+    file.seek(SeekFrom::End(-8 - length as i64))?;
+    file.read_exact(&mut encrypted)?;
+    let manifest_json = encryption.decrypt(&encrypted).map_err(|_| {
+        ChainDBError::DecryptionError(
+            "Could not decrypt the backup: wrong password or corrupted archive".to_string(),
+        )
+    })?;
+    let manifest: BackupManifest = serde_json::from_slice(&manifest_json)?;
+
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(ChainDBError::ValidationError(format!(
+            "Backup format version {} is not supported",
+            manifest.format_version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Reads, decrypts and verifies a file of the archive
+fn read_entry(
+    file: &mut File,
+    entry: &BackupEntry,
+    encryption: &DataEncryption,
+) -> Result<Vec<u8>, ChainDBError> {
+    let corrupted =
+        || ChainDBError::ValidationError(format!("Backup of {} is corrupted", entry.path));
+
+    let mut encrypted = vec![0; entry.length as usize];
+    file.seek(SeekFrom::Start(entry.offset))?;
+    file.read_exact(&mut encrypted).map_err(|_| corrupted())?;
+    let bytes = encryption.decrypt(&encrypted).map_err(|_| corrupted())?;
+
+    if bytes.len() as u64 != entry.size || sha256_hex(&bytes) != entry.sha256 {
+        return Err(ChainDBError::ValidationError(format!(
+            "Checksum mismatch for {}",
+            entry.path
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Tables of the database: directories with a `metadata.cdb`, in name order
+fn table_dirs(base_path: &Path) -> Result<Vec<String>, ChainDBError> {
+    let mut tables = Vec::new();
+    for entry in fs::read_dir(base_path)? {
+        let path = entry?.path();
+        if path.is_dir() && path.join(METADATA_FILE).exists() {
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                tables.push(name.to_string());
+            }
+        }
+    }
+    tables.sort();
+    Ok(tables)
+}
+
+/// Files of a table that are part of a backup: the metadata and the data segments
+fn table_files(table_path: &Path) -> Result<Vec<String>, ChainDBError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(table_path)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let is_data_file = name.starts_with("data_") && name.ends_with(".cdb");
+        if path.is_file() && name != LOCK_FILE && (name == METADATA_FILE || is_data_file) {
+            files.push(name.to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Rejects archive paths that would escape the database directory
+fn safe_relative_path(path: &str) -> Result<PathBuf, ChainDBError> {
+    let relative = PathBuf::from(path);
+    if relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(relative)
+    } else {
+        Err(ChainDBError::ValidationError(format!(
+            "Invalid path in backup: {}",
+            path
+        )))
+    }
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,329 @@
+//! Offline maintenance of ChainDB data directories, through the library and without the server.
+//!
+//! Commands work on the `.chain_db_data` directory below the current directory, or below the
+//! directory given with `-C`. Credentials can be given as flags or through the
+//! `CHAIN_DB_USER` and `CHAIN_DB_PASSWORD` environment variables.
+
+use chain_db::backup::{BackupKind, BackupManifest};
+use chain_db::check::CheckReport;
+use chain_db::transfer::{CsvColumn, ExportOptions, TransferFormat};
+use chain_db::{ChainDB, ChainDBError, Table};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "chain-db-admin",
+    version,
+    about = "Offline maintenance of ChainDB databases"
+)]
+struct Cli {
+    /// Run as if started in this directory (the one holding `.chain_db_data`)
+    #[arg(short = 'C', long, global = true)]
+    dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Args)]
+struct Credentials {
+    /// Database name
+    db: String,
+    #[arg(short, long, env = "CHAIN_DB_USER")]
+    user: String,
+    #[arg(short, long, env = "CHAIN_DB_PASSWORD", hide_env_values = true)]
+    password: String,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a database
+    CreateDb(Credentials),
+    /// List the databases of the data directory
+    ListDbs,
+    /// List the tables of a database
+    Tables(Credentials),
+    /// Show the metadata of a table
+    Metadata {
+        #[command(flatten)]
+        credentials: Credentials,
+        table: String,
+    },
+    /// Write the records of a table to stdout or to a file
+    Dump {
+        #[command(flatten)]
+        credentials: Credentials,
+        table: String,
+        /// json, ndjson or csv
+        #[arg(short, long, default_value = "json")]
+        format: TransferFormat,
+        /// CSV columns, as `header=field` or `field`, separated by commas
+        #[arg(long)]
+        columns: Option<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check the integrity of every table; exits with 1 when problems are found
+    Verify {
+        #[command(flatten)]
+        credentials: Credentials,
+        /// Quarantine unreadable lines and rewrite inconsistent metadata
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Sweep expired documents and delete data files left without live records
+    Compact(Credentials),
+    /// Change the password, re-encrypting every table
+    ChangePassword {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(long, env = "CHAIN_DB_NEW_PASSWORD", hide_env_values = true)]
+        new_password: String,
+    },
+    /// Back a database up to a file, or to its backups directory when no file is given
+    Backup {
+        #[command(flatten)]
+        credentials: Credentials,
+        dest: Option<PathBuf>,
+        /// Store only what changed since `--base` (or, without a destination file, since the
+        /// most recent backup in the backups directory)
+        #[arg(long)]
+        incremental: bool,
+        /// Backup the incremental backup is based on
+        #[arg(long, requires = "incremental")]
+        base: Option<PathBuf>,
+    },
+    /// List the backups in the backups directory of a database
+    Backups(Credentials),
+    /// Restore a database from a backup, or from a full backup followed by incremental ones
+    Restore {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(required = true)]
+        archives: Vec<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(dir) = &cli.dir {
+        if let Err(e) = std::env::set_current_dir(dir) {
+            eprintln!("Cannot use {}: {}", dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match run(cli.command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, ChainDBError> {
+    match command {
+        Command::CreateDb(credentials) => {
+            ChainDB::create_database(&credentials.db, &credentials.user, &credentials.password)?;
+            println!("Database '{}' created", credentials.db);
+        }
+        Command::ListDbs => {
+            for name in ChainDB::list_databases()? {
+                println!("{}", name);
+            }
+        }
+        Command::Tables(credentials) => {
+            let mut tables = connect(&credentials)?.list_tables()?;
+            tables.sort();
+            for table in tables {
+                println!("{}", table);
+            }
+        }
+        Command::Metadata { credentials, table } => {
+            let db = connect(&credentials)?;
+            print_json(&open_table(&db, &table)?.metadata())?;
+        }
+        Command::Dump {
+            credentials,
+            table,
+            format,
+            columns,
+            output,
+        } => {
+            let db = connect(&credentials)?;
+            let table = open_table(&db, &table)?;
+            let options = ExportOptions {
+                format,
+                columns: columns.as_deref().map(CsvColumn::parse_list),
+            };
+            let exported = match &output {
+                Some(path) => table.export(BufWriter::new(File::create(path)?), options)?,
+                None => table.export(io::stdout().lock(), options)?,
+            };
+            if let Some(path) = output {
+                eprintln!("{} records written to {}", exported, path.display());
+            }
+        }
+        Command::Verify {
+            credentials,
+            repair,
+        } => {
+            let db = connect(&credentials)?;
+            let report = if repair { db.repair()? } else { db.check()? };
+            print_check_report(&report);
+            if !report.is_ok() && !repair {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Compact(credentials) => {
+            let db = connect(&credentials)?;
+            let mut tables = db.list_tables()?;
+            tables.sort();
+            for name in tables {
+                let mut table = open_table(&db, &name)?;
+                if table.retention().is_none() {
+                    println!("{}: no retention policy, nothing to compact", name);
+                    continue;
+                }
+                let stats = table.sweep()?;
+                println!(
+                    "{}: {} expired documents swept, {} data files deleted",
+                    name, stats.expired, stats.files_dropped
+                );
+            }
+        }
+        Command::ChangePassword {
+            credentials,
+            new_password,
+        } => {
+            let mut db = connect(&credentials)?;
+            db.change_password(&new_password)?;
+            println!("Password of '{}' changed", credentials.db);
+        }
+        Command::Backup {
+            credentials,
+            dest,
+            incremental,
+            base,
+        } => {
+            let db = connect(&credentials)?;
+            match (dest, incremental) {
+                (Some(dest), false) => print_manifest(&dest, &db.backup(&dest)?),
+                (Some(dest), true) => {
+                    let Some(base) = base else {
+                        return Err(ChainDBError::ValidationError(
+                            "An incremental backup to a file needs --base".to_string(),
+                        ));
+                    };
+                    print_manifest(&dest, &db.backup_incremental(&base, &dest)?);
+                }
+                (None, false) => println!("{}", db.create_backup()?.file_name),
+                (None, true) => println!("{}", db.create_incremental_backup()?.file_name),
+            }
+        }
+        Command::Backups(credentials) => {
+            for backup in connect(&credentials)?.list_backups()? {
+                println!(
+                    "{}\t{} bytes\t{}",
+                    backup.file_name, backup.size, backup.modified_at
+                );
+            }
+        }
+        Command::Restore {
+            credentials,
+            archives,
+        } => {
+            let manifest = if archives.len() == 1 {
+                ChainDB::restore(
+                    &archives[0],
+                    &credentials.db,
+                    &credentials.user,
+                    &credentials.password,
+                )?
+            } else {
+                ChainDB::restore_chain(
+                    &archives,
+                    &credentials.db,
+                    &credentials.user,
+                    &credentials.password,
+                )?
+            };
+            println!(
+                "Database '{}' restored from backup {} ({})",
+                credentials.db, manifest.id, manifest.created_at
+            );
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn connect(credentials: &Credentials) -> Result<ChainDB, ChainDBError> {
+    Ok(ChainDB::connect(&credentials.db, &credentials.user, &credentials.password)?.db)
+}
+
+/// Opens a table of the database, without creating it when it doesn't exist
+fn open_table(db: &ChainDB, name: &str) -> Result<Table<serde_json::Value>, ChainDBError> {
+    if !db.list_tables()?.iter().any(|table| table == name) {
+        return Err(ChainDBError::TableNotFound(name.to_string()));
+    }
+    db.create_table(name)
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), ChainDBError> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn print_manifest(dest: &Path, manifest: &BackupManifest) {
+    let size: u64 = manifest.files.iter().map(|file| file.size).sum();
+    println!(
+        "{} backup {} written to {}: {} files, {} bytes",
+        match manifest.kind {
+            BackupKind::Full => "Full",
+            BackupKind::Incremental => "Incremental",
+        },
+        manifest.id,
+        dest.display(),
+        manifest.files.len(),
+        size
+    );
+}
+
+fn print_check_report(report: &CheckReport) {
+    for table in &report.tables {
+        let status = if table.is_ok() { "ok" } else { "PROBLEMS" };
+        println!(
+            "{}: {} ({} records in {} data files)",
+            table.table, status, table.records, table.data_files
+        );
+        for bad_line in &table.bad_lines {
+            println!(
+                "  {} line {}: {}",
+                bad_line.file, bad_line.line, bad_line.error
+            );
+        }
+        for duplicate in &table.duplicate_doc_ids {
+            println!(
+                "  doc_id {} stored at records {:?}",
+                duplicate.doc_id, duplicate.records
+            );
+        }
+        for issue in &table.metadata_issues {
+            println!("  {}", issue);
+        }
+        if table.quarantined > 0 {
+            println!("  {} lines quarantined", table.quarantined);
+        }
+        if table.metadata_rewritten {
+            println!("  metadata rewritten");
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Interactive shell for debugging ChainDB databases.
+//!
+//! Works on the `.chain_db_data` directory below the current directory (or below the directory
+//! given with `-C`), or on a running server with `--server`. Type `help` for the commands; the
+//! command history is kept in `~/.chain_db_history`.
+
+use chain_db::api::models::TableData;
+use chain_db::client::{Client, Document, TableClient};
+use chain_db::query::QueryOptions;
+use chain_db::shell::{keep_in_history, parse_command, ShellCommand, HELP};
+use chain_db::table::ComparisonOperator;
+use chain_db::{ChainDB, ChainDBError, Table};
+use clap::Parser;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const HISTORY_FILE: &str = ".chain_db_history";
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "chain-db-shell",
+    version,
+    about = "Interactive shell for ChainDB databases"
+)]
+struct Cli {
+    /// Database selected at startup
+    db: Option<String>,
+    /// URL of a running server, e.g. http://localhost:2818
+    #[arg(short, long, conflicts_with = "dir")]
+    server: Option<String>,
+    /// Run as if started in this directory (the one holding `.chain_db_data`)
+    #[arg(short = 'C', long)]
+    dir: Option<PathBuf>,
+    #[arg(short, long, env = "CHAIN_DB_USER")]
+    user: Option<String>,
+    #[arg(short, long, env = "CHAIN_DB_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+}
+
+/// Where the commands are executed
+trait Backend {
+    fn use_db(&mut self, db: &str, user: &str, password: &str) -> Result<(), ChainDBError>;
+    fn databases(&self) -> Result<Vec<String>, ChainDBError>;
+    fn tables(&self) -> Result<Vec<String>, ChainDBError>;
+    /// Matching records and the total number of matches
+    fn find(
+        &self,
+        table: &str,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<serde_json::Value>, usize), ChainDBError>;
+    fn get(&self, table: &str, doc_id: &str) -> Result<Option<serde_json::Value>, ChainDBError>;
+    fn history(&self, table: &str, limit: usize) -> Result<Vec<serde_json::Value>, ChainDBError>;
+    /// Persists a record and returns it as stored
+    fn persist(
+        &mut self,
+        table: &str,
+        data: serde_json::Value,
+    ) -> Result<serde_json::Value, ChainDBError>;
+}
+
+/// Runs the commands through the library, on the local data directory
+#[derive(Default)]
+struct LocalBackend {
+    db: Option<ChainDB>,
+}
+
+impl LocalBackend {
+    fn db(&self) -> Result<&ChainDB, ChainDBError> {
+        self.db.as_ref().ok_or_else(no_database)
+    }
+
+    /// Opens a table of the database, without creating it when it doesn't exist
+    fn table(&self, name: &str) -> Result<Table<TableData>, ChainDBError> {
+        let db = self.db()?;
+        if !db.list_tables()?.iter().any(|table| table == name) {
+            return Err(ChainDBError::TableNotFound(name.to_string()));
+        }
+        db.create_table(name)
+    }
+}
+
+impl Backend for LocalBackend {
+    fn use_db(&mut self, db: &str, user: &str, password: &str) -> Result<(), ChainDBError> {
+        self.db = Some(ChainDB::connect(db, user, password)?.db);
+        Ok(())
+    }
+
+    fn databases(&self) -> Result<Vec<String>, ChainDBError> {
+        ChainDB::list_databases()
+    }
+
+    fn tables(&self) -> Result<Vec<String>, ChainDBError> {
+        let mut tables = self.db()?.list_tables()?;
+        tables.sort();
+        Ok(tables)
+    }
+
+    fn find(
+        &self,
+        table: &str,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<serde_json::Value>, usize), ChainDBError> {
+        let options = QueryOptions {
+            limit,
+            count_total: true,
+            ..Default::default()
+        };
+        let page = self.table(table)?.query(criteria, &options)?;
+        let records = page.records.iter().map(TableData::to_json).collect();
+        Ok((records, page.total.unwrap_or_default()))
+    }
+
+    fn get(&self, table: &str, doc_id: &str) -> Result<Option<serde_json::Value>, ChainDBError> {
+        Ok(self
+            .table(table)?
+            .get_by_doc_id(doc_id)?
+            .map(|(record, _)| record.to_json()))
+    }
+
+    fn history(&self, table: &str, limit: usize) -> Result<Vec<serde_json::Value>, ChainDBError> {
+        let history = self.table(table)?.get_history(limit)?;
+        Ok(history.iter().map(TableData::to_json).collect())
+    }
+
+    fn persist(
+        &mut self,
+        table: &str,
+        data: serde_json::Value,
+    ) -> Result<serde_json::Value, ChainDBError> {
+        // Como a rota persist, cria a tabela quando ela ainda não existe
+        let mut table: Table<TableData> = self.db()?.create_table(table)?;
+        table.persist(&TableData::from_json(data))?;
+        Ok(table.get_table()?.to_json())
+    }
+}
+
+/// Runs the commands through the HTTP API of a running server
+struct ServerBackend {
+    /// Runs the requests of the async client
+    runtime: tokio::runtime::Runtime,
+    /// URL of the server
+    server: String,
+    /// Connection to the selected database
+    client: Option<Client>,
+}
+
+impl ServerBackend {
+    fn new(server: &str) -> Result<Self, ChainDBError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            runtime,
+            server: server.to_string(),
+            client: None,
+        })
+    }
+
+    fn client(&self) -> Result<&Client, ChainDBError> {
+        self.client.as_ref().ok_or_else(no_database)
+    }
+
+    fn table(&self, name: &str) -> Result<TableClient<serde_json::Value>, ChainDBError> {
+        Ok(self.client()?.table(name))
+    }
+}
+
+impl Backend for ServerBackend {
+    fn use_db(&mut self, db: &str, user: &str, password: &str) -> Result<(), ChainDBError> {
+        // O banco atual só é trocado se as credenciais forem aceitas
+        let client = self
+            .runtime
+            .block_on(Client::connect(&self.server, db, user, password))?;
+        self.client = Some(client);
+        Ok(())
+    }
+
+    fn databases(&self) -> Result<Vec<String>, ChainDBError> {
+        Err(ChainDBError::ValidationError(
+            "Listing databases is only available on a local data directory".to_string(),
+        ))
+    }
+
+    fn tables(&self) -> Result<Vec<String>, ChainDBError> {
+        let mut tables = self.runtime.block_on(self.client()?.tables())?;
+        tables.sort();
+        Ok(tables)
+    }
+
+    fn find(
+        &self,
+        table: &str,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<serde_json::Value>, usize), ChainDBError> {
+        let (documents, total) = self
+            .runtime
+            .block_on(self.table(table)?.find_advanced_with_total(criteria, limit))?;
+        let records = documents
+            .iter()
+            .map(Document::to_json)
+            .collect::<Result<_, _>>()?;
+        Ok((records, total))
+    }
+
+    fn get(&self, table: &str, doc_id: &str) -> Result<Option<serde_json::Value>, ChainDBError> {
+        self.runtime
+            .block_on(self.table(table)?.get(doc_id))?
+            .map(|document| document.to_json())
+            .transpose()
+    }
+
+    fn history(&self, table: &str, limit: usize) -> Result<Vec<serde_json::Value>, ChainDBError> {
+        self.runtime
+            .block_on(self.table(table)?.history(limit))?
+            .iter()
+            .map(Document::to_json)
+            .collect()
+    }
+
+    fn persist(
+        &mut self,
+        table: &str,
+        data: serde_json::Value,
+    ) -> Result<serde_json::Value, ChainDBError> {
+        self.runtime
+            .block_on(self.table(table)?.persist(&data))?
+            .to_json()
+    }
+}
+
+fn no_database() -> ChainDBError {
+    ChainDBError::ValidationError("No database selected, run 'use <db>' first".to_string())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(dir) = &cli.dir {
+        if let Err(e) = std::env::set_current_dir(dir) {
+            eprintln!("Cannot use {}: {}", dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut backend: Box<dyn Backend> = match &cli.server {
+        Some(server) => match ServerBackend::new(server) {
+            Ok(backend) => Box::new(backend),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Box::<LocalBackend>::default(),
+    };
+
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let history_path = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(HISTORY_FILE);
+    // O arquivo ainda não existe na primeira execução
+    let _ = editor.load_history(&history_path);
+
+    let mut current_db = None;
+    if let Some(db) = &cli.db {
+        match run_command(
+            backend.as_mut(),
+            &cli,
+            &mut current_db,
+            ShellCommand::Use {
+                db: db.clone(),
+                credentials: None,
+            },
+        ) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    loop {
+        let prompt = match &current_db {
+            Some(db) => format!("chain-db:{}> ", db),
+            None => "chain-db> ".to_string(),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            // Ctrl-C descarta a linha atual, Ctrl-D encerra
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                break;
+            }
+        };
+        if keep_in_history(&line) {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        let command = match parse_command(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        match run_command(backend.as_mut(), &cli, &mut current_db, command) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Err(e) = editor.save_history(&history_path) {
+        eprintln!(
+            "Cannot save the history to {}: {}",
+            history_path.display(),
+            e
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+/// Runs a command, returning `false` when the shell should exit
+fn run_command(
+    backend: &mut dyn Backend,
+    cli: &Cli,
+    current_db: &mut Option<String>,
+    command: ShellCommand,
+) -> Result<bool, ChainDBError> {
+    match command {
+        ShellCommand::Use { db, credentials } => {
+            let (user, password) = match credentials {
+                Some(credentials) => credentials,
+                None => match (&cli.user, &cli.password) {
+                    (Some(user), Some(password)) => (user.clone(), password.clone()),
+                    _ => return Err(ChainDBError::ValidationError(
+                        "No credentials, use 'use <db> <user> <password>' or --user and --password"
+                            .to_string(),
+                    )),
+                },
+            };
+            backend.use_db(&db, &user, &password)?;
+            println!("Using database '{}'", db);
+            *current_db = Some(db);
+        }
+        ShellCommand::Databases => {
+            for db in backend.databases()? {
+                println!("{}", db);
+            }
+        }
+        ShellCommand::Tables => {
+            for table in backend.tables()? {
+                println!("{}", table);
+            }
+        }
+        ShellCommand::Find {
+            table,
+            criteria,
+            limit,
+        } => {
+            let (records, total) = backend.find(&table, criteria, limit)?;
+            let shown = records.len();
+            print_json(&serde_json::Value::Array(records))?;
+            if total > shown {
+                println!("({} of {} records)", shown, total);
+            } else {
+                println!("({} records)", shown);
+            }
+        }
+        ShellCommand::Get { table, doc_id } => match backend.get(&table, &doc_id)? {
+            Some(record) => print_json(&record)?,
+            None => println!("Document with doc_id {} not found", doc_id),
+        },
+        ShellCommand::History { table, limit } => {
+            let records = backend.history(&table, limit)?;
+            let shown = records.len();
+            print_json(&serde_json::Value::Array(records))?;
+            println!("({} records)", shown);
+        }
+        ShellCommand::Persist { table, data } => print_json(&backend.persist(&table, data)?)?,
+        ShellCommand::Help => println!("{}", HELP),
+        ShellCommand::Exit => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn print_json(value: &serde_json::Value) -> Result<(), ChainDBError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
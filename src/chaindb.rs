@@ -1,16 +1,24 @@
 use base64::{engine::general_purpose, Engine as _};
-use chrono;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use crate::backup::{self, BackupInfo, BackupKind, BackupManifest};
+use crate::check::{self, CheckReport};
 use crate::config::Config;
 use crate::encryption::DataEncryption;
 use crate::errors::ChainDBError;
+use crate::lock;
+use crate::record::Record;
 use crate::table::{Table, METADATA_FILE};
+use crate::transaction::{self, Transaction};
 
-const DATA_DIR: &str = ".chain_db_data";
+pub(crate) const DATA_DIR: &str = ".chain_db_data";
+
+/// Data de modificação e tamanho do `config.cdb` de um banco
+pub(crate) type ConfigStamp = (Option<SystemTime>, u64);
 
 #[derive(Debug, Serialize)]
 pub struct ConnectionInfo {
@@ -20,10 +28,12 @@ pub struct ConnectionInfo {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChainDB {
-    base_path: PathBuf,
+    pub(crate) base_path: PathBuf,
     config: Config,
     #[serde(skip)]
-    encryption: DataEncryption,
+    pub(crate) encryption: DataEncryption,
+    #[serde(skip, default = "lock::default_timeout")]
+    pub(crate) lock_timeout: Duration,
 }
 
 impl ChainDB {
@@ -55,6 +65,26 @@ impl ChainDB {
         Ok(())
     }
 
+    /// Names of the databases in the data directory, in alphabetical order
+    pub fn list_databases() -> Result<Vec<String>, ChainDBError> {
+        let data_dir = PathBuf::from(DATA_DIR);
+        if !data_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut databases = Vec::new();
+        for entry in fs::read_dir(&data_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Diretórios ocultos guardam backups e restaurações em andamento
+            if !name.starts_with('.') && entry.path().join("config.cdb").exists() {
+                databases.push(name);
+            }
+        }
+        databases.sort();
+        Ok(databases)
+    }
+
     pub fn connect(name: &str, user: &str, password: &str) -> Result<ConnectionInfo, ChainDBError> {
         let encryption = DataEncryption::new(password);
         let data_dir = PathBuf::from(DATA_DIR);
@@ -67,12 +97,15 @@ impl ChainDB {
         let config: Config = serde_json::from_slice(&config_json)?;
 
         // Verify credentials
-        if config.name != name || config.user != user || config.password != password {
+        if config.name != name || !config.matches(user, password) {
             return Err(ChainDBError::InvalidCredentials(
                 "Invalid credentials".to_string(),
             ));
         }
 
+        // Concluir uma transação interrompida depois do commit
+        transaction::recover(&base_path, &encryption, lock::default_timeout())?;
+
         // Generate auth token
         let auth_data = format!("{}:{}:{}", name, user, password);
         let auth_token = general_purpose::STANDARD.encode(auth_data);
@@ -82,11 +115,25 @@ impl ChainDB {
                 base_path,
                 config,
                 encryption,
+                lock_timeout: lock::default_timeout(),
             },
             auth_token,
         })
     }
 
+    /// Data de modificação e tamanho do `config.cdb` do banco `name`. Mudam quando a senha é
+    /// trocada ou um backup é restaurado, inclusive por outro processo
+    pub(crate) fn config_stamp(name: &str) -> Option<ConfigStamp> {
+        let config_path = PathBuf::from(DATA_DIR).join(name).join("config.cdb");
+        let file_metadata = fs::metadata(config_path).ok()?;
+        Some((file_metadata.modified().ok(), file_metadata.len()))
+    }
+
+    /// Confere usuário e senha com a configuração carregada no `connect`
+    pub(crate) fn verify_credentials(&self, user: &str, password: &str) -> bool {
+        self.config.matches(user, password)
+    }
+
     pub fn change_password(&mut self, new_password: &str) -> Result<(), ChainDBError> {
         // Verificar se a nova senha é diferente da atual
         if self.config.password == new_password {
@@ -117,19 +164,8 @@ impl ChainDB {
             )));
         }
 
-        // Create bkp_timestamp directory
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let backup_dir = self.base_path.join(format!("bkp_{}", timestamp));
-        fs::create_dir_all(&backup_dir)?;
-
-        // Get current table path
-        let table_path = self.base_path.clone();
-        println!("table_path: {:?}", table_path);
-
         // Criar uma estrutura para controlar o processo e permitir rollback
         struct ChangePasswordProcess {
-            original_path: PathBuf,
-            backup_dir: PathBuf,
             temp_dir: PathBuf,
             success: bool,
         }
@@ -151,16 +187,14 @@ impl ChainDB {
 
         // Iniciar o processo com controle de rollback
         let mut process = ChangePasswordProcess {
-            original_path: self.base_path.clone(),
-            backup_dir: backup_dir.clone(),
             temp_dir: self.base_path.join("temp"),
             success: false,
         };
 
-        // Copy the table folder to the backup directory
-        let backup_path = backup_dir.join(self.base_path.file_name().unwrap_or_default());
-        copy_dir_all_files(&table_path, &backup_dir)?;
-        println!("Backup created at: {:?}", backup_dir);
+        // Backup cifrado com a senha atual, antes de qualquer alteração. Não remove backups
+        // antigos: a troca de senha não deve apagar nada que o usuário não pediu
+        let backup = self.store_backup(None, false)?;
+        println!("Backup created: {}", backup.file_name);
 
         // Create a temp folder to prepare the updated files with the new password
         let temp_dir = process.temp_dir.clone();
@@ -174,8 +208,9 @@ impl ChainDB {
             let entry = entry?;
             let path = entry.path();
 
-            // Ignorar arquivos e diretórios especiais
-            if !path.is_dir() || path == temp_dir || path == backup_dir {
+            // Ignorar arquivos e diretórios especiais (incluindo cópias bkp_ de versões antigas)
+            let is_old_backup = entry.file_name().to_string_lossy().starts_with("bkp_");
+            if !path.is_dir() || path == temp_dir || is_old_backup {
                 continue;
             }
 
@@ -334,11 +369,37 @@ impl ChainDB {
 
     pub fn create_table<T>(&self, name: &str) -> Result<Table<T>, ChainDBError>
     where
-        T: Serialize + for<'de> Deserialize<'de>,
+        T: Record,
     {
         let table_path = self.base_path.join(name);
         fs::create_dir_all(&table_path)?;
-        Table::new(table_path, self.encryption.clone())
+        let mut table =
+            Table::with_lock_timeout(table_path, self.encryption.clone(), self.lock_timeout)?;
+        table.set_user(Some(self.config.user.clone()));
+        Ok(table)
+    }
+
+    /// Usuário do banco, registrado em `created_by` e `updated_by`
+    pub(crate) fn user(&self) -> &str {
+        &self.config.user
+    }
+
+    /// Tempo máximo de espera pelos locks das tabelas
+    pub fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
+    /// Define quanto tempo as tabelas abertas a partir desta conexão (e as transações) esperam
+    /// pelos locks antes de retornar `ChainDBError::LockTimeout`. O padrão é 5 segundos, ou o
+    /// valor da variável de ambiente `CHAIN_DB_LOCK_TIMEOUT_MS`
+    pub fn set_lock_timeout(&mut self, lock_timeout: Duration) {
+        self.lock_timeout = lock_timeout;
+    }
+
+    /// Starts a transaction that buffers writes across tables of this database and
+    /// commits them all-or-nothing (see `Transaction::commit`)
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction::new(self)
     }
 
     /// Lists all tables in the database
@@ -373,43 +434,187 @@ impl ChainDB {
 
         Ok(tables)
     }
-}
 
-// Função auxiliar para copiar diretórios recursivamente
-fn copy_dir_all_files(source: &PathBuf, destination: &PathBuf) -> Result<(), ChainDBError> {
-    // Criar o diretório de destino se não existir
-    if !destination.exists() {
-        fs::create_dir_all(destination)?;
+    /// Writes a backup of the whole database to `dest` as a single archive file, encrypted with
+    /// the database password. Writes can go on during the backup: each table is captured
+    /// between two writes and no transaction is captured halfway through
+    pub fn backup(&self, dest: impl AsRef<Path>) -> Result<BackupManifest, ChainDBError> {
+        backup::write_backup(
+            &self.base_path,
+            &self.config.name,
+            &self.encryption,
+            self.lock_timeout,
+            dest.as_ref(),
+            None,
+        )
     }
 
-    // Ler o conteúdo do diretório de origem
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = entry.file_name();
-
-        // Ignorar o diretório de backup para evitar recursão infinita
-        if path.is_dir() {
-            // Ignorar pastas que começam com "bkp_" ou que são o próprio destino
-            let dir_name = file_name.to_string_lossy();
-            if dir_name.starts_with("bkp_") || path.starts_with(destination) {
-                println!("Skipping backup directory: {:?}", path);
-                continue;
+    /// Writes an incremental backup to `dest`, with only the segments and metadata that changed
+    /// since the backup in `base` (full or incremental). Restoring it needs `base` and the
+    /// backups `base` depends on
+    pub fn backup_incremental(
+        &self,
+        base: impl AsRef<Path>,
+        dest: impl AsRef<Path>,
+    ) -> Result<BackupManifest, ChainDBError> {
+        let base = backup::read_manifest(base.as_ref(), &self.encryption)?;
+        if base.database != self.config.name {
+            return Err(ChainDBError::ValidationError(format!(
+                "Backup {} belongs to database '{}'",
+                base.id, base.database
+            )));
+        }
+        backup::write_backup(
+            &self.base_path,
+            &self.config.name,
+            &self.encryption,
+            self.lock_timeout,
+            dest.as_ref(),
+            Some(&base),
+        )
+    }
+
+    /// Restores a backup archive as the database `name`, replacing it if it exists. An
+    /// incremental archive is restored together with its base backups, looked up in the same
+    /// directory. The credentials are the ones of the database when the backup was made. The
+    /// archives are fully verified before the current database is touched
+    pub fn restore(
+        archive: impl AsRef<Path>,
+        name: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<BackupManifest, ChainDBError> {
+        let chain = backup::backup_chain(archive.as_ref(), &DataEncryption::new(password))?;
+        Self::restore_chain(&chain, name, user, password)
+    }
+
+    /// Restores a full backup followed by a chain of incremental backups, each based on the
+    /// previous one, as the database `name`
+    pub fn restore_chain<P: AsRef<Path>>(
+        archives: &[P],
+        name: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<BackupManifest, ChainDBError> {
+        let archives: Vec<PathBuf> = archives
+            .iter()
+            .map(|archive| archive.as_ref().to_path_buf())
+            .collect();
+        let base_path = PathBuf::from(DATA_DIR).join(name);
+        backup::restore_backup(
+            &archives,
+            &base_path,
+            name,
+            user,
+            password,
+            &DataEncryption::new(password),
+            lock::default_timeout(),
+        )
+    }
+
+    /// Reads the manifest of a backup archive made with the given password
+    pub fn backup_manifest(
+        archive: impl AsRef<Path>,
+        password: &str,
+    ) -> Result<BackupManifest, ChainDBError> {
+        backup::read_manifest(archive.as_ref(), &DataEncryption::new(password))
+    }
+
+    /// Backs the database up into its backups directory (`.chain_db_data/.backups/<name>`), then
+    /// deletes the oldest backups beyond `CHAIN_DB_BACKUP_KEEP` (7 by default)
+    pub fn create_backup(&self) -> Result<BackupInfo, ChainDBError> {
+        self.store_backup(None, true)
+    }
+
+    /// Like `create_backup`, but incremental, based on the most recent backup in the backups
+    /// directory. Falls back to a full backup when there is none
+    pub fn create_incremental_backup(&self) -> Result<BackupInfo, ChainDBError> {
+        let dir = backup::backups_dir(&self.config.name);
+        let base = self.list_backups()?.iter().rev().find_map(|info| {
+            backup::read_manifest(&dir.join(&info.file_name), &self.encryption).ok()
+        });
+        self.store_backup(base, true)
+    }
+
+    /// Grava um backup no diretório de backups e, com `prune`, remove os mais antigos
+    fn store_backup(
+        &self,
+        base: Option<BackupManifest>,
+        prune: bool,
+    ) -> Result<BackupInfo, ChainDBError> {
+        let dir = backup::backups_dir(&self.config.name);
+        let kind = match base {
+            Some(_) => BackupKind::Incremental,
+            None => BackupKind::Full,
+        };
+        let file_name = backup::backup_file_name(&self.config.name, kind);
+        backup::write_backup(
+            &self.base_path,
+            &self.config.name,
+            &self.encryption,
+            self.lock_timeout,
+            &dir.join(&file_name),
+            base.as_ref(),
+        )?;
+
+        if prune {
+            let removed = self.prune_backups(backup::backup_keep().max(1))?;
+            if !removed.is_empty() {
+                println!("Backups antigos removidos: {:?}", removed);
             }
         }
 
-        let destination_path = destination.join(file_name);
+        self.list_backups()?
+            .into_iter()
+            .find(|backup| backup.file_name == file_name)
+            .ok_or_else(|| ChainDBError::ValidationError("Backup was pruned".to_string()))
+    }
+
+    /// Backups in the backups directory of the database, oldest first
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>, ChainDBError> {
+        backup::list_backups(&self.config.name)
+    }
+
+    /// Deletes the oldest backups of the database, keeping the `keep` most recent ones and the
+    /// backups they need to be restored
+    pub fn prune_backups(&self, keep: usize) -> Result<Vec<String>, ChainDBError> {
+        backup::prune_backups(&self.config.name, keep, &self.encryption)
+    }
 
-        if path.is_dir() {
-            // Recursivamente copiar subdiretórios
-            copy_dir_all_files(&path, &destination_path)?;
-        } else {
-            // Copiar arquivo
-            fs::copy(&path, &destination_path)?;
+    /// Checks the integrity of every table: each line of the data files must decode, decrypt
+    /// and parse, the metadata must match the data files and doc_ids must be unique. Nothing is
+    /// changed; see `repair`
+    pub fn check(&self) -> Result<CheckReport, ChainDBError> {
+        self.check_tables(false)
+    }
+
+    /// Like `check`, but moves unreadable lines to each table's quarantine file
+    /// (`quarantine.ndjson`), leaving tombstones in their place, and rewrites metadata that
+    /// doesn't match the data files. Duplicate doc_ids are only reported
+    pub fn repair(&self) -> Result<CheckReport, ChainDBError> {
+        self.check_tables(true)
+    }
+
+    fn check_tables(&self, repair: bool) -> Result<CheckReport, ChainDBError> {
+        let mut tables = self.list_tables()?;
+        tables.sort();
+
+        let mut report = CheckReport::default();
+        for table_name in tables {
+            report.tables.push(check::check_table(
+                &self.base_path.join(&table_name),
+                &self.encryption,
+                self.lock_timeout,
+                repair,
+            )?);
         }
+        Ok(report)
     }
 
-    Ok(())
+    /// Nome do banco de dados
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
 }
 
 // Função para calcular o tamanho de um diretório recursivamente
@@ -430,7 +635,7 @@ fn calculate_dir_size(dir: &PathBuf, total_size: &mut u64) -> Result<(), ChainDB
 }
 
 // Função para obter o espaço disponível no sistema de arquivos
-fn get_available_space(path: &PathBuf) -> Result<u64, ChainDBError> {
+pub(crate) fn get_available_space(path: &Path) -> Result<u64, ChainDBError> {
     // Esta é uma implementação simplificada que funciona apenas em sistemas Unix-like
     // Para uma implementação completa, seria necessário usar bibliotecas específicas para cada SO
 
@@ -439,7 +644,7 @@ fn get_available_space(path: &PathBuf) -> Result<u64, ChainDBError> {
         use std::process::Command;
 
         let output = Command::new("df")
-            .args(&["-k", &path.to_string_lossy()])
+            .args(["-k", &path.to_string_lossy()])
             .output()
             .map_err(|e| {
                 ChainDBError::ValidationError(format!("Failed to execute df command: {}", e))
@@ -0,0 +1,298 @@
+//! Integrity check (fsck) of tables.
+//!
+//! Every line of every data file is decoded from base64, decrypted and parsed as JSON, the
+//! table metadata is compared with the data files actually on disk, and doc_ids stored more
+//! than once are reported. A repair moves the unreadable lines to the table's quarantine file,
+//! leaving tombstones in their place so the position of every other record is kept, and
+//! rewrites the metadata from the data files.
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::encryption::DataEncryption;
+use crate::errors::ChainDBError;
+use crate::lock::{FileLock, LockMode};
+use crate::retention::TOMBSTONE_FIELD;
+use crate::table::{data_file_name, TableMetadata, METADATA_FILE, RECORDS_PER_FILE};
+
+/// File of a table that receives the quarantined lines, one JSON object per line with the
+/// original line and where it was found
+pub const QUARANTINE_FILE: &str = "quarantine.ndjson";
+
+/// Result of `ChainDB::check` or `ChainDB::repair`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub tables: Vec<TableReport>,
+}
+
+impl CheckReport {
+    /// `true` when no table has problems
+    pub fn is_ok(&self) -> bool {
+        self.tables.iter().all(TableReport::is_ok)
+    }
+}
+
+/// Result of the check of a table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableReport {
+    pub table: String,
+    pub data_files: usize,
+    /// Lines read successfully, tombstones included
+    pub records: u64,
+    pub bad_lines: Vec<BadLine>,
+    pub duplicate_doc_ids: Vec<DuplicateDocId>,
+    /// Differences between the metadata and the data files
+    pub metadata_issues: Vec<String>,
+    /// Lines moved to the quarantine file by a repair
+    pub quarantined: usize,
+    /// Whether a repair rewrote the metadata
+    pub metadata_rewritten: bool,
+}
+
+impl TableReport {
+    pub fn is_ok(&self) -> bool {
+        self.bad_lines.is_empty()
+            && self.duplicate_doc_ids.is_empty()
+            && self.metadata_issues.is_empty()
+    }
+}
+
+/// A line that can't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BadLine {
+    pub file: String,
+    /// Line number in the file, starting at 1
+    pub line: usize,
+    pub error: String,
+}
+
+/// A doc_id found in more than one record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateDocId {
+    pub doc_id: String,
+    /// Positions of the records in the table (as in `Table::get`)
+    pub records: Vec<u64>,
+}
+
+/// Checks the table stored in `path`, holding its shared lock (exclusive when repairing)
+pub(crate) fn check_table(
+    path: &Path,
+    encryption: &DataEncryption,
+    lock_timeout: Duration,
+    repair: bool,
+) -> Result<TableReport, ChainDBError> {
+    let mode = if repair {
+        LockMode::Exclusive
+    } else {
+        LockMode::Shared
+    };
+    let _lock = FileLock::table(path, mode, lock_timeout)?;
+
+    let mut report = TableReport {
+        table: path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        ..Default::default()
+    };
+
+    let metadata = match TableMetadata::load(path, encryption) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            report
+                .metadata_issues
+                .push(format!("{} can't be read: {}", METADATA_FILE, e));
+            None
+        }
+    };
+
+    let file_indexes = data_file_indexes(path)?;
+    report.data_files = file_indexes.len();
+    let last_file = file_indexes.last().copied().unwrap_or(0);
+    let mut last_file_lines = 0;
+    let mut doc_ids: HashMap<String, Vec<u64>> = HashMap::new();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    for &file_index in &file_indexes {
+        let file_name = data_file_name(file_index);
+        let file_path = path.join(&file_name);
+        let mut lines = read_lines(&file_path)?;
+
+        let mut bad_positions = Vec::new();
+        for (position, line) in lines.iter().enumerate() {
+            match decode_line(line, encryption) {
+                Ok(record_value) => {
+                    report.records += 1;
+                    if let Some(doc_id) = record_value.get("doc_id").and_then(|id| id.as_str()) {
+                        doc_ids
+                            .entry(doc_id.to_string())
+                            .or_default()
+                            .push(file_index as u64 * RECORDS_PER_FILE as u64 + position as u64);
+                    }
+                }
+                Err(error) => {
+                    report.bad_lines.push(BadLine {
+                        file: file_name.clone(),
+                        line: position + 1,
+                        error,
+                    });
+                    bad_positions.push(position);
+                }
+            }
+        }
+
+        if file_index < last_file && lines.len() != RECORDS_PER_FILE {
+            report.metadata_issues.push(format!(
+                "{} has {} records, expected {}",
+                file_name,
+                lines.len(),
+                RECORDS_PER_FILE
+            ));
+        }
+        if file_index == last_file {
+            last_file_lines = lines.len();
+        }
+
+        if repair && !bad_positions.is_empty() {
+            quarantine(
+                path,
+                &file_name,
+                &mut lines,
+                &bad_positions,
+                &now,
+                encryption,
+            )?;
+            report.quarantined += bad_positions.len();
+        }
+    }
+
+    let mut duplicates: Vec<DuplicateDocId> = doc_ids
+        .into_iter()
+        .filter(|(_, records)| records.len() > 1)
+        .map(|(doc_id, records)| DuplicateDocId { doc_id, records })
+        .collect();
+    duplicates.sort_by(|a, b| a.records[0].cmp(&b.records[0]));
+    report.duplicate_doc_ids = duplicates;
+
+    // Posição seguinte ao último registro gravado
+    let total_records = last_file as u64 * RECORDS_PER_FILE as u64 + last_file_lines as u64;
+    if let Some(metadata) = &metadata {
+        if metadata.total_records != total_records {
+            report.metadata_issues.push(format!(
+                "total_records is {}, but the data files hold {}",
+                metadata.total_records, total_records
+            ));
+        }
+        if metadata.current_file != last_file {
+            report.metadata_issues.push(format!(
+                "current_file is {}, but the last data file is {}",
+                metadata.current_file,
+                data_file_name(last_file)
+            ));
+        }
+    }
+
+    let metadata_ok = metadata.as_ref().is_some_and(|metadata| {
+        metadata.total_records == total_records && metadata.current_file == last_file
+    });
+    if repair && !metadata_ok {
+        // Mantém a política de retenção quando os metadados antigos podem ser lidos
+        let mut repaired = metadata.unwrap_or_else(TableMetadata::new);
+        repaired.total_records = total_records;
+        repaired.current_file = last_file;
+        fs::write(path.join(METADATA_FILE), repaired.encrypt(encryption)?)?;
+        report.metadata_rewritten = true;
+    }
+
+    Ok(report)
+}
+
+/// Decodes, decrypts and parses a line, describing the first step that fails
+fn decode_line(line: &str, encryption: &DataEncryption) -> Result<serde_json::Value, String> {
+    let encrypted_data = general_purpose::STANDARD
+        .decode(line)
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    let decrypted_data = encryption
+        .decrypt(&encrypted_data)
+        .map_err(|e| format!("Decryption failed: {}", e))?;
+    serde_json::from_slice(&decrypted_data).map_err(|e| format!("Invalid JSON: {}", e))
+}
+
+/// Appends the bad lines to the quarantine file and replaces them with tombstones
+fn quarantine(
+    path: &Path,
+    file_name: &str,
+    lines: &mut [String],
+    bad_positions: &[usize],
+    now: &str,
+    encryption: &DataEncryption,
+) -> Result<(), ChainDBError> {
+    let mut quarantine = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path.join(QUARANTINE_FILE))?;
+    for &position in bad_positions {
+        let entry = serde_json::json!({
+            "file": file_name,
+            "line": position + 1,
+            "content": lines[position],
+            "quarantined_at": now,
+        });
+        writeln!(quarantine, "{}", entry)?;
+    }
+    quarantine.sync_all()?;
+
+    let tombstone = serde_json::json!({ TOMBSTONE_FIELD: now, "_quarantined": true });
+    let tombstone_line =
+        general_purpose::STANDARD.encode(encryption.encrypt(&serde_json::to_vec(&tombstone)?)?);
+    for &position in bad_positions {
+        lines[position] = tombstone_line.clone();
+    }
+
+    // Grava em um arquivo temporário e substitui, para nunca deixar o arquivo pela metade
+    let file_path = path.join(file_name);
+    let temp_path = file_path.with_extension("cdb.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    for line in lines.iter() {
+        writeln!(writer, "{}", line)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&temp_path, &file_path)?;
+    Ok(())
+}
+
+/// Lines of a data file. A last line without a newline (an interrupted write) is included
+fn read_lines(file_path: &Path) -> Result<Vec<String>, ChainDBError> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(File::open(file_path)?).split(b'\n') {
+        // Bytes que não são UTF-8 viram uma linha inválida em vez de um erro de leitura
+        lines.push(String::from_utf8_lossy(&line?).to_string());
+    }
+    Ok(lines)
+}
+
+/// Indexes of the `data_N.cdb` files of a table, in order
+fn data_file_indexes(path: &Path) -> Result<Vec<u32>, ChainDBError> {
+    let mut indexes = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(index) = file_name
+            .strip_prefix("data_")
+            .and_then(|rest| rest.strip_suffix(".cdb"))
+            .and_then(|index| index.parse::<u32>().ok())
+        {
+            indexes.push(index);
+        }
+    }
+    indexes.sort_unstable();
+    Ok(indexes)
+}
@@ -0,0 +1,664 @@
+//! Typed async client for the HTTP API of a ChainDB server.
+//!
+//! Requests and responses use the models of `api::models`, records are (de)serialized into the
+//! caller's own types, and failures reported by the server are mapped back into
+//! `ChainDBError`, so code using the client handles errors the same way as code using the
+//! library directly.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), chain_db::ChainDBError> {
+//! use chain_db::client::Client;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Order {
+//!     status: String,
+//!     total: f64,
+//! }
+//!
+//! let client = Client::connect("http://localhost:2818", "shop", "admin", "secret").await?;
+//! let orders = client.table::<Order>("orders");
+//! let order = orders.persist(&Order { status: "open".into(), total: 42.0 }).await?;
+//! orders
+//!     .update(&order.doc_id, &Order { status: "paid".into(), total: 42.0 })
+//!     .await?;
+//!
+//! let mut events = orders.subscribe().await?;
+//! while let Some(event) = events.next().await {
+//!     println!("{:?}", event?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::{SinkExt, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::{HeaderValue, AUTHORIZATION, IF_MATCH};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::api::models::{
+    ApiResponse, ConnectDatabaseRequest, CreateDatabaseRequest, FindWhereAdvancedCriteria,
+    FindWhereAdvancedRequest, FindWhereRequest, PersistTableRequest, UpdateTableRequest,
+};
+use crate::errors::ChainDBError;
+use crate::events::{Event, EventType};
+use crate::table::ComparisonOperator;
+
+/// Characters escaped in table names and doc_ids placed in a URL path: everything but the
+/// unreserved characters, and also `.`, so that names such as `..` can't change the path
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'~');
+
+/// System fields added by the server to every record it returns
+const SYSTEM_FIELDS: [&str; 6] = [
+    "doc_id",
+    "revision",
+    "created_at",
+    "updated_at",
+    "created_by",
+    "updated_by",
+];
+
+/// Connection to a database of a ChainDB server. Cloning is cheap and shares the HTTP
+/// connection pool
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// Base URL of the API, ending in `/api/v1`
+    base_url: String,
+    database: String,
+    /// Value of the Authorization header
+    authorization: String,
+}
+
+impl Client {
+    /// Creates a database on the server
+    pub async fn create_database(
+        url: &str,
+        name: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<(), ChainDBError> {
+        let request = CreateDatabaseRequest {
+            name: name.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        let response = reqwest::Client::new()
+            .post(format!("{}/database/create", api_url(url)))
+            .json(&request)
+            .send()
+            .await
+            .map_err(transport_error)?;
+        read_response::<String>(response).await.map(|_| ())
+    }
+
+    /// Connects to a database, checking the credentials with the server
+    pub async fn connect(
+        url: &str,
+        name: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, ChainDBError> {
+        let http = reqwest::Client::new();
+        let base_url = api_url(url);
+        let request = ConnectDatabaseRequest {
+            name: name.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        let response = http
+            .post(format!("{}/database/connect", base_url))
+            .json(&request)
+            .send()
+            .await
+            .map_err(transport_error)?;
+        let auth_token = read_response::<String>(response).await?;
+
+        Ok(Self {
+            http,
+            base_url,
+            database: name.to_string(),
+            authorization: format!("Basic {}", auth_token),
+        })
+    }
+
+    /// Name of the connected database
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    /// Handle to a table whose records are (de)serialized as `T`. The table is created by the
+    /// server on the first write, as with the HTTP API
+    pub fn table<T>(&self, name: &str) -> TableClient<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        TableClient {
+            client: self.clone(),
+            name: name.to_string(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Names of the tables of the database
+    pub async fn tables(&self) -> Result<Vec<String>, ChainDBError> {
+        self.send(Method::GET, "/tables", None::<&()>, None).await
+    }
+
+    /// Subscribes to the persist and update events of every table of the database
+    pub async fn subscribe(&self) -> Result<Subscription<serde_json::Value>, ChainDBError> {
+        Subscription::open(self, None).await
+    }
+
+    /// Sends a request with the database credentials and returns the `data` of the response
+    async fn send<B, R>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        if_match: Option<u64>,
+    ) -> Result<R, ChainDBError>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let response = self.send_request(method, path, body, if_match).await?;
+        response_data(response)
+    }
+
+    /// Like `send`, returning the whole successful response (with `page` and `explain`)
+    async fn send_request<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        if_match: Option<u64>,
+    ) -> Result<ApiResponse<serde_json::Value>, ChainDBError>
+    where
+        B: Serialize + ?Sized,
+    {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path))
+            .header(AUTHORIZATION, &self.authorization);
+        if let Some(revision) = if_match {
+            request = request.header(IF_MATCH, format!("\"{}\"", revision));
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(transport_error)?;
+        read_api_response(response).await
+    }
+}
+
+/// Handle to a table of the connected database, returned by `Client::table`
+#[derive(Debug, Clone)]
+pub struct TableClient<T> {
+    client: Client,
+    name: String,
+    _marker: PhantomData<T>,
+}
+
+impl<T> TableClient<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Persists a new record and returns it as stored, with its doc_id
+    pub async fn persist(&self, record: &T) -> Result<Document<T>, ChainDBError> {
+        let request = PersistTableRequest {
+            data: serde_json::to_value(record)?,
+        };
+        let stored: serde_json::Value = self
+            .client
+            .send(Method::POST, &self.path("/persist"), Some(&request), None)
+            .await?;
+        Document::from_json(stored)
+    }
+
+    /// Replaces the content of a document and returns it as stored
+    pub async fn update(&self, doc_id: &str, record: &T) -> Result<Document<T>, ChainDBError> {
+        self.send_update(doc_id, record, None).await
+    }
+
+    /// Like `update`, but fails with `ChainDBError::RevisionConflict` unless the document is
+    /// still at `revision`
+    pub async fn update_if(
+        &self,
+        doc_id: &str,
+        record: &T,
+        revision: u64,
+    ) -> Result<Document<T>, ChainDBError> {
+        self.send_update(doc_id, record, Some(revision)).await
+    }
+
+    /// Reads a document by doc_id
+    pub async fn get(&self, doc_id: &str) -> Result<Option<Document<T>>, ChainDBError> {
+        let path = self.path(&format!("/doc/{}", encode_segment(doc_id)));
+        match self
+            .client
+            .send::<(), serde_json::Value>(Method::GET, &path, None, None)
+            .await
+        {
+            Ok(stored) => Document::from_json(stored).map(Some),
+            Err(ChainDBError::RecordNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Records whose fields are equal to the values of `criteria`, newest first
+    pub async fn find(
+        &self,
+        criteria: HashMap<String, serde_json::Value>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Document<T>>, ChainDBError> {
+        let request = FindWhereRequest {
+            criteria,
+            limit,
+            reverse: Some(true),
+            sort: Vec::new(),
+            fields: None,
+            offset: None,
+            cursor: None,
+            count_total: false,
+            explain: false,
+        };
+        let records: Vec<serde_json::Value> = self
+            .client
+            .send(Method::POST, &self.path("/find"), Some(&request), None)
+            .await?;
+        records.into_iter().map(Document::from_json).collect()
+    }
+
+    /// Records matching every criterion, newest first. Takes the same criteria as
+    /// `Table::find_where_advanced`
+    pub async fn find_advanced(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Document<T>>, ChainDBError> {
+        let (records, _) = self.send_find_advanced(criteria, limit, false).await?;
+        Ok(records)
+    }
+
+    /// Like `find_advanced`, also returning the number of records matching the criteria,
+    /// regardless of `limit`. Counting makes the server read the whole table
+    pub async fn find_advanced_with_total(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<Document<T>>, usize), ChainDBError> {
+        let (records, total) = self.send_find_advanced(criteria, limit, true).await?;
+        Ok((records, total.unwrap_or_default()))
+    }
+
+    /// The latest `limit` records, newest first
+    pub async fn history(&self, limit: usize) -> Result<Vec<Document<T>>, ChainDBError> {
+        let path = self.path(&format!("/history?limit={}", limit));
+        let records: Vec<serde_json::Value> = self
+            .client
+            .send::<(), _>(Method::GET, &path, None, None)
+            .await?;
+        records.into_iter().map(Document::from_json).collect()
+    }
+
+    /// Subscribes to the persist and update events of this table
+    pub async fn subscribe(&self) -> Result<Subscription<T>, ChainDBError> {
+        Subscription::open(&self.client, Some(self.name.clone())).await
+    }
+
+    async fn send_find_advanced(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+        count_total: bool,
+    ) -> Result<(Vec<Document<T>>, Option<usize>), ChainDBError> {
+        let request = FindWhereAdvancedRequest {
+            criteria: criteria
+                .into_iter()
+                .map(|(field, (operator, value))| FindWhereAdvancedCriteria {
+                    field,
+                    operator,
+                    value,
+                })
+                .collect(),
+            limit,
+            reverse: Some(true),
+            sort: Vec::new(),
+            fields: None,
+            offset: None,
+            cursor: None,
+            count_total,
+            explain: false,
+        };
+        let response = self
+            .client
+            .send_request(
+                Method::POST,
+                &self.path("/find-advanced"),
+                Some(&request),
+                None,
+            )
+            .await?;
+        let total = response.page.as_ref().and_then(|page| page.total);
+        let records: Vec<serde_json::Value> = response_data(response)?;
+        let records = records
+            .into_iter()
+            .map(Document::from_json)
+            .collect::<Result<_, _>>()?;
+        Ok((records, total))
+    }
+
+    async fn send_update(
+        &self,
+        doc_id: &str,
+        record: &T,
+        revision: Option<u64>,
+    ) -> Result<Document<T>, ChainDBError> {
+        let request = UpdateTableRequest {
+            data: serde_json::to_value(record)?,
+            doc_id: doc_id.to_string(),
+        };
+        let stored: serde_json::Value = self
+            .client
+            .send(
+                Method::POST,
+                &self.path("/update"),
+                Some(&request),
+                revision,
+            )
+            .await?;
+        Document::from_json(stored)
+    }
+
+    fn path(&self, route: &str) -> String {
+        format!("/table/{}{}", encode_segment(&self.name), route)
+    }
+}
+
+/// A record returned by the server: the caller's type plus the system fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document<T> {
+    pub doc_id: String,
+    pub revision: Option<u64>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+    pub data: T,
+}
+
+impl<T: DeserializeOwned> Document<T> {
+    /// Splits a record as returned by the API (the fields of the record next to `doc_id` and
+    /// the other system fields) into the system fields and `T`
+    pub fn from_json(value: serde_json::Value) -> Result<Self, ChainDBError> {
+        let serde_json::Value::Object(mut fields) = value else {
+            return Err(ChainDBError::SerializationError(
+                "Expected a JSON object for a record".to_string(),
+            ));
+        };
+        let mut system: HashMap<&str, serde_json::Value> = HashMap::new();
+        for name in SYSTEM_FIELDS {
+            if let Some(value) = fields.remove(name) {
+                system.insert(name, value);
+            }
+        }
+        let text = |name: &str| system.get(name).and_then(|v| v.as_str()).map(String::from);
+
+        Ok(Self {
+            doc_id: text("doc_id").unwrap_or_default(),
+            revision: system.get("revision").and_then(|v| v.as_u64()),
+            created_at: text("created_at"),
+            updated_at: text("updated_at"),
+            created_by: text("created_by"),
+            updated_by: text("updated_by"),
+            data: serde_json::from_value(serde_json::Value::Object(fields))?,
+        })
+    }
+}
+
+impl<T: Serialize> Document<T> {
+    /// The record as returned by the API: the fields of `data` next to the system fields.
+    /// Reverses `from_json`
+    pub fn to_json(&self) -> Result<serde_json::Value, ChainDBError> {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(&self.data)? else {
+            return Err(ChainDBError::SerializationError(
+                "Expected a record serialized as a JSON object".to_string(),
+            ));
+        };
+        fields.insert("doc_id".to_string(), self.doc_id.clone().into());
+        let system = [
+            ("revision", self.revision.map(serde_json::Value::from)),
+            ("created_at", self.created_at.clone().map(Into::into)),
+            ("updated_at", self.updated_at.clone().map(Into::into)),
+            ("created_by", self.created_by.clone().map(Into::into)),
+            ("updated_by", self.updated_by.clone().map(Into::into)),
+        ];
+        for (name, value) in system {
+            if let Some(value) = value {
+                fields.insert(name.to_string(), value);
+            }
+        }
+        Ok(serde_json::Value::Object(fields))
+    }
+}
+
+/// Event received through a `Subscription`, with the record decoded as `T`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableEvent<T> {
+    pub event_type: EventType,
+    pub database: String,
+    pub table: String,
+    /// Content of the persisted or updated record
+    pub data: Option<T>,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// WebSocket subscription to the events of a database, or of one of its tables
+pub struct Subscription<T> {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Only events of this table are returned, when set
+    table: Option<String>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    async fn open(client: &Client, table: Option<String>) -> Result<Self, ChainDBError> {
+        let url = client
+            .base_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        let mut request = format!("{}/events", url)
+            .into_client_request()
+            .map_err(|e| ChainDBError::ServerError(e.to_string()))?;
+        request.headers_mut().insert(
+            AUTHORIZATION.as_str(),
+            HeaderValue::from_str(&client.authorization)
+                .map_err(|e| ChainDBError::InvalidCredentials(e.to_string()))?,
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(|e| ChainDBError::ServerError(e.to_string()))?;
+
+        // A primeira mensagem confirma a conexão
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => {
+                let confirmation: serde_json::Value = serde_json::from_str(&text)?;
+                if confirmation["status"] != "connected" {
+                    return Err(ChainDBError::ServerError(format!(
+                        "Unexpected event subscription response: {}",
+                        text
+                    )));
+                }
+            }
+            Some(Ok(other)) => {
+                return Err(ChainDBError::ServerError(format!(
+                    "Unexpected event subscription response: {:?}",
+                    other
+                )))
+            }
+            Some(Err(e)) => return Err(ChainDBError::ServerError(e.to_string())),
+            None => {
+                return Err(ChainDBError::ServerError(
+                    "Event subscription closed by the server".to_string(),
+                ))
+            }
+        }
+
+        Ok(Self {
+            socket,
+            table,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Waits for the next event. Returns `None` once the server closes the connection
+    pub async fn next(&mut self) -> Option<Result<TableEvent<T>, ChainDBError>> {
+        loop {
+            let text = match self.socket.next().await? {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => return None,
+                // Pings são respondidos pelo próprio tungstenite
+                Ok(_) => continue,
+                Err(e) => return Some(Err(ChainDBError::ServerError(e.to_string()))),
+            };
+            let event: Event = match serde_json::from_str(&text) {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if self
+                .table
+                .as_ref()
+                .is_some_and(|table| *table != event.table)
+            {
+                continue;
+            }
+            let data = match event.data.map(serde_json::from_value).transpose() {
+                Ok(data) => data,
+                Err(e) => return Some(Err(e.into())),
+            };
+            return Some(Ok(TableEvent {
+                event_type: event.event_type,
+                database: event.database,
+                table: event.table,
+                data,
+                timestamp: event.timestamp,
+            }));
+        }
+    }
+
+    /// Closes the connection
+    pub async fn close(mut self) -> Result<(), ChainDBError> {
+        self.socket
+            .close(None)
+            .await
+            .map_err(|e| ChainDBError::ServerError(e.to_string()))?;
+        // Descarta o que ainda estiver em trânsito até o servidor confirmar o fechamento
+        while let Some(Ok(_)) = self.socket.next().await {}
+        let _ = self.socket.flush().await;
+        Ok(())
+    }
+}
+
+/// `<url>/api/v1`, the base URL of the API of the server at `url`
+fn api_url(url: &str) -> String {
+    format!("{}/api/v1", url.trim_end_matches('/'))
+}
+
+/// Percent-encodes `value` to be used as a single segment of a URL path
+fn encode_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+fn transport_error(error: reqwest::Error) -> ChainDBError {
+    ChainDBError::ServerError(error.to_string())
+}
+
+/// Reads an `ApiResponse`, returning its `data` or the error it reports
+async fn read_response<R: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<R, ChainDBError> {
+    response_data(read_api_response(response).await?)
+}
+
+/// The `data` of a successful `ApiResponse`
+fn response_data<R: DeserializeOwned>(
+    response: ApiResponse<serde_json::Value>,
+) -> Result<R, ChainDBError> {
+    Ok(serde_json::from_value(
+        response.data.unwrap_or(serde_json::Value::Null),
+    )?)
+}
+
+/// Reads an `ApiResponse`, returning it when successful or the error it reports
+async fn read_api_response(
+    response: reqwest::Response,
+) -> Result<ApiResponse<serde_json::Value>, ChainDBError> {
+    let status = response.status();
+    let body = response.text().await.map_err(transport_error)?;
+    let response: ApiResponse<serde_json::Value> = match serde_json::from_str(&body) {
+        Ok(response) => response,
+        // Erros dos guards de autenticação não seguem o formato ApiResponse
+        Err(_) if !status.is_success() => {
+            return Err(response_error(status, body.trim().to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if response.success {
+        Ok(response)
+    } else {
+        let message = response
+            .message
+            .unwrap_or_else(|| format!("Request failed with status {}", status));
+        // Só um 404 com ApiResponse vem de um documento ausente; o do Rocket para rotas
+        // inexistentes cai no ramo anterior
+        if status == StatusCode::NOT_FOUND {
+            return Err(ChainDBError::RecordNotFound(message));
+        }
+        Err(response_error(status, message))
+    }
+}
+
+/// Maps a failure reported by the server to the `ChainDBError` the library would have returned
+pub(crate) fn response_error(status: StatusCode, message: String) -> ChainDBError {
+    if status == StatusCode::PRECONDITION_FAILED {
+        if let Some(conflict) = revision_conflict(&message) {
+            return conflict;
+        }
+    }
+    if status == StatusCode::UNAUTHORIZED
+        || message.contains("wrong user/password")
+        || message.contains("wrong Authorization token")
+    {
+        return ChainDBError::InvalidCredentials(message);
+    }
+    ChainDBError::ServerError(message)
+}
+
+/// Parses the message of `ChainDBError::RevisionConflict` back into the error
+fn revision_conflict(message: &str) -> Option<ChainDBError> {
+    let pattern = regex::Regex::new(
+        r"Revision conflict for doc_id (\S+): expected revision (\d+), found (\d+)",
+    )
+    .ok()?;
+    let captures = pattern.captures(message)?;
+    Some(ChainDBError::RevisionConflict {
+        doc_id: captures[1].to_string(),
+        expected: captures[2].parse().ok()?,
+        actual: captures[3].parse().ok()?,
+    })
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -15,4 +17,15 @@ impl Config {
             password: password.to_string(),
         }
     }
+
+    /// Confere usuário e senha em tempo constante. Os valores passam antes pelo SHA-256, para
+    /// que o tempo da comparação não revele nem o tamanho nem o prefixo em comum
+    pub fn matches(&self, user: &str, password: &str) -> bool {
+        let digest = |value: &str| Sha256::digest(value.as_bytes());
+        let user_matches = digest(&self.user).as_slice().ct_eq(digest(user).as_slice());
+        let password_matches = digest(&self.password)
+            .as_slice()
+            .ct_eq(digest(password).as_slice());
+        (user_matches & password_matches).into()
+    }
 }
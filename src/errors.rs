@@ -2,6 +2,9 @@ use base64;
 use serde_json;
 use std::io;
 use std::string::FromUtf8Error;
+use std::time::Duration;
+
+use crate::lock::LockMode;
 
 #[derive(Debug)]
 pub enum ChainDBError {
@@ -15,6 +18,26 @@ pub enum ChainDBError {
     ConfigNotFound(String),
     ValidationError(String),
     RecordNotFound(String),
+    TableNotFound(String),
+    TransactionError(String),
+    PatchError(String),
+    /// Failure reported by (or while talking to) a remote ChainDB server
+    ServerError(String),
+    LockTimeout {
+        resource: String,
+        mode: LockMode,
+        timeout: Duration,
+    },
+    RevisionConflict {
+        doc_id: String,
+        expected: u64,
+        actual: u64,
+    },
+    QueryParseError {
+        message: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 impl From<io::Error> for ChainDBError {
@@ -29,6 +52,12 @@ impl From<serde_json::Error> for ChainDBError {
     }
 }
 
+impl From<csv::Error> for ChainDBError {
+    fn from(error: csv::Error) -> Self {
+        ChainDBError::SerializationError(error.to_string())
+    }
+}
+
 impl From<String> for ChainDBError {
     fn from(error: String) -> Self {
         ChainDBError::EncryptionError(error)
@@ -70,6 +99,39 @@ impl std::fmt::Display for ChainDBError {
             }
             ChainDBError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
             ChainDBError::RecordNotFound(name) => write!(f, "Record not found: {}", name),
+            ChainDBError::TableNotFound(name) => write!(f, "Table not found: {}", name),
+            ChainDBError::TransactionError(msg) => write!(f, "Transaction error: {}", msg),
+            ChainDBError::PatchError(msg) => write!(f, "Patch error: {}", msg),
+            ChainDBError::ServerError(msg) => write!(f, "Server error: {}", msg),
+            ChainDBError::LockTimeout {
+                resource,
+                mode,
+                timeout,
+            } => write!(
+                f,
+                "Could not acquire {} lock on {} within {} ms: it is in use by another process or connection",
+                mode,
+                resource,
+                timeout.as_millis()
+            ),
+            ChainDBError::RevisionConflict {
+                doc_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Revision conflict for doc_id {}: expected revision {}, found {}",
+                doc_id, expected, actual
+            ),
+            ChainDBError::QueryParseError {
+                message,
+                line,
+                column,
+            } => write!(
+                f,
+                "Query parse error at line {}, column {}: {}",
+                line, column, message
+            ),
         }
     }
 }
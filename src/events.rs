@@ -68,6 +68,12 @@ pub struct EventSubscription {
     pub table: Option<String>,
 }
 
+impl Default for EventManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventManager {
     /// Creates a new event manager
     pub fn new() -> Self {
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::query::SortKey;
+
+/// How the records of a table are read to answer a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessPath {
+    /// Every data file is read, decrypted and tested against the criteria.
+    /// Tables have no secondary indexes, so this is currently the only access path
+    FullScan,
+}
+
+/// Order in which the data files (and the records inside them) are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanDirection {
+    /// From the most recent record to the oldest
+    NewestFirst,
+    /// From the oldest record to the most recent
+    OldestFirst,
+}
+
+impl ScanDirection {
+    pub fn from_reverse(reverse: bool) -> Self {
+        if reverse {
+            ScanDirection::NewestFirst
+        } else {
+            ScanDirection::OldestFirst
+        }
+    }
+}
+
+/// The plan chosen to run a query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub access: AccessPath,
+    pub direction: ScanDirection,
+    /// Data files that will be read, in scan order
+    pub files: Vec<String>,
+    /// Number of criteria each record is tested against
+    pub criteria: usize,
+    /// Sort keys applied in memory after the scan (empty when results keep the scan order)
+    pub sort: Vec<SortKey>,
+}
+
+/// Runtime statistics of a single data file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileStats {
+    pub file: String,
+    pub records_decrypted: usize,
+    pub records_matched: usize,
+    pub bytes_read: u64,
+    pub elapsed_ms: f64,
+}
+
+impl FileStats {
+    pub(crate) fn new(file: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            records_decrypted: 0,
+            records_matched: 0,
+            bytes_read: 0,
+            elapsed_ms: 0.0,
+        }
+    }
+}
+
+/// Runtime statistics of a query, with totals and a breakdown per data file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionStats {
+    pub records_decrypted: usize,
+    pub records_matched: usize,
+    pub bytes_read: u64,
+    /// Total time spent on the query, including sorting and projection
+    pub elapsed_ms: f64,
+    /// Files actually read, in scan order
+    pub files: Vec<FileStats>,
+}
+
+impl ExecutionStats {
+    /// Adds the statistics of a data file that has been completely processed
+    pub(crate) fn add_file(&mut self, file: FileStats) {
+        self.records_decrypted += file.records_decrypted;
+        self.records_matched += file.records_matched;
+        self.bytes_read += file.bytes_read;
+        self.files.push(file);
+    }
+}
+
+/// Plan and runtime statistics of a query, returned when `explain` is requested
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explain {
+    pub plan: QueryPlan,
+    pub stats: ExecutionStats,
+}
+
+pub(crate) fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,193 @@
+//! Lazy iteration over the records of a table.
+//!
+//! `TableIter` reads one data file at a time and decrypts one line per call to `next`, so a
+//! table of any size can be processed in constant memory. The shared table lock is held only
+//! while a data file is being read, never between calls, so writers are not blocked by a slow
+//! consumer. Records persisted after the iterator was created are not returned.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream};
+use serde::Deserialize;
+
+use crate::encryption::DataEncryption;
+use crate::errors::ChainDBError;
+use crate::lock::{FileLock, LockMode};
+use crate::record::FieldLayout;
+use crate::retention::Liveness;
+use crate::table::{data_file_name, TableMetadata, RECORDS_PER_FILE};
+
+/// Records decrypted ahead of a `Stream` consumer. The reading task waits when they are not
+/// consumed, which keeps memory bounded
+const STREAM_BUFFER: usize = 64;
+
+/// Boxed iterator of records, as returned by `Table::query_iter`
+pub type RecordIter<T> = Box<dyn Iterator<Item = Result<T, ChainDBError>> + Send>;
+
+/// Iterator over the records of a table, oldest first (`Table::iter`) or newest first
+/// (`Table::iter_rev`)
+pub struct TableIter<T> {
+    path: PathBuf,
+    encryption: DataEncryption,
+    lock_timeout: Duration,
+    liveness: Liveness,
+    reverse: bool,
+    /// Records that existed when the iterator was created
+    total_records: u64,
+    /// Data files not read yet, in reading order
+    files: VecDeque<u32>,
+    /// Lines of the current data file, with their position in the table
+    lines: VecDeque<(u64, String)>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> TableIter<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    pub(crate) fn new(
+        path: PathBuf,
+        encryption: DataEncryption,
+        lock_timeout: Duration,
+        metadata: &TableMetadata,
+        layout: FieldLayout,
+        reverse: bool,
+    ) -> Self {
+        let mut files: VecDeque<u32> = if metadata.total_records == 0 {
+            VecDeque::new()
+        } else {
+            (0..=metadata.current_file).collect()
+        };
+        if reverse {
+            files.make_contiguous().reverse();
+        }
+
+        Self {
+            path,
+            encryption,
+            lock_timeout,
+            liveness: Liveness::new(metadata, layout),
+            reverse,
+            total_records: metadata.total_records,
+            files,
+            lines: VecDeque::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Reads the next data file into `lines`. Returns `false` when there are no files left
+    fn load_next_file(&mut self) -> Result<bool, ChainDBError> {
+        let Some(file_index) = self.files.pop_front() else {
+            return Ok(false);
+        };
+
+        let file_path = self.path.join(data_file_name(file_index));
+        let lines = {
+            let _lock = FileLock::table(&self.path, LockMode::Shared, self.lock_timeout)?;
+            // Arquivos totalmente expirados podem ter sido removidos pela limpeza
+            if !file_path.exists() {
+                return Ok(true);
+            }
+            BufReader::new(File::open(&file_path)?)
+                .lines()
+                .collect::<Result<Vec<String>, _>>()?
+        };
+
+        let first_index = file_index as u64 * RECORDS_PER_FILE as u64;
+        self.lines = lines
+            .into_iter()
+            .enumerate()
+            .map(|(position, line)| (first_index + position as u64, line))
+            // Ignora os registros gravados depois da criação do iterador
+            .filter(|(index, line)| *index < self.total_records && !line.is_empty())
+            .collect();
+        if self.reverse {
+            self.lines.make_contiguous().reverse();
+        }
+
+        Ok(true)
+    }
+
+    fn decode(&self, index: u64, line: &str) -> Result<Option<T>, ChainDBError> {
+        let encrypted_data = general_purpose::STANDARD
+            .decode(line)
+            .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
+        let decrypted_data = self.encryption.decrypt(&encrypted_data)?;
+        let record_value: serde_json::Value = serde_json::from_slice(&decrypted_data)?;
+
+        if !self.liveness.is_live(index, &record_value) {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_value(record_value)?))
+    }
+
+    /// Like `next`, also returning the position of the record in the table
+    pub(crate) fn next_indexed(&mut self) -> Option<Result<(u64, T), ChainDBError>> {
+        loop {
+            let Some((index, line)) = self.lines.pop_front() else {
+                match self.load_next_file() {
+                    Ok(true) => continue,
+                    Ok(false) => return None,
+                    Err(e) => return Some(Err(e)),
+                }
+            };
+
+            match self.decode(index, &line) {
+                Ok(Some(record)) => return Some(Ok((index, record))),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<T> Iterator for TableIter<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    type Item = Result<T, ChainDBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_indexed()
+            .map(|record| record.map(|(_, record)| record))
+    }
+}
+
+impl<T> TableIter<T>
+where
+    T: for<'de> Deserialize<'de> + Send + 'static,
+{
+    /// Turns the iterator into an async `Stream`, see `blocking_stream`
+    pub fn into_stream(self) -> impl Stream<Item = Result<T, ChainDBError>> + Send + 'static {
+        blocking_stream(self)
+    }
+}
+
+/// Runs a blocking iterator (such as a `TableIter`) on Tokio's blocking thread pool and exposes
+/// its items as an async `Stream`. Only a few items are produced ahead of the consumer, and
+/// dropping the stream stops the iteration. Must be called from within a Tokio runtime
+pub fn blocking_stream<I>(iter: I) -> impl Stream<Item = I::Item> + Send + 'static
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER);
+
+    tokio::task::spawn_blocking(move || {
+        for item in iter {
+            // O envio espera enquanto o buffer estiver cheio; falha se o stream foi descartado
+            if futures::executor::block_on(sender.send(item)).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
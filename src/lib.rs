@@ -1,12 +1,34 @@
+pub mod aggregate;
 pub mod api;
+pub mod backup;
 pub mod chaindb;
+pub mod check;
+pub mod client;
 pub mod config;
 pub mod encryption;
 pub mod errors;
 pub mod events;
+pub mod explain;
+pub mod iter;
+pub mod lock;
+pub mod patch;
+pub mod query;
+pub mod record;
+pub mod retention;
+pub mod shell;
+pub mod sql;
 pub mod table;
+pub mod transaction;
+pub mod transfer;
+
+// tests.rs mantém o `mod tests` interno de quando era declarado em main.rs
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests;
 
 pub use chaindb::ChainDB;
 pub use errors::ChainDBError;
 pub use events::{get_event_manager, Event, EventManager, EventSubscription, EventType};
+pub use iter::TableIter;
 pub use table::Table;
+pub use transaction::Transaction;
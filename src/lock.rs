@@ -0,0 +1,143 @@
+//! Advisory file locks that keep several processes (or a server and an embedded `ChainDB`)
+//! from writing the same table at the same time.
+//!
+//! Each table directory has a `.lock` file. Writers hold an exclusive lock on it while they
+//! append records or rewrite files; readers hold a shared lock, so they never see a file that
+//! is being rewritten. The database directory has its own `.lock`, held by transactions while
+//! they write and apply the journal. Locks are taken per operation and released when the
+//! guard is dropped.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::errors::ChainDBError;
+
+pub const LOCK_FILE: &str = ".lock";
+
+/// Environment variable with the default lock wait timeout, in milliseconds
+pub const LOCK_TIMEOUT_ENV: &str = "CHAIN_DB_LOCK_TIMEOUT_MS";
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Kind of lock held on a table or database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Many readers at the same time, no writer
+    Shared,
+    /// A single writer, no readers
+    Exclusive,
+}
+
+impl std::fmt::Display for LockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockMode::Shared => write!(f, "shared"),
+            LockMode::Exclusive => write!(f, "exclusive"),
+        }
+    }
+}
+
+/// Default time to wait for a lock: `CHAIN_DB_LOCK_TIMEOUT_MS` if set, otherwise 5 seconds
+pub fn default_timeout() -> Duration {
+    std::env::var(LOCK_TIMEOUT_ENV)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT)
+}
+
+/// A lock held on a table or database directory. Released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+    mode: LockMode,
+}
+
+impl FileLock {
+    /// Locks the table stored in `table_path`, waiting up to `timeout` for other holders.
+    ///
+    /// Returns `ChainDBError::LockTimeout` if the lock is still held by someone else after
+    /// `timeout`. A zero timeout fails immediately.
+    pub fn table(
+        table_path: &Path,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<Self, ChainDBError> {
+        Self::acquire(
+            table_path,
+            format!("table '{}'", dir_name(table_path)),
+            mode,
+            timeout,
+        )
+    }
+
+    /// Locks the database stored in `db_path`. Same rules as `FileLock::table`
+    pub fn database(
+        db_path: &Path,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<Self, ChainDBError> {
+        Self::acquire(
+            db_path,
+            format!("database '{}'", dir_name(db_path)),
+            mode,
+            timeout,
+        )
+    }
+
+    fn acquire(
+        dir: &Path,
+        resource: String,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<Self, ChainDBError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_FILE))?;
+
+        let started_at = Instant::now();
+        loop {
+            let result = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+
+            match result {
+                Ok(()) => return Ok(Self { file, mode }),
+                Err(TryLockError::WouldBlock) if started_at.elapsed() < timeout => {
+                    std::thread::sleep(
+                        RETRY_INTERVAL.min(timeout.saturating_sub(started_at.elapsed())),
+                    );
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(ChainDBError::LockTimeout {
+                        resource,
+                        mode,
+                        timeout,
+                    })
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
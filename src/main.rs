@@ -1,23 +1,19 @@
+use chain_db::api;
+use rocket::data::{Limits, ToByteUnit};
 use std::error::Error;
 
-mod api;
-mod chaindb;
-mod config;
-mod encryption;
-mod errors;
-mod events;
-mod table;
-
-#[cfg(test)]
-mod tests;
-
 #[rocket::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let figment = rocket::Config::figment()
         .merge(("port", 2818))
         .merge(("address", "0.0.0.0"))
+        // Lotes de /persist-batch e /update-batch podem ser bem maiores que o limite padrão
+        .merge(("limits", Limits::default().limit("json", 32.mebibytes())))
         .merge(("log_level", rocket::config::LogLevel::Debug));
 
+    // Remove periodicamente os documentos expirados das tabelas abertas
+    api::registry::spawn_sweeper();
+
     let _rocket = rocket::custom(figment)
         .attach(api::CORS)
         .mount("/api/v1", api::routes())
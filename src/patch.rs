@@ -0,0 +1,61 @@
+//! Partial updates applied server-side, so clients don't need to fetch and resend the whole
+//! record to change one field.
+//!
+//! Two formats are supported:
+//!
+//! * RFC 7396 merge patch: an object whose fields replace the document's fields, `null`
+//!   removing them, e.g. `{"status": "paid", "note": null}`
+//! * RFC 6902 JSON Patch: a list of operations (`add`, `remove`, `replace`, `move`, `copy`,
+//!   `test`), e.g. `[{"op": "replace", "path": "/status", "value": "paid"}]`
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ChainDBError;
+
+pub use json_patch::PatchOperation;
+
+/// A partial update of a document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Patch {
+    /// RFC 6902 JSON Patch operations, applied in order and all-or-nothing
+    JsonPatch(Vec<PatchOperation>),
+    /// RFC 7396 merge patch
+    Merge(serde_json::Value),
+}
+
+impl Patch {
+    /// Parses a RFC 6902 JSON Patch document (a list of operations)
+    pub fn json_patch(value: serde_json::Value) -> Result<Self, ChainDBError> {
+        serde_json::from_value(value)
+            .map(Patch::JsonPatch)
+            .map_err(|e| ChainDBError::PatchError(format!("Invalid JSON Patch: {}", e)))
+    }
+
+    /// Builds a RFC 7396 merge patch
+    pub fn merge(value: serde_json::Value) -> Self {
+        Patch::Merge(value)
+    }
+
+    /// Interprets `value` as a JSON Patch if it is an array, and as a merge patch otherwise
+    pub fn detect(value: serde_json::Value) -> Result<Self, ChainDBError> {
+        if value.is_array() {
+            Self::json_patch(value)
+        } else {
+            Ok(Self::merge(value))
+        }
+    }
+
+    /// Applies the patch to `document`. If an operation fails (e.g. a `test` that doesn't
+    /// match, or a path that doesn't exist), `document` is left unchanged
+    pub fn apply(&self, document: &mut serde_json::Value) -> Result<(), ChainDBError> {
+        match self {
+            Patch::JsonPatch(operations) => json_patch::patch(document, operations)
+                .map_err(|e| ChainDBError::PatchError(e.to_string())),
+            Patch::Merge(patch) => {
+                json_patch::merge(document, patch);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use crate::backup::sha256_hex;
+use crate::errors::ChainDBError;
+use crate::explain::Explain;
+use crate::record::FieldLayout;
+use crate::table::{compare_ordering, ComparisonOperator};
+
+/// Direction used when sorting query results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortDirection {
+    /// Ascending order (smallest first)
+    #[default]
+    #[serde(alias = "asc")]
+    Asc,
+    /// Descending order (largest first)
+    #[serde(alias = "desc")]
+    Desc,
+}
+
+/// A single sort key. Multiple keys are applied in order, the first one having precedence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    /// Field to sort by (searched in the root object first, then in the record's fields)
+    pub field: String,
+    /// Sort direction (default: `Asc`)
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+impl SortKey {
+    pub fn asc(field: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            direction: SortDirection::Asc,
+        }
+    }
+
+    pub fn desc(field: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            direction: SortDirection::Desc,
+        }
+    }
+
+    /// Parses a comma separated list of sort keys, e.g. `total:desc,name`
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, ChainDBError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = ChainDBError;
+
+    /// Parses `field`, `field:asc` or `field:desc`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (field, direction) = match value.rsplit_once(':') {
+            Some((field, direction)) => (field, direction),
+            None => (value, "asc"),
+        };
+
+        if field.is_empty() {
+            return Err(ChainDBError::ValidationError(format!(
+                "Invalid sort key '{}'",
+                value
+            )));
+        }
+
+        match direction.to_lowercase().as_str() {
+            "asc" => Ok(Self::asc(field)),
+            "desc" => Ok(Self::desc(field)),
+            _ => Err(ChainDBError::ValidationError(format!(
+                "Invalid sort direction '{}' for field '{}'. Expected 'asc' or 'desc'",
+                direction, field
+            ))),
+        }
+    }
+}
+
+/// Options controlling ordering, projection and pagination of a query
+#[derive(Debug, Clone)]
+pub struct QueryOptions {
+    /// Sort keys. When empty, records are returned in scan order (see `reverse`)
+    pub sort: Vec<SortKey>,
+    /// Fields to keep in each record. `doc_id` is always kept. `None` returns whole records
+    pub fields: Option<Vec<String>>,
+    /// Maximum number of records in the page. `None` returns every remaining record
+    pub limit: Option<usize>,
+    /// Number of matching records to skip. Ignored when `cursor` is set
+    pub offset: Option<usize>,
+    /// Opaque cursor returned as `next_cursor` by a previous page of the same query
+    pub cursor: Option<String>,
+    /// Scan from the most recent record to the oldest (default: true)
+    pub reverse: bool,
+    /// Count every matching record in `QueryPage::total`. Requires reading the whole table
+    pub count_total: bool,
+    /// Return the query plan and execution statistics in `QueryPage::explain`
+    pub explain: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            sort: Vec::new(),
+            fields: None,
+            limit: None,
+            offset: None,
+            cursor: None,
+            reverse: true,
+            count_total: false,
+            explain: false,
+        }
+    }
+}
+
+/// A page of query results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryPage<T> {
+    /// Records of the requested page
+    pub records: Vec<T>,
+    /// Total number of records matching the criteria, regardless of pagination. Only present
+    /// when `QueryOptions::count_total` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// Cursor for the next page, or `None` when this is the last page
+    pub next_cursor: Option<String>,
+    /// Plan and execution statistics, only present when `QueryOptions::explain` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<Explain>,
+}
+
+/// Sort key value of the last record of a page. `Missing` and `Null` sort differently, so they
+/// are kept apart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum CursorKey {
+    Missing,
+    Value(serde_json::Value),
+}
+
+/// Position of the last record of a page (keyset pagination): its sort key values and its
+/// position in the table, which breaks ties between records with equal keys. Records written
+/// or removed between two pages do not shift the next page, and the cursor is bound to the
+/// query that produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    keys: Vec<CursorKey>,
+    index: u64,
+    query: String,
+}
+
+impl Cursor {
+    /// Cursor positioned right after `record`, found at `index` by the query `query`
+    /// (see `query_hash`)
+    pub(crate) fn after(
+        record: &serde_json::Value,
+        index: u64,
+        layout: FieldLayout,
+        sort: &[SortKey],
+        query: &str,
+    ) -> Self {
+        let keys = sort_values(record, layout, sort)
+            .into_iter()
+            .map(|value| match value {
+                Some(value) => CursorKey::Value(value.clone()),
+                None => CursorKey::Missing,
+            })
+            .collect();
+
+        Self {
+            keys,
+            index,
+            query: query.to_string(),
+        }
+    }
+
+    /// Encodes the cursor as an opaque URL safe string
+    pub fn encode(&self) -> String {
+        let cursor = serde_json::to_vec(self).unwrap_or_default();
+        general_purpose::URL_SAFE_NO_PAD.encode(cursor)
+    }
+
+    /// Decodes a cursor produced by `encode`, rejecting cursors produced by a different query
+    pub fn decode(cursor: &str, query: &str) -> Result<Self, ChainDBError> {
+        let cursor = general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Cursor>(&bytes).ok())
+            .ok_or_else(|| ChainDBError::ValidationError("Invalid cursor".to_string()))?;
+
+        if cursor.query != query {
+            return Err(ChainDBError::ValidationError(
+                "Cursor does not match the query".to_string(),
+            ));
+        }
+        Ok(cursor)
+    }
+
+    /// Whether `record`, found at `index`, comes after the cursor in the order of the query
+    pub(crate) fn follows(
+        &self,
+        record: &serde_json::Value,
+        index: u64,
+        layout: FieldLayout,
+        sort: &[SortKey],
+        reverse: bool,
+    ) -> bool {
+        let values = sort_values(record, layout, sort);
+        for ((key, value), cursor_key) in sort.iter().zip(values).zip(&self.keys) {
+            let cursor_value = match cursor_key {
+                CursorKey::Value(value) => Some(value),
+                CursorKey::Missing => None,
+            };
+            let ordering = match key.direction {
+                SortDirection::Asc => sort_ordering(value, cursor_value),
+                SortDirection::Desc => sort_ordering(cursor_value, value),
+            };
+            if ordering != Ordering::Equal {
+                return ordering == Ordering::Greater;
+            }
+        }
+
+        // Chaves iguais mantêm a ordem de leitura
+        if reverse {
+            index < self.index
+        } else {
+            index > self.index
+        }
+    }
+
+    /// Position in the table of the record the cursor was created from
+    pub(crate) fn index(&self) -> u64 {
+        self.index
+    }
+}
+
+/// Short hash identifying a query by its criteria, sort and scan direction, used to bind
+/// cursors to the query that produced them. Projection and page size may change between pages
+pub fn query_hash(
+    criteria: &HashMap<String, (ComparisonOperator, serde_json::Value)>,
+    options: &QueryOptions,
+) -> String {
+    // BTreeMap para que a ordem dos critérios não altere o hash
+    let criteria: BTreeMap<_, _> = criteria.iter().collect();
+    let query = serde_json::json!({
+        "criteria": criteria,
+        "sort": options.sort,
+        "reverse": options.reverse,
+    });
+
+    sha256_hex(query.to_string().as_bytes())[..16].to_string()
+}
+
+/// Values of the sort keys of a record, resolved through `layout`
+fn sort_values<'a>(
+    record: &'a serde_json::Value,
+    layout: FieldLayout,
+    sort: &[SortKey],
+) -> Vec<Option<&'a serde_json::Value>> {
+    sort.iter()
+        .map(|key| {
+            record
+                .as_object()
+                .and_then(|obj| layout.resolve(obj, &key.field))
+        })
+        .collect()
+}
+
+/// Compares two records by the given sort keys
+fn compare_records(
+    a: &serde_json::Value,
+    b: &serde_json::Value,
+    layout: FieldLayout,
+    sort: &[SortKey],
+) -> Ordering {
+    for key in sort {
+        let a_value = a
+            .as_object()
+            .and_then(|obj| layout.resolve(obj, &key.field));
+        let b_value = b
+            .as_object()
+            .and_then(|obj| layout.resolve(obj, &key.field));
+
+        let ordering = match key.direction {
+            SortDirection::Asc => sort_ordering(a_value, b_value),
+            SortDirection::Desc => sort_ordering(b_value, a_value),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Sorts records by the given keys, resolved through `layout`. The sort is stable, so records
+/// with equal keys keep their scan order
+pub fn sort_records(records: &mut [serde_json::Value], layout: FieldLayout, sort: &[SortKey]) {
+    if sort.is_empty() {
+        return;
+    }
+
+    records.sort_by(|a, b| compare_records(a, b, layout, sort));
+}
+
+/// Like `sort_records`, for records paired with their position in the table
+pub(crate) fn sort_indexed(
+    records: &mut [(u64, serde_json::Value)],
+    layout: FieldLayout,
+    sort: &[SortKey],
+) {
+    if sort.is_empty() {
+        return;
+    }
+
+    records.sort_by(|(_, a), (_, b)| compare_records(a, b, layout, sort));
+}
+
+/// Total order used for sorting: missing < null < bool < number < string < array < object.
+/// Values of the same type are compared with the same rules as the comparison operators
+fn sort_ordering(a: Option<&serde_json::Value>, b: Option<&serde_json::Value>) -> Ordering {
+    fn rank(value: Option<&serde_json::Value>) -> u8 {
+        match value {
+            None => 0,
+            Some(serde_json::Value::Null) => 1,
+            Some(serde_json::Value::Bool(_)) => 2,
+            Some(serde_json::Value::Number(_)) => 3,
+            Some(serde_json::Value::String(_)) => 4,
+            Some(serde_json::Value::Array(_)) => 5,
+            Some(serde_json::Value::Object(_)) => 6,
+        }
+    }
+
+    match (a, b) {
+        (Some(serde_json::Value::Bool(a)), Some(serde_json::Value::Bool(b))) => a.cmp(b),
+        (Some(a_value), Some(b_value)) if rank(a) == rank(b) => {
+            compare_ordering(a_value, b_value).unwrap_or(Ordering::Equal)
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Keeps only the requested fields of a record, preserving its shape: root fields stay in
+/// the root object and, with a nested layout, fields of the nested object (`data` for
+/// `TableData`) stay inside it. `doc_id` is always kept
+pub fn project_record(
+    record: serde_json::Value,
+    layout: FieldLayout,
+    fields: &[String],
+) -> serde_json::Value {
+    let serde_json::Value::Object(mut record_obj) = record else {
+        return record;
+    };
+    let nested_key = match layout {
+        FieldLayout::Root => None,
+        FieldLayout::Nested(key) => Some(key),
+    };
+
+    let data = match nested_key.and_then(|key| record_obj.remove(key)) {
+        Some(serde_json::Value::Object(data_obj)) => Some(
+            data_obj
+                .into_iter()
+                .filter(|(key, _)| fields.contains(key))
+                .collect::<serde_json::Map<_, _>>(),
+        ),
+        Some(other) => {
+            record_obj.insert(nested_key.unwrap_or_default().to_string(), other);
+            None
+        }
+        None => None,
+    };
+
+    let mut projected: serde_json::Map<String, serde_json::Value> = record_obj
+        .into_iter()
+        .filter(|(key, _)| {
+            key == "doc_id" || Some(key.as_str()) == nested_key || fields.contains(key)
+        })
+        .collect();
+
+    if let (Some(key), Some(data)) = (nested_key, data) {
+        projected.insert(key.to_string(), serde_json::Value::Object(data));
+    }
+
+    serde_json::Value::Object(projected)
+}
@@ -0,0 +1,176 @@
+//! Records stored in tables.
+//!
+//! Every stored document has the system fields (`doc_id`, `revision`, `created_at`, ...) at its
+//! root. Where the record's own fields are depends on its type, described by
+//! [`Record::LAYOUT`]: plain structs are stored flat, next to the system fields, while
+//! `TableData` keeps them in a `data` object. Queries, sorting, projections, aggregations,
+//! retention and exports resolve field names through the layout, so any `Serialize +
+//! Deserialize` struct can be stored without mimicking `TableData`.
+//!
+//! ```
+//! use chain_db::record::Record;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Order {
+//!     status: String,
+//!     total: f64,
+//! }
+//!
+//! impl Record for Order {}
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::models::TableData;
+use crate::table::SYSTEM_FIELDS;
+
+/// Identifier of a stored document (a UUID v4 generated on persist)
+pub type DocId = String;
+
+/// Where the fields of a record are in its stored document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldLayout {
+    /// At the root, next to the system fields
+    Root,
+    /// Inside the object stored under this key. Fields are looked up at the root first, so
+    /// the system fields stay reachable
+    Nested(&'static str),
+}
+
+/// Layout of `TableData` and of schemaless `serde_json::Value` records, and of transaction
+/// operations built by hand
+impl Default for FieldLayout {
+    fn default() -> Self {
+        FieldLayout::Nested("data")
+    }
+}
+
+impl FieldLayout {
+    /// The object holding the record's fields
+    pub fn fields<'a>(
+        &self,
+        document: &'a serde_json::Value,
+    ) -> Option<&'a serde_json::Map<String, serde_json::Value>> {
+        match self {
+            FieldLayout::Root => document.as_object(),
+            FieldLayout::Nested(key) => document.get(*key)?.as_object(),
+        }
+    }
+
+    pub fn fields_mut<'a>(
+        &self,
+        document: &'a mut serde_json::Value,
+    ) -> Option<&'a mut serde_json::Map<String, serde_json::Value>> {
+        match self {
+            FieldLayout::Root => document.as_object_mut(),
+            FieldLayout::Nested(key) => document.get_mut(*key)?.as_object_mut(),
+        }
+    }
+
+    /// Payload of the persist and update events: the record's fields, or the whole document
+    /// when they are missing
+    pub(crate) fn event_data(&self, document: serde_json::Value) -> serde_json::Value {
+        match self {
+            FieldLayout::Root => document,
+            FieldLayout::Nested(key) => match document.get(*key) {
+                Some(fields) => fields.clone(),
+                None => document,
+            },
+        }
+    }
+
+    /// Locates a field of a stored document: at the root (where the system fields are) or, if
+    /// it isn't there, in the record's fields.
+    ///
+    /// Dot-separated paths (e.g. `customer.address.city`) walk nested objects when no field
+    /// has the exact name.
+    pub fn resolve<'a>(
+        &self,
+        document_obj: &'a serde_json::Map<String, serde_json::Value>,
+        field: &str,
+    ) -> Option<&'a serde_json::Value> {
+        let nested = match self {
+            FieldLayout::Root => None,
+            FieldLayout::Nested(key) => match document_obj.get(*key) {
+                Some(serde_json::Value::Object(nested)) => Some(nested),
+                _ => None,
+            },
+        };
+
+        if let Some(value) = document_obj
+            .get(field)
+            .or_else(|| nested.and_then(|nested| nested.get(field)))
+        {
+            return Some(value);
+        }
+
+        if !field.contains('.') {
+            return None;
+        }
+
+        resolve_path(document_obj, field)
+            .or_else(|| nested.and_then(|nested| resolve_path(nested, field)))
+    }
+
+    /// Removes the system fields from the record's fields, so only the system sets them
+    pub(crate) fn strip_system_fields(&self, document: &mut serde_json::Value) {
+        if let FieldLayout::Nested(_) = self {
+            if let Some(fields) = self.fields_mut(document) {
+                for field in SYSTEM_FIELDS {
+                    fields.remove(*field);
+                }
+            }
+        }
+    }
+}
+
+/// Walks nested objects following a dot-separated path
+fn resolve_path<'a>(
+    obj: &'a serde_json::Map<String, serde_json::Value>,
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    let mut segments = path.split('.');
+    let mut current = obj.get(segments.next()?)?;
+
+    for segment in segments {
+        current = current.as_object()?.get(segment)?;
+    }
+
+    Some(current)
+}
+
+/// A type that can be stored in a `Table`.
+///
+/// The defaults fit plain structs: their fields are stored at the root of the document and
+/// the record doesn't carry its doc_id. Fields named like a system field (`doc_id`,
+/// `revision`, `created_at`, `updated_at`, `created_by`, `updated_by`) are filled in by the
+/// table when the record is read back and overwritten when it is written.
+pub trait Record: Serialize + for<'de> Deserialize<'de> {
+    /// Where the fields of the record are in the stored document
+    const LAYOUT: FieldLayout = FieldLayout::Root;
+
+    /// The doc_id carried by the record, if its type keeps one. `Table::save` updates the
+    /// document with this id instead of persisting a new one
+    fn doc_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Record for TableData {
+    const LAYOUT: FieldLayout = FieldLayout::Nested("data");
+
+    fn doc_id(&self) -> Option<&str> {
+        self.doc_id.as_deref()
+    }
+}
+
+/// Schemaless documents. Fields are looked up at the root and then in `data`, so documents
+/// written as `TableData` can be read as plain JSON
+impl Record for serde_json::Value {
+    const LAYOUT: FieldLayout = FieldLayout::Nested("data");
+
+    fn doc_id(&self) -> Option<&str> {
+        self.get("doc_id").and_then(|doc_id| doc_id.as_str())
+    }
+}
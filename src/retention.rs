@@ -0,0 +1,163 @@
+//! Expiration of documents: per-table retention policies and per-document TTL fields.
+//!
+//! Expired documents are hidden from reads as soon as they expire. `Table::sweep` later
+//! replaces them with small tombstone lines (keeping the position of every other record in its
+//! data file) and deletes data files whose records have all expired.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ChainDBError;
+use crate::record::FieldLayout;
+use crate::table::{TableMetadata, RECORDS_PER_FILE};
+
+/// Field present only in tombstones, with the time the document was swept
+pub(crate) const TOMBSTONE_FIELD: &str = "_expired_at";
+
+/// Environment variable with the interval between background sweeps, in seconds
+pub const SWEEP_INTERVAL_ENV: &str = "CHAIN_DB_SWEEP_INTERVAL_SECS";
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// When the documents of a table expire. Every rule is optional; a document expires as soon
+/// as any of them says so
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Seconds after its last write (`updated_at`) after which a document expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Number of most recently persisted documents to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_records: Option<u64>,
+    /// Field holding each document's expiration time, as an RFC 3339 string or Unix seconds.
+    /// Documents without the field don't expire through it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_field: Option<String>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.max_age_secs.is_none() && self.max_records.is_none() && self.ttl_field.is_none()
+    }
+
+    /// Rejects a `max_age_secs` too large to be represented as a duration
+    pub fn validate(&self) -> Result<(), ChainDBError> {
+        match self.max_age_secs {
+            Some(secs) if max_age(secs).is_none() => Err(ChainDBError::ValidationError(format!(
+                "max_age_secs must be at most {}",
+                chrono::Duration::MAX.num_seconds()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `max_age_secs` as a duration, if it fits in one
+fn max_age(secs: u64) -> Option<chrono::Duration> {
+    i64::try_from(secs)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+}
+
+/// Outcome of `Table::sweep`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweepStats {
+    /// Documents replaced by tombstones
+    pub expired: usize,
+    /// Data files deleted because all their records had expired
+    pub files_dropped: usize,
+}
+
+/// Interval between background sweeps: `CHAIN_DB_SWEEP_INTERVAL_SECS` if set, otherwise 60
+pub fn sweep_interval() -> std::time::Duration {
+    let secs = std::env::var(SWEEP_INTERVAL_ENV)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+    std::time::Duration::from_secs(secs)
+}
+
+/// Decides which stored records are still visible, at a fixed point in time
+#[derive(Debug, Clone)]
+pub(crate) struct Liveness {
+    now: DateTime<Utc>,
+    max_age: Option<chrono::Duration>,
+    ttl_field: Option<String>,
+    /// Where `ttl_field` is in the stored documents
+    layout: FieldLayout,
+    /// Records stored before this position fall outside `max_records`
+    first_live_index: u64,
+}
+
+impl Liveness {
+    pub(crate) fn new(metadata: &TableMetadata, layout: FieldLayout) -> Self {
+        let policy = metadata.retention.clone().unwrap_or_default();
+
+        Self {
+            now: Utc::now(),
+            // Políticas gravadas antes da validação podem ter um max_age fora do intervalo,
+            // que equivale a nunca expirar
+            max_age: policy.max_age_secs.and_then(max_age),
+            ttl_field: policy.ttl_field,
+            layout,
+            first_live_index: policy
+                .max_records
+                .map(|max_records| metadata.total_records.saturating_sub(max_records))
+                .unwrap_or(0),
+        }
+    }
+
+    /// Position of a record in the table, from its data file and line
+    pub(crate) fn index(file_index: u32, line: usize) -> u64 {
+        file_index as u64 * RECORDS_PER_FILE as u64 + line as u64
+    }
+
+    /// `false` for tombstones and expired documents
+    pub(crate) fn is_live(&self, index: u64, record: &serde_json::Value) -> bool {
+        let Some(record_obj) = record.as_object() else {
+            return true;
+        };
+
+        if index < self.first_live_index || record_obj.contains_key(TOMBSTONE_FIELD) {
+            return false;
+        }
+
+        if let Some(max_age) = self.max_age {
+            let updated_at = record_obj.get("updated_at").and_then(parse_time);
+            // Uma data de expiração além do maior DateTime nunca é atingida
+            let expires_at = updated_at.and_then(|time| time.checked_add_signed(max_age));
+            if matches!(expires_at, Some(expires_at) if expires_at <= self.now) {
+                return false;
+            }
+        }
+
+        if let Some(ttl_field) = &self.ttl_field {
+            let expires_at = self
+                .layout
+                .resolve(record_obj, ttl_field)
+                .and_then(parse_time);
+            if matches!(expires_at, Some(expires_at) if expires_at <= self.now) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Tombstone line content replacing an expired record
+    pub(crate) fn tombstone(&self) -> serde_json::Value {
+        serde_json::json!({ TOMBSTONE_FIELD: self.now.to_rfc3339() })
+    }
+}
+
+/// Reads an RFC 3339 string or a number of Unix seconds
+fn parse_time(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    match value {
+        serde_json::Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        serde_json::Value::Number(secs) => {
+            DateTime::from_timestamp(secs.as_f64()?.floor() as i64, 0)
+        }
+        _ => None,
+    }
+}
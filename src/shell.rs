@@ -0,0 +1,418 @@
+//! Commands of the interactive shell (`chain-db-shell`).
+//!
+//! A line is parsed into a [`ShellCommand`]; the `find` conditions become the same
+//! `(ComparisonOperator, value)` criteria taken by `Table::find_where_advanced`, so the shell
+//! behaves exactly like the library and the `find-advanced` route.
+
+use std::collections::HashMap;
+
+use crate::errors::ChainDBError;
+use crate::table::ComparisonOperator;
+
+/// Records shown by `history <table>` when no limit is given
+pub const DEFAULT_HISTORY_LIMIT: usize = 10;
+
+/// Help text printed by the `help` command
+pub const HELP: &str = "\
+Commands:
+  use <db> [<user> <password>]        select a database
+  dbs                                 list the databases (local mode only)
+  tables                              list the tables of the database
+  find <table> [where <cond> [and <cond> ...]] [limit <n>]
+  get <table> <doc_id>                read a document by doc_id
+  history <table> [<n>]               latest records of a table
+  persist <table> <json>              append a record, e.g. persist orders {\"status\": \"paid\"}
+  help                                show this help
+  exit | quit                         leave the shell
+
+Conditions are `<field> <operator> <value>`. Operators: = != > >= < <= contains starts_with
+ends_with icontains istarts_with iends_with ieq in, not in, between, matches, exists,
+not exists, is null. Values are JSON (numbers, true, [1, 2]) or text, quoted when it has spaces.";
+
+/// A parsed shell command
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellCommand {
+    Use {
+        db: String,
+        /// User and password, when given on the command line
+        credentials: Option<(String, String)>,
+    },
+    Databases,
+    Tables,
+    Find {
+        table: String,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    },
+    Get {
+        table: String,
+        doc_id: String,
+    },
+    History {
+        table: String,
+        limit: usize,
+    },
+    Persist {
+        table: String,
+        data: serde_json::Value,
+    },
+    Help,
+    Exit,
+}
+
+/// A word of a command line and the column (starting at 1) where it starts
+#[derive(Debug, Clone, PartialEq)]
+struct Word {
+    text: String,
+    /// Whether the word was written between quotes (it is then always text)
+    quoted: bool,
+    column: usize,
+}
+
+/// Parses a command line. Returns `None` for an empty line or a comment (`#`)
+pub fn parse_command(line: &str) -> Result<Option<ShellCommand>, ChainDBError> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+
+    // `persist` recebe o resto da linha como JSON, sem passar pelo tokenizador
+    let (keyword, rest) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+    if keyword.eq_ignore_ascii_case("persist") {
+        return parse_persist(line, rest.trim_start()).map(Some);
+    }
+
+    let words = tokenize(line)?;
+    let mut words = Words::new(line, words);
+    let keyword = words.next_word("a command")?;
+
+    let command = match keyword.text.to_ascii_lowercase().as_str() {
+        "use" => {
+            let db = words.next_word("a database name")?.text;
+            let credentials = match words.next() {
+                Some(user) => Some((user.text, words.next_word("a password")?.text)),
+                None => None,
+            };
+            ShellCommand::Use { db, credentials }
+        }
+        "dbs" | "databases" => ShellCommand::Databases,
+        "tables" => ShellCommand::Tables,
+        "find" => parse_find(&mut words)?,
+        "get" => ShellCommand::Get {
+            table: words.next_word("a table name")?.text,
+            doc_id: words.next_word("a doc_id")?.text,
+        },
+        "history" => {
+            let table = words.next_word("a table name")?.text;
+            let limit = match words.next() {
+                Some(word) => words.number(&word)?,
+                None => DEFAULT_HISTORY_LIMIT,
+            };
+            ShellCommand::History { table, limit }
+        }
+        "help" | "?" => ShellCommand::Help,
+        "exit" | "quit" => ShellCommand::Exit,
+        _ => {
+            return Err(parse_error(
+                format!("Unknown command '{}', try 'help'", keyword.text),
+                keyword.column,
+            ))
+        }
+    };
+
+    if let Some(extra) = words.next() {
+        return Err(parse_error(
+            format!("Unexpected '{}'", extra.text),
+            extra.column,
+        ));
+    }
+    Ok(Some(command))
+}
+
+/// Whether a command line may be saved in the history file. `use` lines with more than the
+/// database name carry a password, so they are left out even when they don't parse
+pub fn keep_in_history(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some(keyword) if keyword.eq_ignore_ascii_case("use") => words.count() <= 1,
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// `find <table> [where <cond> [and <cond> ...]] [limit <n>]`
+fn parse_find(words: &mut Words) -> Result<ShellCommand, ChainDBError> {
+    let table = words.next_word("a table name")?.text;
+    let mut criteria = HashMap::new();
+    let mut limit = None;
+
+    if words.accept("where") {
+        loop {
+            let field = words.next_word("a field name")?;
+            let (operator, value) = parse_condition(words, &field)?;
+            operator.validate(&field.text, &value)?;
+            // Os critérios são indexados pelo campo, então cada campo só pode aparecer uma vez
+            if criteria.contains_key(&field.text) {
+                return Err(parse_error(
+                    format!(
+                        "Field '{}' is used twice, use 'between [min, max]' for a range",
+                        field.text
+                    ),
+                    field.column,
+                ));
+            }
+            criteria.insert(field.text, (operator, value));
+            if !words.accept("and") {
+                break;
+            }
+        }
+    }
+
+    if words.accept("limit") {
+        let word = words.next_word("a limit")?;
+        limit = Some(words.number(&word)?);
+    }
+
+    Ok(ShellCommand::Find {
+        table,
+        criteria,
+        limit,
+    })
+}
+
+/// `<operator> [<value>]`, after the field of a condition
+fn parse_condition(
+    words: &mut Words,
+    field: &Word,
+) -> Result<(ComparisonOperator, serde_json::Value), ChainDBError> {
+    let operator_word = words.next_word(&format!("an operator after '{}'", field.text))?;
+    let operator = match operator_word.text.to_ascii_lowercase().as_str() {
+        "=" | "==" => ComparisonOperator::Eq,
+        "!=" | "<>" => ComparisonOperator::Ne,
+        ">" => ComparisonOperator::Gt,
+        ">=" => ComparisonOperator::Ge,
+        "<" => ComparisonOperator::Lt,
+        "<=" => ComparisonOperator::Le,
+        "contains" => ComparisonOperator::Contains,
+        "starts_with" => ComparisonOperator::StartsWith,
+        "ends_with" => ComparisonOperator::EndsWith,
+        "ieq" => ComparisonOperator::EqIgnoreCase,
+        "icontains" => ComparisonOperator::ContainsIgnoreCase,
+        "istarts_with" => ComparisonOperator::StartsWithIgnoreCase,
+        "iends_with" => ComparisonOperator::EndsWithIgnoreCase,
+        "in" => ComparisonOperator::In,
+        "between" => ComparisonOperator::Between,
+        "matches" | "regex" => ComparisonOperator::Regex,
+        // Operadores sem valor
+        "exists" => return Ok((ComparisonOperator::Exists, serde_json::Value::Null)),
+        "not" => {
+            let next = words.next_word("'in' or 'exists' after 'not'")?;
+            match next.text.to_ascii_lowercase().as_str() {
+                "in" => ComparisonOperator::NotIn,
+                "exists" => return Ok((ComparisonOperator::NotExists, serde_json::Value::Null)),
+                _ => {
+                    return Err(parse_error(
+                        format!(
+                            "Expected 'in' or 'exists' after 'not', found '{}'",
+                            next.text
+                        ),
+                        next.column,
+                    ))
+                }
+            }
+        }
+        "is" => {
+            let next = words.next_word("'null' after 'is'")?;
+            if !next.text.eq_ignore_ascii_case("null") {
+                return Err(parse_error(
+                    format!("Expected 'null' after 'is', found '{}'", next.text),
+                    next.column,
+                ));
+            }
+            return Ok((ComparisonOperator::IsNull, serde_json::Value::Null));
+        }
+        _ => {
+            return Err(parse_error(
+                format!("Unknown operator '{}'", operator_word.text),
+                operator_word.column,
+            ))
+        }
+    };
+
+    let value = words.next_word(&format!("a value after '{}'", operator_word.text))?;
+    Ok((operator, word_value(value)))
+}
+
+/// `persist <table> <json>`
+fn parse_persist(line: &str, rest: &str) -> Result<ShellCommand, ChainDBError> {
+    // Posição do trecho dentro da linha original
+    let column = |text: &str| {
+        line[..text.as_ptr() as usize - line.as_ptr() as usize]
+            .chars()
+            .count()
+            + 1
+    };
+    let (table, json) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if table.is_empty() {
+        return Err(parse_error("Expected a table name", column(rest)));
+    }
+    let json = json.trim();
+    let data: serde_json::Value = serde_json::from_str(json).map_err(|e| {
+        parse_error(
+            format!("Expected a JSON object to persist: {}", e),
+            column(json),
+        )
+    })?;
+    if !data.is_object() {
+        return Err(parse_error(
+            "Expected a JSON object to persist",
+            column(json),
+        ));
+    }
+    Ok(ShellCommand::Persist {
+        table: table.to_string(),
+        data,
+    })
+}
+
+/// Value of a condition: quoted words are text, other words are JSON when they parse as JSON
+fn word_value(word: Word) -> serde_json::Value {
+    if word.quoted {
+        return serde_json::Value::String(word.text);
+    }
+    serde_json::from_str(&word.text).unwrap_or(serde_json::Value::String(word.text))
+}
+
+/// Splits a line into words. Quotes group text with spaces, and brackets and braces group a
+/// JSON array or object
+fn tokenize(line: &str) -> Result<Vec<Word>, ChainDBError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+
+        if chars[i] == '"' || chars[i] == '\'' {
+            let quote = chars[i];
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(parse_error("Unterminated quoted text", start + 1)),
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&c) if c == quote => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            words.push(Word {
+                text,
+                quoted: true,
+                column: start + 1,
+            });
+            continue;
+        }
+
+        // Agrupa colchetes e chaves, ignorando os que estão dentro de strings
+        let mut depth = 0usize;
+        let mut in_string = false;
+        while i < chars.len() {
+            let c = chars[i];
+            if in_string {
+                if c == '\\' {
+                    i += 1;
+                } else if c == '"' {
+                    in_string = false;
+                }
+            } else if c == '"' && depth > 0 {
+                in_string = true;
+            } else if c == '[' || c == '{' {
+                depth += 1;
+            } else if (c == ']' || c == '}') && depth > 0 {
+                depth -= 1;
+            } else if c.is_whitespace() && depth == 0 {
+                break;
+            }
+            i += 1;
+        }
+        if depth > 0 || in_string {
+            return Err(parse_error("Unbalanced brackets", start + 1));
+        }
+        words.push(Word {
+            text: chars[start..i].iter().collect(),
+            quoted: false,
+            column: start + 1,
+        });
+    }
+
+    Ok(words)
+}
+
+/// Cursor over the words of a line
+struct Words {
+    words: std::vec::IntoIter<Word>,
+    /// Column just after the end of the line, for "expected ..." errors
+    end_column: usize,
+}
+
+impl Words {
+    fn new(line: &str, words: Vec<Word>) -> Self {
+        Self {
+            words: words.into_iter(),
+            end_column: line.chars().count() + 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<Word> {
+        self.words.next()
+    }
+
+    fn next_word(&mut self, expected: &str) -> Result<Word, ChainDBError> {
+        self.words
+            .next()
+            .ok_or_else(|| parse_error(format!("Expected {}", expected), self.end_column))
+    }
+
+    /// Consumes the next word when it is the (unquoted) keyword
+    fn accept(&mut self, keyword: &str) -> bool {
+        match self.words.as_slice().first() {
+            Some(word) if !word.quoted && word.text.eq_ignore_ascii_case(keyword) => {
+                self.words.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn number(&self, word: &Word) -> Result<usize, ChainDBError> {
+        word.text.parse().map_err(|_| {
+            parse_error(
+                format!("Expected a number, found '{}'", word.text),
+                word.column,
+            )
+        })
+    }
+}
+
+fn parse_error(message: impl Into<String>, column: usize) -> ChainDBError {
+    ChainDBError::QueryParseError {
+        message: message.into(),
+        line: 1,
+        column,
+    }
+}
@@ -0,0 +1,211 @@
+use crate::errors::ChainDBError;
+
+/// Kinds of tokens of the query language
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// Keyword or unquoted identifier (keywords are matched case-insensitively by the parser)
+    Word(String),
+    /// Text between single quotes, double quotes or backticks
+    Quoted {
+        quote: char,
+        text: String,
+    },
+    Number(serde_json::Number),
+    Comma,
+    LeftParen,
+    RightParen,
+    Star,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    End,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "'{}'", word),
+            TokenKind::Quoted { quote, text } => write!(f, "{}{}{}", quote, text, quote),
+            TokenKind::Number(number) => write!(f, "{}", number),
+            TokenKind::Comma => write!(f, "','"),
+            TokenKind::LeftParen => write!(f, "'('"),
+            TokenKind::RightParen => write!(f, "')'"),
+            TokenKind::Star => write!(f, "'*'"),
+            TokenKind::Eq => write!(f, "'='"),
+            TokenKind::Ne => write!(f, "'!='"),
+            TokenKind::Gt => write!(f, "'>'"),
+            TokenKind::Ge => write!(f, "'>='"),
+            TokenKind::Lt => write!(f, "'<'"),
+            TokenKind::Le => write!(f, "'<='"),
+            TokenKind::End => write!(f, "end of query"),
+        }
+    }
+}
+
+/// A token and the byte offset where it starts in the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub position: usize,
+}
+
+/// Builds a parse error pointing to a byte offset of the query
+pub fn parse_error(query: &str, position: usize, message: impl Into<String>) -> ChainDBError {
+    let before = &query[..position.min(query.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map(|line| line.chars().count())
+        .unwrap_or(0)
+        + 1;
+
+    ChainDBError::QueryParseError {
+        message: message.into(),
+        line,
+        column,
+    }
+}
+
+/// Splits a query into tokens. The last token is always `TokenKind::End`
+pub fn tokenize(query: &str) -> Result<Vec<Token>, ChainDBError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(position, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let kind = match c {
+            ',' => single(&mut chars, TokenKind::Comma),
+            '(' => single(&mut chars, TokenKind::LeftParen),
+            ')' => single(&mut chars, TokenKind::RightParen),
+            '*' => single(&mut chars, TokenKind::Star),
+            '=' => {
+                chars.next();
+                // Accept both `=` and `==`
+                if matches!(chars.peek(), Some((_, '='))) {
+                    chars.next();
+                }
+                TokenKind::Eq
+            }
+            '!' => {
+                chars.next();
+                match chars.next() {
+                    Some((_, '=')) => TokenKind::Ne,
+                    _ => return Err(parse_error(query, position, "Expected '=' after '!'")),
+                }
+            }
+            '>' => {
+                chars.next();
+                if matches!(chars.peek(), Some((_, '='))) {
+                    chars.next();
+                    TokenKind::Ge
+                } else {
+                    TokenKind::Gt
+                }
+            }
+            '<' => {
+                chars.next();
+                match chars.peek() {
+                    Some((_, '=')) => {
+                        chars.next();
+                        TokenKind::Le
+                    }
+                    Some((_, '>')) => {
+                        chars.next();
+                        TokenKind::Ne
+                    }
+                    _ => TokenKind::Lt,
+                }
+            }
+            '\'' | '"' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // A doubled quote inside the text is an escaped quote
+                        Some((_, q)) if q == c => {
+                            if matches!(chars.peek(), Some((_, next)) if *next == c) {
+                                chars.next();
+                                text.push(c);
+                            } else {
+                                break;
+                            }
+                        }
+                        Some((_, other)) => text.push(other),
+                        None => {
+                            return Err(parse_error(query, position, "Unterminated quoted text"))
+                        }
+                    }
+                }
+                TokenKind::Quoted { quote: c, text }
+            }
+            c if c.is_ascii_digit()
+                || (c == '-'
+                    && query[position + 1..]
+                        .chars()
+                        .next()
+                        .is_some_and(|next| next.is_ascii_digit() || next == '.')) =>
+            {
+                let mut text = String::new();
+                text.push(c);
+                chars.next();
+                while let Some(&(_, next)) = chars.peek() {
+                    let exponent_sign = (next == '-' || next == '+') && text.ends_with(['e', 'E']);
+                    if next.is_ascii_digit() || matches!(next, '.' | 'e' | 'E') || exponent_sign {
+                        text.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let number = serde_json::from_str::<serde_json::Number>(&text).map_err(|_| {
+                    parse_error(query, position, format!("Invalid number '{}'", text))
+                })?;
+                TokenKind::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&(_, next)) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == '.' {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                TokenKind::Word(word)
+            }
+            other => {
+                return Err(parse_error(
+                    query,
+                    position,
+                    format!("Unexpected character '{}'", other),
+                ))
+            }
+        };
+
+        tokens.push(Token { kind, position });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: query.len(),
+    });
+
+    Ok(tokens)
+}
+
+fn single(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    kind: TokenKind,
+) -> TokenKind {
+    chars.next();
+    kind
+}
@@ -0,0 +1,348 @@
+//! A small SQL-like query language for ad-hoc queries.
+//!
+//! ```text
+//! SELECT name, total FROM orders
+//! WHERE total > 100 AND status IN ('paid', 'shipped')
+//! ORDER BY total DESC
+//! LIMIT 20
+//! ```
+//!
+//! Conditions are evaluated with the same semantics as `ComparisonOperator`, and aggregate
+//! functions (`COUNT`, `SUM`, `AVG`, `MIN`, `MAX`) with `GROUP BY` use `Table::aggregate`'s engine.
+//! Prefixing a query with `EXPLAIN` also returns its plan and execution statistics.
+
+mod lexer;
+mod parser;
+
+pub use parser::{parse, Condition, Query, SelectItem};
+
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::aggregate::{AggregateFunction, Aggregation, Aggregator};
+use crate::chaindb::ChainDB;
+use crate::errors::ChainDBError;
+use crate::explain::{duration_ms, ExecutionStats, Explain};
+use crate::query::{sort_records, SortKey};
+use crate::record::{FieldLayout, Record};
+use crate::table::{CompiledCriterion, Table};
+
+/// Rows produced by a query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResult {
+    /// Resulting rows, one JSON object per record or group
+    pub rows: Vec<serde_json::Value>,
+    /// Number of rows before LIMIT and OFFSET were applied
+    pub total: usize,
+    /// Plan and execution statistics, only present for `EXPLAIN` queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<Explain>,
+}
+
+/// Parses and runs a query against a table of the database
+pub fn execute(db: &ChainDB, query: &str) -> Result<QueryResult, ChainDBError> {
+    execute_query(db, &parse(query)?)
+}
+
+/// Runs an already parsed query against a table of the database
+pub fn execute_query(db: &ChainDB, query: &Query) -> Result<QueryResult, ChainDBError> {
+    if !db.list_tables()?.contains(&query.table) {
+        return Err(ChainDBError::TableNotFound(query.table.clone()));
+    }
+
+    let table = db.create_table::<serde_json::Value>(&query.table)?;
+    query.execute(&table)
+}
+
+/// A WHERE condition ready to be evaluated against records
+enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    Criterion(CompiledCriterion),
+}
+
+impl Filter {
+    fn compile(condition: &Condition) -> Result<Self, ChainDBError> {
+        let compile_all = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(Filter::compile)
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(match condition {
+            Condition::And(conditions) => Filter::And(compile_all(conditions)?),
+            Condition::Or(conditions) => Filter::Or(compile_all(conditions)?),
+            Condition::Not(condition) => Filter::Not(Box::new(Filter::compile(condition)?)),
+            Condition::Compare {
+                field,
+                operator,
+                value,
+            } => Filter::Criterion(CompiledCriterion::new(field, operator, value)?),
+        })
+    }
+
+    fn matches(&self, record: &serde_json::Value, layout: FieldLayout) -> bool {
+        match self {
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(record, layout)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(record, layout)),
+            Filter::Not(filter) => !filter.matches(record, layout),
+            Filter::Criterion(criterion) => criterion.matches_record(record, layout),
+        }
+    }
+
+    fn criteria_count(&self) -> usize {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => {
+                filters.iter().map(Filter::criteria_count).sum()
+            }
+            Filter::Not(filter) => filter.criteria_count(),
+            Filter::Criterion(_) => 1,
+        }
+    }
+}
+
+impl Query {
+    /// Runs the query against a table. The table named in FROM is not checked
+    pub fn execute<T>(&self, table: &Table<T>) -> Result<QueryResult, ChainDBError>
+    where
+        T: Record,
+    {
+        let started_at = Instant::now();
+        let filter = self.filter.as_ref().map(Filter::compile).transpose()?;
+        let matches = |record: &serde_json::Value| {
+            filter
+                .as_ref()
+                .map(|filter| filter.matches(record, T::LAYOUT))
+                .unwrap_or(true)
+        };
+
+        let is_aggregate = !self.group_by.is_empty()
+            || self
+                .select
+                .iter()
+                .any(|item| matches!(item, SelectItem::Aggregate(_)));
+
+        let (mut result, mut stats) = if is_aggregate {
+            self.execute_aggregate(table, matches)?
+        } else {
+            self.execute_select(table, matches)?
+        };
+
+        if self.explain {
+            stats.elapsed_ms = duration_ms(started_at.elapsed());
+            let criteria = filter.as_ref().map(Filter::criteria_count).unwrap_or(0);
+            result.explain = Some(Explain {
+                plan: table.plan(!is_aggregate, criteria, &self.order_by),
+                stats,
+            });
+        }
+
+        Ok(result)
+    }
+
+    fn execute_select<T>(
+        &self,
+        table: &Table<T>,
+        matches: impl Fn(&serde_json::Value) -> bool,
+    ) -> Result<(QueryResult, ExecutionStats), ChainDBError>
+    where
+        T: Record,
+    {
+        let start = self.offset.unwrap_or(0);
+        let end = self
+            .limit
+            .map(|limit| start.saturating_add(limit))
+            .unwrap_or(usize::MAX);
+        let keep_all = !self.order_by.is_empty();
+
+        let mut total = 0;
+        let mut records = Vec::new();
+
+        // Sem ORDER BY, os registros são retornados do mais recente para o mais antigo
+        let stats = table.scan_with_stats(true, |record, file_stats| {
+            if matches(&record) {
+                if keep_all || (start..end).contains(&total) {
+                    records.push(record);
+                }
+                total += 1;
+                file_stats.records_matched += 1;
+            }
+            Ok(true)
+        })?;
+
+        if keep_all {
+            // ORDER BY pode usar o alias de um campo selecionado
+            let order_by: Vec<SortKey> = self
+                .order_by
+                .iter()
+                .map(|key| SortKey {
+                    field: self.field_for_alias(&key.field),
+                    direction: key.direction,
+                })
+                .collect();
+            sort_records(&mut records, T::LAYOUT, &order_by);
+            records = records.into_iter().skip(start).take(end - start).collect();
+        }
+
+        let rows = records
+            .iter()
+            .map(|record| self.project(record, T::LAYOUT))
+            .collect();
+
+        Ok((
+            QueryResult {
+                rows,
+                total,
+                explain: None,
+            },
+            stats,
+        ))
+    }
+
+    fn execute_aggregate<T>(
+        &self,
+        table: &Table<T>,
+        matches: impl Fn(&serde_json::Value) -> bool,
+    ) -> Result<(QueryResult, ExecutionStats), ChainDBError>
+    where
+        T: Record,
+    {
+        let mut aggregations = Vec::new();
+        for item in &self.select {
+            match item {
+                SelectItem::Aggregate(aggregation) => aggregations.push(aggregation.clone()),
+                SelectItem::Field { field, .. } if !self.group_by.contains(field) => {
+                    return Err(ChainDBError::ValidationError(format!(
+                        "Field '{}' must appear in GROUP BY or be used in an aggregate function",
+                        field
+                    )));
+                }
+                SelectItem::All => {
+                    return Err(ChainDBError::ValidationError(
+                        "SELECT * cannot be used with aggregate functions or GROUP BY".to_string(),
+                    ));
+                }
+                SelectItem::Field { .. } => {}
+            }
+        }
+
+        // GROUP BY sem agregações ainda precisa de um acumulador para listar os grupos
+        if aggregations.is_empty() {
+            aggregations.push(Aggregation::new(AggregateFunction::Count, None));
+        }
+
+        let mut aggregator = Aggregator::new(T::LAYOUT, &self.group_by, &aggregations)?;
+        let stats = table.scan_with_stats(false, |record, file_stats| {
+            if matches(&record) {
+                aggregator.add(&record);
+                file_stats.records_matched += 1;
+            }
+            Ok(true)
+        })?;
+
+        let mut rows: Vec<serde_json::Value> = aggregator
+            .finish()
+            .into_iter()
+            .map(|group| {
+                let mut row = serde_json::Map::new();
+                for item in &self.select {
+                    match item {
+                        SelectItem::Field { field, alias } => {
+                            let value = group
+                                .key
+                                .get(field)
+                                .cloned()
+                                .unwrap_or(serde_json::Value::Null);
+                            row.insert(alias.clone().unwrap_or_else(|| field.clone()), value);
+                        }
+                        SelectItem::Aggregate(aggregation) => {
+                            let name = aggregation.name();
+                            let value = group
+                                .values
+                                .get(&name)
+                                .cloned()
+                                .unwrap_or(serde_json::Value::Null);
+                            row.insert(name, value);
+                        }
+                        SelectItem::All => {}
+                    }
+                }
+                serde_json::Value::Object(row)
+            })
+            .collect();
+
+        // As linhas agrupadas têm os campos na raiz
+        sort_records(&mut rows, FieldLayout::Root, &self.order_by);
+
+        let total = rows.len();
+        let rows = rows
+            .into_iter()
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        Ok((
+            QueryResult {
+                rows,
+                total,
+                explain: None,
+            },
+            stats,
+        ))
+    }
+
+    /// Returns the field selected under `alias`, or `alias` itself when it is not an alias
+    fn field_for_alias(&self, alias: &str) -> String {
+        self.select
+            .iter()
+            .find_map(|item| match item {
+                SelectItem::Field {
+                    field,
+                    alias: Some(name),
+                } if name == alias => Some(field.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| alias.to_string())
+    }
+
+    /// Builds the output row of a record according to the SELECT list
+    fn project(&self, record: &serde_json::Value, layout: FieldLayout) -> serde_json::Value {
+        let mut row = serde_json::Map::new();
+        let Some(record_obj) = record.as_object() else {
+            return record.clone();
+        };
+
+        for item in &self.select {
+            match item {
+                SelectItem::All => {
+                    // Os campos do registro são expostos no mesmo nível que doc_id, como na API
+                    let nested = match layout {
+                        FieldLayout::Nested(key) => Some(key),
+                        FieldLayout::Root => None,
+                    };
+                    if let Some(fields) = nested.and_then(|_| layout.fields(record)) {
+                        row.extend(fields.clone());
+                    }
+                    row.extend(
+                        record_obj
+                            .iter()
+                            .filter(|(key, _)| Some(key.as_str()) != nested)
+                            .map(|(key, value)| (key.clone(), value.clone())),
+                    );
+                }
+                SelectItem::Field { field, alias } => {
+                    let value = layout
+                        .resolve(record_obj, field)
+                        .cloned()
+                        .unwrap_or(serde_json::Value::Null);
+                    row.insert(alias.clone().unwrap_or_else(|| field.clone()), value);
+                }
+                SelectItem::Aggregate(_) => {}
+            }
+        }
+
+        serde_json::Value::Object(row)
+    }
+}
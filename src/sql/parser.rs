@@ -0,0 +1,524 @@
+use crate::aggregate::{AggregateFunction, Aggregation};
+use crate::errors::ChainDBError;
+use crate::query::SortKey;
+use crate::sql::lexer::{parse_error, tokenize, Token, TokenKind};
+use crate::table::ComparisonOperator;
+
+/// Words that cannot be used as unquoted field names
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "AND", "OR", "NOT", "IN", "BETWEEN", "LIKE", "ILIKE", "IS", "NULL",
+    "TRUE", "FALSE", "ORDER", "GROUP", "BY", "ASC", "DESC", "LIMIT", "OFFSET", "AS", "EXISTS",
+    "MATCHES", "CONTAINS", "STARTS", "ENDS", "WITH", "DISTINCT", "EXPLAIN",
+];
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// `EXPLAIN SELECT ...`: return the plan and execution statistics with the rows
+    pub explain: bool,
+    pub select: Vec<SelectItem>,
+    pub table: String,
+    pub filter: Option<Condition>,
+    pub group_by: Vec<String>,
+    pub order_by: Vec<SortKey>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// An item of the SELECT list
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`: every field of the record
+    All,
+    /// A field (or nested field path), optionally renamed with `AS`
+    Field {
+        field: String,
+        alias: Option<String>,
+    },
+    /// An aggregate function such as `COUNT(*)` or `SUM(total)`
+    Aggregate(Aggregation),
+}
+
+/// A WHERE condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+    Compare {
+        field: String,
+        operator: ComparisonOperator,
+        value: serde_json::Value,
+    },
+}
+
+/// Parses a query such as
+/// `SELECT name, total FROM orders WHERE total > 100 ORDER BY total DESC LIMIT 20`
+pub fn parse(query: &str) -> Result<Query, ChainDBError> {
+    let tokens = tokenize(query)?;
+    let mut parser = Parser {
+        query,
+        tokens,
+        index: 0,
+    };
+    parser.parse_query()
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn parse_query(&mut self) -> Result<Query, ChainDBError> {
+        let explain = self.accept_keyword("EXPLAIN");
+        self.expect_keyword("SELECT")?;
+        let select = self.parse_select_list()?;
+
+        self.expect_keyword("FROM")?;
+        let table = self.parse_identifier("table name")?;
+
+        let filter = if self.accept_keyword("WHERE") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+
+        let mut group_by = Vec::new();
+        if self.accept_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                group_by.push(self.parse_identifier("field name")?);
+                if !self.accept(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let field = self.parse_identifier("field name")?;
+                if self.accept_keyword("DESC") {
+                    order_by.push(SortKey::desc(&field));
+                } else {
+                    self.accept_keyword("ASC");
+                    order_by.push(SortKey::asc(&field));
+                }
+                if !self.accept(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let limit = if self.accept_keyword("LIMIT") {
+            Some(self.parse_count("LIMIT")?)
+        } else {
+            None
+        };
+
+        let offset = if self.accept_keyword("OFFSET") {
+            Some(self.parse_count("OFFSET")?)
+        } else {
+            None
+        };
+
+        let token = self.peek().clone();
+        if token.kind != TokenKind::End {
+            return Err(self.error_at(&token, format!("Unexpected {}", token.kind)));
+        }
+
+        Ok(Query {
+            explain,
+            select,
+            table,
+            filter,
+            group_by,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_select_list(&mut self) -> Result<Vec<SelectItem>, ChainDBError> {
+        let mut items = Vec::new();
+
+        loop {
+            if self.accept(&TokenKind::Star) {
+                items.push(SelectItem::All);
+            } else if let Some(function) = self.peek_aggregate_function() {
+                self.next();
+                items.push(SelectItem::Aggregate(self.parse_aggregate(function)?));
+            } else {
+                let field = self.parse_identifier("field name")?;
+                let alias = self.parse_alias()?;
+                items.push(SelectItem::Field { field, alias });
+            }
+
+            if !self.accept(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    fn peek_aggregate_function(&self) -> Option<AggregateFunction> {
+        let TokenKind::Word(word) = &self.peek().kind else {
+            return None;
+        };
+        if self.tokens.get(self.index + 1).map(|token| &token.kind) != Some(&TokenKind::LeftParen) {
+            return None;
+        }
+
+        match word.to_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+
+    fn parse_aggregate(
+        &mut self,
+        function: AggregateFunction,
+    ) -> Result<Aggregation, ChainDBError> {
+        self.expect(&TokenKind::LeftParen)?;
+
+        let mut aggregation =
+            if function == AggregateFunction::Count && self.accept(&TokenKind::Star) {
+                Aggregation::new(function, None)
+            } else if function == AggregateFunction::Count && self.accept_keyword("DISTINCT") {
+                let field = self.parse_identifier("field name")?;
+                Aggregation::new(AggregateFunction::CountDistinct, Some(&field))
+            } else {
+                let field = self.parse_identifier("field name")?;
+                Aggregation::new(function, Some(&field))
+            };
+
+        self.expect(&TokenKind::RightParen)?;
+        aggregation.alias = self.parse_alias()?;
+        Ok(aggregation)
+    }
+
+    fn parse_alias(&mut self) -> Result<Option<String>, ChainDBError> {
+        if self.accept_keyword("AS") {
+            Ok(Some(self.parse_identifier("alias")?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Condition, ChainDBError> {
+        let mut conditions = vec![self.parse_and()?];
+        while self.accept_keyword("OR") {
+            conditions.push(self.parse_and()?);
+        }
+
+        Ok(if conditions.len() == 1 {
+            conditions.remove(0)
+        } else {
+            Condition::Or(conditions)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Condition, ChainDBError> {
+        let mut conditions = vec![self.parse_not()?];
+        while self.accept_keyword("AND") {
+            conditions.push(self.parse_not()?);
+        }
+
+        Ok(if conditions.len() == 1 {
+            conditions.remove(0)
+        } else {
+            Condition::And(conditions)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Condition, ChainDBError> {
+        if self.accept_keyword("NOT") {
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+
+        if self.accept(&TokenKind::LeftParen) {
+            let condition = self.parse_or()?;
+            self.expect(&TokenKind::RightParen)?;
+            return Ok(condition);
+        }
+
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<Condition, ChainDBError> {
+        let start = self.peek().clone();
+        let field = self.parse_identifier("field name")?;
+
+        let compare = |operator: ComparisonOperator, value: serde_json::Value| Condition::Compare {
+            field: field.clone(),
+            operator,
+            value,
+        };
+
+        let token = self.next();
+        let operator = match &token.kind {
+            TokenKind::Eq => Some(ComparisonOperator::Eq),
+            TokenKind::Ne => Some(ComparisonOperator::Ne),
+            TokenKind::Gt => Some(ComparisonOperator::Gt),
+            TokenKind::Ge => Some(ComparisonOperator::Ge),
+            TokenKind::Lt => Some(ComparisonOperator::Lt),
+            TokenKind::Le => Some(ComparisonOperator::Le),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            let value = self.parse_value()?;
+            return self.validated(&start, compare(operator, value));
+        }
+
+        if is_keyword(&token, "IS") {
+            let negated = self.accept_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            // Como em SQL, um campo ausente é tratado como NULL
+            let is_null = Condition::Or(vec![
+                compare(ComparisonOperator::NotExists, serde_json::Value::Null),
+                compare(ComparisonOperator::IsNull, serde_json::Value::Null),
+            ]);
+            return Ok(if negated {
+                Condition::Not(Box::new(is_null))
+            } else {
+                is_null
+            });
+        }
+
+        // Os demais predicados podem ser negados com NOT (ex.: `status NOT IN (...)`)
+        let (negated, token) = if is_keyword(&token, "NOT") {
+            (true, self.next())
+        } else {
+            (false, token)
+        };
+
+        let condition = match &token.kind {
+            TokenKind::Word(word) => match word.to_uppercase().as_str() {
+                "IN" => {
+                    self.expect(&TokenKind::LeftParen)?;
+                    let mut values = Vec::new();
+                    loop {
+                        values.push(self.parse_value()?);
+                        if !self.accept(&TokenKind::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(&TokenKind::RightParen)?;
+                    let operator = if negated {
+                        ComparisonOperator::NotIn
+                    } else {
+                        ComparisonOperator::In
+                    };
+                    return self.validated(&start, compare(operator, serde_json::json!(values)));
+                }
+                "BETWEEN" => {
+                    let min = self.parse_value()?;
+                    self.expect_keyword("AND")?;
+                    let max = self.parse_value()?;
+                    compare(ComparisonOperator::Between, serde_json::json!([min, max]))
+                }
+                "LIKE" | "ILIKE" => {
+                    let pattern = self.parse_string()?;
+                    let mut regex = like_to_regex(&pattern);
+                    if word.eq_ignore_ascii_case("ILIKE") {
+                        regex.insert_str(0, "(?i)");
+                    }
+                    compare(ComparisonOperator::Regex, serde_json::json!(regex))
+                }
+                "MATCHES" => compare(ComparisonOperator::Regex, self.parse_value()?),
+                "CONTAINS" => compare(ComparisonOperator::Contains, self.parse_value()?),
+                "STARTS" => {
+                    self.expect_keyword("WITH")?;
+                    compare(ComparisonOperator::StartsWith, self.parse_value()?)
+                }
+                "ENDS" => {
+                    self.expect_keyword("WITH")?;
+                    compare(ComparisonOperator::EndsWith, self.parse_value()?)
+                }
+                "EXISTS" => compare(ComparisonOperator::Exists, serde_json::Value::Null),
+                _ => return Err(self.expected_operator(&token)),
+            },
+            _ => return Err(self.expected_operator(&token)),
+        };
+
+        let condition = self.validated(&start, condition)?;
+        Ok(if negated {
+            Condition::Not(Box::new(condition))
+        } else {
+            condition
+        })
+    }
+
+    /// Validates the operand of a comparison, reporting the position of the predicate
+    fn validated(&self, start: &Token, condition: Condition) -> Result<Condition, ChainDBError> {
+        if let Condition::Compare {
+            field,
+            operator,
+            value,
+        } = &condition
+        {
+            if let Err(e) = operator.validate(field, value) {
+                let message = match e {
+                    ChainDBError::ValidationError(message) => message,
+                    other => other.to_string(),
+                };
+                return Err(self.error_at(start, message));
+            }
+        }
+        Ok(condition)
+    }
+
+    fn expected_operator(&self, token: &Token) -> ChainDBError {
+        self.error_at(
+            token,
+            format!("Expected a comparison operator but found {}", token.kind),
+        )
+    }
+
+    fn parse_value(&mut self) -> Result<serde_json::Value, ChainDBError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Quoted { quote, text } if *quote != '`' => Ok(serde_json::json!(text)),
+            TokenKind::Number(number) => Ok(serde_json::Value::Number(number.clone())),
+            TokenKind::Word(word) if word.eq_ignore_ascii_case("TRUE") => {
+                Ok(serde_json::json!(true))
+            }
+            TokenKind::Word(word) if word.eq_ignore_ascii_case("FALSE") => {
+                Ok(serde_json::json!(false))
+            }
+            TokenKind::Word(word) if word.eq_ignore_ascii_case("NULL") => {
+                Ok(serde_json::Value::Null)
+            }
+            _ => Err(self.error_at(&token, format!("Expected a value but found {}", token.kind))),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, ChainDBError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Quoted { quote, text } if *quote != '`' => Ok(text.clone()),
+            _ => Err(self.error_at(
+                &token,
+                format!("Expected a string but found {}", token.kind),
+            )),
+        }
+    }
+
+    fn parse_identifier(&mut self, what: &str) -> Result<String, ChainDBError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Word(word) if !is_reserved(word) => Ok(word.clone()),
+            TokenKind::Quoted { quote, text } if *quote == '"' || *quote == '`' => Ok(text.clone()),
+            _ => Err(self.error_at(
+                &token,
+                format!("Expected {} but found {}", what, token.kind),
+            )),
+        }
+    }
+
+    fn parse_count(&mut self, clause: &str) -> Result<usize, ChainDBError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Number(number) if number.is_u64() => {
+                Ok(number.as_u64().unwrap_or(0) as usize)
+            }
+            _ => Err(self.error_at(
+                &token,
+                format!(
+                    "Expected a non-negative integer after {} but found {}",
+                    clause, token.kind
+                ),
+            )),
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        if token.kind != TokenKind::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn accept(&mut self, kind: &TokenKind) -> bool {
+        if &self.peek().kind == kind {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), ChainDBError> {
+        let token = self.next();
+        if &token.kind == kind {
+            Ok(())
+        } else {
+            Err(self.error_at(
+                &token,
+                format!("Expected {} but found {}", kind, token.kind),
+            ))
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if is_keyword(self.peek(), keyword) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ChainDBError> {
+        let token = self.next();
+        if is_keyword(&token, keyword) {
+            Ok(())
+        } else {
+            Err(self.error_at(
+                &token,
+                format!("Expected {} but found {}", keyword, token.kind),
+            ))
+        }
+    }
+
+    fn error_at(&self, token: &Token, message: impl Into<String>) -> ChainDBError {
+        parse_error(self.query, token.position, message)
+    }
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(&token.kind, TokenKind::Word(word) if word.eq_ignore_ascii_case(keyword))
+}
+
+fn is_reserved(word: &str) -> bool {
+    KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+/// Converts a LIKE pattern (`%` for any text, `_` for one character) to an anchored regex
+fn like_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
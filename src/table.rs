@@ -1,17 +1,42 @@
 use base64::{engine::general_purpose, Engine as _};
+use chrono::DateTime;
+use futures::Stream;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use uuid::Uuid;
 
+use crate::aggregate::{AggregateGroup, Aggregation, Aggregator};
+use crate::check::{self, TableReport};
 use crate::encryption::DataEncryption;
 use crate::errors::ChainDBError;
 use crate::events::{emit_table_persist, emit_table_update};
-
-const RECORDS_PER_FILE: usize = 1000;
+use crate::explain::{
+    duration_ms, AccessPath, ExecutionStats, Explain, FileStats, QueryPlan, ScanDirection,
+};
+use crate::iter::{RecordIter, TableIter};
+use crate::lock::{self, FileLock, LockMode};
+use crate::patch::Patch;
+use crate::query::{
+    project_record, query_hash, sort_indexed, Cursor, QueryOptions, QueryPage, SortKey,
+};
+use crate::record::{DocId, FieldLayout, Record};
+use crate::retention::{Liveness, RetentionPolicy, SweepStats};
+use crate::transfer::{
+    export_records, import_records, DocIdMode, ExportOptions, ImportOptions, ImportReport,
+};
+
+pub(crate) const RECORDS_PER_FILE: usize = 1000;
 pub const METADATA_FILE: &str = "metadata.cdb";
+/// Versão do formato de armazenamento das tabelas (linhas base64 de registros JSON
+/// criptografados, `RECORDS_PER_FILE` por arquivo, e `metadata.cdb`). Só muda quando versões
+/// anteriores deixam de conseguir ler os arquivos
+pub const STORAGE_FORMAT_VERSION: u32 = 1;
 
 /// Operadores de comparação disponíveis para consultas avançadas
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -34,117 +59,760 @@ pub enum ComparisonOperator {
     StartsWith,
     /// Termina com (para strings)
     EndsWith,
+    /// Igual a, ignorando maiúsculas/minúsculas (para strings)
+    EqIgnoreCase,
+    /// Contém, ignorando maiúsculas/minúsculas (para strings)
+    ContainsIgnoreCase,
+    /// Começa com, ignorando maiúsculas/minúsculas (para strings)
+    StartsWithIgnoreCase,
+    /// Termina com, ignorando maiúsculas/minúsculas (para strings)
+    EndsWithIgnoreCase,
+    /// O valor está presente na lista informada (o valor deve ser um array)
+    In,
+    /// O valor não está presente na lista informada (o valor deve ser um array)
+    NotIn,
+    /// Entre dois limites, inclusive (o valor deve ser um array `[min, max]`)
+    Between,
+    /// Corresponde à expressão regular informada
+    Regex,
+    /// O campo existe no registro (o valor é ignorado)
+    Exists,
+    /// O campo não existe no registro (o valor é ignorado)
+    NotExists,
+    /// O campo existe e é `null` (o valor é ignorado)
+    IsNull,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ComparisonOperator {
+    /// Validates the operand supplied for this operator.
+    ///
+    /// Only the operators that require a specific operand shape are checked, so the
+    /// original operators keep accepting any value as before.
+    pub fn validate(&self, field: &str, value: &serde_json::Value) -> Result<(), ChainDBError> {
+        match self {
+            ComparisonOperator::In | ComparisonOperator::NotIn if !value.is_array() => {
+                return Err(ChainDBError::ValidationError(format!(
+                    "Operator {:?} on field '{}' requires an array value",
+                    self, field
+                )));
+            }
+            ComparisonOperator::Between => match value.as_array() {
+                Some(bounds) if bounds.len() == 2 => {
+                    let comparable = matches!(
+                        (&bounds[0], &bounds[1]),
+                        (serde_json::Value::Number(_), serde_json::Value::Number(_))
+                            | (serde_json::Value::String(_), serde_json::Value::String(_))
+                    );
+                    if !comparable {
+                        return Err(ChainDBError::ValidationError(format!(
+                            "Operator Between on field '{}' requires two numbers or two strings",
+                            field
+                        )));
+                    }
+                }
+                _ => {
+                    return Err(ChainDBError::ValidationError(format!(
+                        "Operator Between on field '{}' requires an array with exactly two values",
+                        field
+                    )));
+                }
+            },
+            ComparisonOperator::Regex => match value.as_str() {
+                Some(pattern) => {
+                    regex::Regex::new(pattern).map_err(|e| {
+                        ChainDBError::ValidationError(format!(
+                            "Invalid regular expression for field '{}': {}",
+                            field, e
+                        ))
+                    })?;
+                }
+                None => {
+                    return Err(ChainDBError::ValidationError(format!(
+                        "Operator Regex on field '{}' requires a string pattern",
+                        field
+                    )));
+                }
+            },
+            ComparisonOperator::EqIgnoreCase
+            | ComparisonOperator::ContainsIgnoreCase
+            | ComparisonOperator::StartsWithIgnoreCase
+            | ComparisonOperator::EndsWithIgnoreCase
+                if !value.is_string() =>
+            {
+                return Err(ChainDBError::ValidationError(format!(
+                    "Operator {:?} on field '{}' requires a string value",
+                    self, field
+                )));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Critério avançado já validado e pronto para ser avaliado contra os registros
+#[derive(Debug)]
+pub(crate) struct CompiledCriterion {
+    pub(crate) field: String,
+    pub(crate) operator: ComparisonOperator,
+    pub(crate) value: serde_json::Value,
+    regex: Option<regex::Regex>,
+}
+
+impl CompiledCriterion {
+    pub(crate) fn new(
+        field: &str,
+        operator: &ComparisonOperator,
+        value: &serde_json::Value,
+    ) -> Result<Self, ChainDBError> {
+        operator.validate(field, value)?;
+
+        // A expressão regular é compilada uma única vez por consulta
+        let regex = match (operator, value.as_str()) {
+            (ComparisonOperator::Regex, Some(pattern)) => Some(
+                regex::Regex::new(pattern)
+                    .map_err(|e| ChainDBError::ValidationError(e.to_string()))?,
+            ),
+            _ => None,
+        };
+
+        Ok(Self {
+            field: field.to_string(),
+            operator: operator.clone(),
+            value: value.clone(),
+            regex,
+        })
+    }
+
+    /// Avalia o critério contra um registro completo
+    pub(crate) fn matches_record(&self, record: &serde_json::Value, layout: FieldLayout) -> bool {
+        let actual = record
+            .as_object()
+            .and_then(|record_obj| layout.resolve(record_obj, &self.field));
+        self.matches(actual)
+    }
+
+    /// Avalia o critério contra o valor do campo (`None` quando o campo não existe)
+    fn matches(&self, actual: Option<&serde_json::Value>) -> bool {
+        match (&self.operator, actual) {
+            (ComparisonOperator::Exists, actual) => actual.is_some(),
+            (ComparisonOperator::NotExists, actual) => actual.is_none(),
+            (ComparisonOperator::IsNull, actual) => matches!(actual, Some(serde_json::Value::Null)),
+            (_, None) => false,
+            (ComparisonOperator::Regex, Some(actual)) => match (actual, &self.regex) {
+                (serde_json::Value::String(a), Some(regex)) => regex.is_match(a),
+                _ => false,
+            },
+            (operator, Some(actual)) => compare_values(actual, &self.value, operator),
+        }
+    }
+}
+
+/// Branch taken by `Table::upsert`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpsertAction {
+    /// No document matched, a new one was persisted
+    Inserted,
+    /// The matching document was replaced
+    Updated,
+}
+
+/// Outcome of `Table::upsert`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpsertResult {
+    pub action: UpsertAction,
+    pub doc_id: String,
+    pub revision: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableMetadata {
     pub current_file: u32,
     pub total_records: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
 }
 
 impl TableMetadata {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             current_file: 0,
             total_records: 0,
+            retention: None,
+        }
+    }
+
+    /// Lê os metadados de uma tabela, ou `None` se a tabela ainda não existir
+    pub(crate) fn load(
+        table_path: &Path,
+        encryption: &DataEncryption,
+    ) -> Result<Option<Self>, ChainDBError> {
+        let metadata_path = table_path.join(METADATA_FILE);
+        if !metadata_path.exists() {
+            return Ok(None);
+        }
+
+        let encrypted_metadata = fs::read(&metadata_path)?;
+        let metadata_json = encryption.decrypt(&encrypted_metadata)?;
+        Ok(Some(serde_json::from_slice(&metadata_json)?))
+    }
+
+    /// Data de modificação e tamanho de `metadata.cdb`, usados para perceber que outro
+    /// processo alterou os metadados sem precisar relê-los
+    fn stamp(table_path: &Path) -> Option<MetadataStamp> {
+        let file_metadata = fs::metadata(table_path.join(METADATA_FILE)).ok()?;
+        Some((file_metadata.modified().ok(), file_metadata.len()))
+    }
+
+    /// Serializa e criptografa os metadados no formato de `metadata.cdb`
+    pub(crate) fn encrypt(&self, encryption: &DataEncryption) -> Result<Vec<u8>, ChainDBError> {
+        let metadata_json = serde_json::to_vec(self)?;
+        encryption.encrypt(&metadata_json)
+    }
+}
+
+/// Nome do arquivo de dados com o índice informado
+pub(crate) fn data_file_name(file_index: u32) -> String {
+    format!("data_{}.cdb", file_index)
+}
+
+/// Substitui o conteúdo de um arquivo de dados por `lines`. Grava em `data_N.cdb.tmp`,
+/// sincroniza e renomeia sobre o original, para que uma falha no meio nunca deixe o
+/// arquivo pela metade
+fn rewrite_data_file(file_path: &Path, lines: &[String]) -> Result<(), ChainDBError> {
+    let temp_path = file_path.with_extension("cdb.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&temp_path, file_path)?;
+    Ok(())
+}
+
+/// Campos gerenciados pelo sistema, que não podem ser definidos pelo usuário
+pub(crate) const SYSTEM_FIELDS: &[&str] = &[
+    "doc_id",
+    "revision",
+    "created_at",
+    "updated_at",
+    "created_by",
+    "updated_by",
+];
+
+/// Quando e por quem um registro foi criado e alterado pela última vez (os campos de sistema
+/// created_at, created_by, updated_at e updated_by). As datas são strings RFC 3339 em UTC
+#[derive(Debug, Clone)]
+pub(crate) struct WriteStamp {
+    created_at: serde_json::Value,
+    created_by: serde_json::Value,
+    updated_at: serde_json::Value,
+    updated_by: serde_json::Value,
+}
+
+impl WriteStamp {
+    /// Gravação de um documento novo por `user`
+    pub(crate) fn created(user: Option<&str>) -> Self {
+        let now = serde_json::Value::String(timestamp_now());
+        let user = user
+            .map(|user| serde_json::Value::String(user.to_string()))
+            .unwrap_or(serde_json::Value::Null);
+
+        Self {
+            created_at: now.clone(),
+            created_by: user.clone(),
+            updated_at: now,
+            updated_by: user,
         }
     }
+
+    /// Nova versão do registro armazenado `stored`, mantendo quando e por quem ele foi criado.
+    /// Registros gravados antes desses campos existirem ficam com `created_*` nulos
+    pub(crate) fn updated(stored: &serde_json::Value, user: Option<&str>) -> Self {
+        let created = |field: &str| {
+            stored
+                .get(field)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null)
+        };
+
+        Self {
+            created_at: created("created_at"),
+            created_by: created("created_by"),
+            ..Self::created(user)
+        }
+    }
+
+    /// Registro importado de `source`, mantendo os dados de gravação que ele trouxer
+    pub(crate) fn imported(source: &serde_json::Value, user: Option<&str>) -> Self {
+        let created = Self::created(user);
+        let field = |name: &str, default: serde_json::Value| {
+            source
+                .get(name)
+                .filter(|value| !value.is_null())
+                .cloned()
+                .unwrap_or(default)
+        };
+
+        Self {
+            created_at: field("created_at", created.created_at.clone()),
+            created_by: field("created_by", created.created_by.clone()),
+            updated_at: field("updated_at", created.updated_at),
+            updated_by: field("updated_by", created.updated_by),
+        }
+    }
+}
+
+/// Data e hora atuais no formato dos campos `created_at` e `updated_at`
+pub(crate) fn timestamp_now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Substitui os campos de sistema informados pelo usuário (na raiz ou nos campos do registro)
+/// pelos valores do sistema: o doc_id do documento, sua revisão e os dados da gravação
+pub(crate) fn assign_system_fields(
+    record_value: &mut serde_json::Value,
+    layout: FieldLayout,
+    doc_id: &str,
+    revision: u64,
+    stamp: &WriteStamp,
+) {
+    // Os campos de sistema ficam apenas na raiz do documento
+    layout.strip_system_fields(record_value);
+
+    if let serde_json::Value::Object(ref mut map) = record_value {
+        map.insert(
+            "doc_id".to_string(),
+            serde_json::Value::String(doc_id.to_string()),
+        );
+        map.insert("revision".to_string(), serde_json::Value::from(revision));
+        map.insert("created_at".to_string(), stamp.created_at.clone());
+        map.insert("created_by".to_string(), stamp.created_by.clone());
+        map.insert("updated_at".to_string(), stamp.updated_at.clone());
+        map.insert("updated_by".to_string(), stamp.updated_by.clone());
+    }
 }
 
+/// Revisão de um registro armazenado. Registros gravados antes da existência de revisões
+/// têm revisão 0
+pub fn record_revision(record_value: &serde_json::Value) -> u64 {
+    record_value
+        .get("revision")
+        .and_then(|revision| revision.as_u64())
+        .unwrap_or(0)
+}
+
+/// Verifica a revisão esperada pelo cliente (controle de concorrência otimista)
+pub(crate) fn check_revision(
+    doc_id: &str,
+    expected_revision: Option<u64>,
+    actual_revision: u64,
+) -> Result<(), ChainDBError> {
+    match expected_revision {
+        Some(expected) if expected != actual_revision => Err(ChainDBError::RevisionConflict {
+            doc_id: doc_id.to_string(),
+            expected,
+            actual: actual_revision,
+        }),
+        _ => Ok(()),
+    }
+}
+
+type MetadataStamp = (Option<SystemTime>, u64);
+
 #[derive(Debug)]
 pub struct Table<T> {
     path: PathBuf,
-    metadata: TableMetadata,
+    /// Atrás de um `RwLock` para que as leituras (`&self`) possam recarregar os metadados
+    metadata: RwLock<TableMetadata>,
+    /// `metadata.cdb` como estava quando os metadados foram lidos ou gravados por último
+    metadata_stamp: Mutex<Option<MetadataStamp>>,
     encryption: DataEncryption,
+    lock_timeout: Duration,
+    /// Usuário registrado em created_by / updated_by
+    user: Option<String>,
     _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T> Table<T>
 where
-    T: Serialize + for<'de> Deserialize<'de>,
+    T: Record,
 {
     pub fn new(path: PathBuf, encryption: DataEncryption) -> Result<Self, ChainDBError> {
+        Self::with_lock_timeout(path, encryption, lock::default_timeout())
+    }
+
+    /// Igual a `new`, definindo quanto tempo esperar pelos locks da tabela
+    pub fn with_lock_timeout(
+        path: PathBuf,
+        encryption: DataEncryption,
+        lock_timeout: Duration,
+    ) -> Result<Self, ChainDBError> {
         fs::create_dir_all(&path)?;
         let metadata_path = path.join(METADATA_FILE);
 
-        let metadata = if metadata_path.exists() {
-            let encrypted_metadata = fs::read(&metadata_path)?;
-            let metadata_json = encryption.decrypt(&encrypted_metadata)?;
-            serde_json::from_slice(&metadata_json)?
+        let existing = {
+            let _lock = FileLock::table(&path, LockMode::Shared, lock_timeout)?;
+            TableMetadata::load(&path, &encryption)?
+                .map(|metadata| (metadata, TableMetadata::stamp(&path)))
+        };
+
+        let (metadata, stamp) = if let Some(existing) = existing {
+            existing
         } else {
-            let metadata = TableMetadata::new();
-            let metadata_json = serde_json::to_vec(&metadata)?;
-            let encrypted_metadata = encryption.encrypt(&metadata_json)?;
-            let mut file = File::create(&metadata_path)?;
-            file.write_all(&encrypted_metadata)?;
-            metadata
+            // Outro processo pode ter criado a tabela enquanto esperávamos o lock exclusivo
+            let _lock = FileLock::table(&path, LockMode::Exclusive, lock_timeout)?;
+            let metadata = match TableMetadata::load(&path, &encryption)? {
+                Some(metadata) => metadata,
+                None => {
+                    let metadata = TableMetadata::new();
+                    let metadata_json = serde_json::to_vec(&metadata)?;
+                    let encrypted_metadata = encryption.encrypt(&metadata_json)?;
+                    let mut file = File::create(&metadata_path)?;
+                    file.write_all(&encrypted_metadata)?;
+                    metadata
+                }
+            };
+            (metadata, TableMetadata::stamp(&path))
         };
 
         Ok(Self {
             path,
-            metadata,
+            metadata: RwLock::new(metadata),
+            metadata_stamp: Mutex::new(stamp),
             encryption,
+            lock_timeout,
+            user: None,
             _phantom: std::marker::PhantomData,
         })
     }
 
-    pub fn persist(&mut self, record: &T) -> Result<(), ChainDBError> {
-        // Converter o registro para Value para adicionar o doc_id
-        let mut record_value = serde_json::to_value(record)?;
-        println!("Record before adding doc_id: {:?}", record_value);
+    /// Tempo máximo de espera pelos locks da tabela
+    pub fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
+    /// Define quanto tempo esperar pelos locks da tabela antes de retornar
+    /// `ChainDBError::LockTimeout` (zero falha imediatamente)
+    pub fn set_lock_timeout(&mut self, lock_timeout: Duration) {
+        self.lock_timeout = lock_timeout;
+    }
+
+    /// Define o usuário registrado em `created_by` e `updated_by` nas próximas gravações
+    pub(crate) fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// Exporta todos os registros da tabela, do mais antigo para o mais recente, no formato
+    /// pedido (NDJSON, array JSON ou CSV). Os registros são lidos e escritos um de cada vez.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(u64)` - Quantidade de registros exportados
+    /// * `Err(ChainDBError)` - Se a leitura da tabela ou a escrita falhar
+    pub fn export<W: Write>(
+        &self,
+        writer: W,
+        options: impl Into<ExportOptions>,
+    ) -> Result<u64, ChainDBError> {
+        export_records(self.records(false), T::LAYOUT, writer, &options.into())
+    }
 
-        // Remover qualquer doc_id que o usuário tenha tentado incluir
-        if let serde_json::Value::Object(ref mut map) = record_value {
-            // Se houver um campo data que é um objeto, remover doc_id dele também
-            if let Some(serde_json::Value::Object(ref mut data_map)) = map.get_mut("data") {
-                data_map.remove("doc_id");
+    /// Importa registros de `reader`, gravando-os em lotes enquanto a entrada é lida.
+    ///
+    /// Com `DocIdMode::Preserve` (padrão), o doc_id, a revisão e os dados de gravação dos
+    /// registros são mantidos; registros cujo doc_id já existe na tabela são rejeitados.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(ImportReport)` - Quantos registros foram importados e quais falharam
+    /// * `Err(ChainDBError)` - Se a entrada for inválida como um todo ou a gravação falhar
+    pub fn import<R: Read>(
+        &mut self,
+        reader: R,
+        options: impl Into<ImportOptions>,
+    ) -> Result<ImportReport, ChainDBError> {
+        let options = options.into();
+        let mut known_doc_ids = self.import_doc_ids(options.doc_ids)?;
+        import_records(reader, &options, |batch, report| {
+            self.import_batch(batch, options.doc_ids, &mut known_doc_ids, report)
+        })
+    }
+
+    /// doc_ids já existentes na tabela, necessários para preservar os doc_ids importados
+    pub(crate) fn import_doc_ids(&self, mode: DocIdMode) -> Result<HashSet<String>, ChainDBError> {
+        let mut doc_ids = HashSet::new();
+        if mode == DocIdMode::Preserve {
+            self.scan(false, |record_value| {
+                if let Some(doc_id) = record_value.get("doc_id").and_then(|id| id.as_str()) {
+                    doc_ids.insert(doc_id.to_string());
+                }
+                Ok(true)
+            })?;
+        }
+        Ok(doc_ids)
+    }
+
+    /// Grava um lote de registros importados. `known_doc_ids` recebe os doc_ids gravados
+    pub(crate) fn import_batch(
+        &mut self,
+        batch: Vec<(u64, serde_json::Value)>,
+        mode: DocIdMode,
+        known_doc_ids: &mut HashSet<String>,
+        report: &mut ImportReport,
+    ) -> Result<(), ChainDBError> {
+        let _lock = self.lock_for_write()?;
+
+        let user = self.user.clone();
+        let mut records = Vec::with_capacity(batch.len());
+        let mut lines = Vec::with_capacity(batch.len());
+        for (position, source) in batch {
+            let encoded = serde_json::from_value::<T>(source.clone())
+                .map_err(ChainDBError::from)
+                .and_then(|record| {
+                    let mut record_value = serde_json::to_value(&record)?;
+                    let source_doc_id = source.get("doc_id").and_then(|id| id.as_str());
+                    let (doc_id, revision, stamp) = match (mode, source_doc_id) {
+                        (DocIdMode::Preserve, Some(doc_id)) => {
+                            if known_doc_ids.contains(doc_id) {
+                                return Err(ChainDBError::ValidationError(format!(
+                                    "Document {} already exists",
+                                    doc_id
+                                )));
+                            }
+                            (
+                                doc_id.to_string(),
+                                record_revision(&source).max(1),
+                                WriteStamp::imported(&source, user.as_deref()),
+                            )
+                        }
+                        _ => (
+                            Uuid::new_v4().to_string(),
+                            1,
+                            WriteStamp::created(user.as_deref()),
+                        ),
+                    };
+                    assign_system_fields(&mut record_value, T::LAYOUT, &doc_id, revision, &stamp);
+                    let line = self.encode_record(&record_value)?;
+                    Ok((doc_id, record, line))
+                });
+
+            match encoded {
+                Ok((doc_id, record, line)) => {
+                    known_doc_ids.insert(doc_id);
+                    records.push(record);
+                    lines.push(line);
+                }
+                Err(e) => report.fail(position, &e),
             }
+        }
+
+        self.append_lines(&lines)?;
+        report.imported += lines.len() as u64;
+        for record in &records {
+            self.emit_record_event(record, emit_table_persist);
+        }
+        Ok(())
+    }
 
-            // Remover doc_id do objeto raiz
-            map.remove("doc_id");
+    /// Metadados da tabela, como lidos na última operação
+    pub fn metadata(&self) -> TableMetadata {
+        self.metadata.read().unwrap().clone()
+    }
 
-            // Gerar um UUID v4 único para o documento
-            let doc_id = Uuid::new_v4().to_string();
+    /// Política de retenção da tabela, se houver
+    pub fn retention(&self) -> Option<RetentionPolicy> {
+        self.metadata.read().unwrap().retention.clone()
+    }
 
-            // Adicionar o doc_id gerado pelo sistema
-            map.insert(
-                "doc_id".to_string(),
-                serde_json::Value::String(doc_id.clone()),
-            );
+    /// Define (ou remove, com `None`) a política de retenção da tabela. Os documentos que ela
+    /// expira deixam de aparecer nas leituras imediatamente; `sweep` libera o espaço. Falha
+    /// com `ChainDBError::ValidationError` se `max_age_secs` for grande demais
+    pub fn set_retention(&mut self, policy: Option<RetentionPolicy>) -> Result<(), ChainDBError> {
+        if let Some(policy) = &policy {
+            policy.validate()?;
         }
+        let _lock = self.lock_for_write()?;
+        self.metadata_mut().retention = policy.filter(|policy| !policy.is_empty());
+        self.save_metadata()
+    }
 
-        println!("Record after adding doc_id: {:?}", record_value);
+    /// Substitui os documentos expirados por marcadores (tombstones), mantendo a posição dos
+    /// demais registros, e remove os arquivos de dados completos em que todos os registros
+    /// expiraram. O arquivo atual nunca é removido, pois ainda recebe novos registros
+    pub fn sweep(&mut self) -> Result<SweepStats, ChainDBError> {
+        let _lock = self.lock_for_write()?;
+        let mut stats = SweepStats::default();
+        if self.retention().is_none() {
+            return Ok(stats);
+        }
 
-        // Converter de volta para bytes
-        let record_json = serde_json::to_vec(&record_value)?;
+        let liveness = self.liveness();
+        let current_file = self.metadata_mut().current_file;
+        for file_index in self.data_file_indexes(false) {
+            let file_path = self.path.join(data_file_name(file_index));
+            if !file_path.exists() {
+                continue;
+            }
+
+            let mut lines = BufReader::new(File::open(&file_path)?)
+                .lines()
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut expired = 0;
+            let mut live = 0;
+            for (position, line) in lines.iter_mut().enumerate() {
+                let decoded = general_purpose::STANDARD.decode(&*line)?;
+                let decrypted = self.encryption.decrypt(&decoded)?;
+                let record_value: serde_json::Value = serde_json::from_slice(&decrypted)?;
+
+                if liveness.is_live(Liveness::index(file_index, position), &record_value) {
+                    live += 1;
+                } else if record_value
+                    .get(crate::retention::TOMBSTONE_FIELD)
+                    .is_none()
+                {
+                    *line = self.encode_record(&liveness.tombstone())?;
+                    expired += 1;
+                }
+            }
+            stats.expired += expired;
+
+            if live == 0 && file_index < current_file {
+                fs::remove_file(&file_path)?;
+                stats.files_dropped += 1;
+            } else if expired > 0 {
+                rewrite_data_file(&file_path, &lines)?;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn liveness(&self) -> Liveness {
+        Liveness::new(&self.metadata(), T::LAYOUT)
+    }
+
+    /// Verifica a integridade da tabela: cada linha dos arquivos de dados (base64, decriptação
+    /// e JSON), os metadados contra os arquivos e doc_ids repetidos. Não altera nada
+    pub fn check(&self) -> Result<TableReport, ChainDBError> {
+        check::check_table(&self.path, &self.encryption, self.lock_timeout, false)
+    }
+
+    /// Como `check`, mas move as linhas ilegíveis para o arquivo de quarentena (deixando
+    /// tombstones no lugar) e reescreve os metadados a partir dos arquivos de dados
+    pub fn repair(&mut self) -> Result<TableReport, ChainDBError> {
+        let report = check::check_table(&self.path, &self.encryption, self.lock_timeout, true)?;
+        self.refresh()?;
+        Ok(report)
+    }
+
+    /// Recarrega os metadados do disco, mesmo que `metadata.cdb` pareça não ter mudado. As
+    /// escritas sempre recarregam, e as leituras quando o arquivo muda
+    pub fn refresh(&mut self) -> Result<(), ChainDBError> {
+        let _lock = self.lock(LockMode::Shared)?;
+        self.reload_metadata()
+    }
+
+    fn lock(&self, mode: LockMode) -> Result<FileLock, ChainDBError> {
+        FileLock::table(&self.path, mode, self.lock_timeout)
+    }
+
+    /// Adquire o lock compartilhado de leitura e, se `metadata.cdb` mudou desde a última
+    /// leitura (outro processo ou outra instância gravou na tabela), recarrega os metadados
+    fn lock_for_read(&self) -> Result<FileLock, ChainDBError> {
+        let lock = self.lock(LockMode::Shared)?;
+        let stamp = TableMetadata::stamp(&self.path);
+        let mut last_stamp = self.metadata_stamp.lock().unwrap();
+        if stamp.is_some() && *last_stamp != stamp {
+            if let Some(metadata) = TableMetadata::load(&self.path, &self.encryption)? {
+                *self.metadata.write().unwrap() = metadata;
+            }
+            *last_stamp = stamp;
+        }
+        Ok(lock)
+    }
+
+    /// Adquire o lock exclusivo de escrita e recarrega os metadados, que podem ter sido
+    /// alterados por outro processo
+    fn lock_for_write(&mut self) -> Result<FileLock, ChainDBError> {
+        let lock = self.lock(LockMode::Exclusive)?;
+        self.reload_metadata()?;
+        Ok(lock)
+    }
+
+    fn reload_metadata(&mut self) -> Result<(), ChainDBError> {
+        let stamp = TableMetadata::stamp(&self.path);
+        if let Some(metadata) = TableMetadata::load(&self.path, &self.encryption)? {
+            *self.metadata_mut() = metadata;
+        }
+        *self.metadata_stamp.get_mut().unwrap() = stamp;
+        Ok(())
+    }
+
+    fn save_metadata(&mut self) -> Result<(), ChainDBError> {
+        let encrypted_metadata = self.metadata.get_mut().unwrap().encrypt(&self.encryption)?;
+        fs::write(self.path.join(METADATA_FILE), encrypted_metadata)?;
+        *self.metadata_stamp.get_mut().unwrap() = TableMetadata::stamp(&self.path);
+        Ok(())
+    }
+
+    /// Metadados em memória, para quem já tem acesso exclusivo à tabela
+    fn metadata_mut(&mut self) -> &mut TableMetadata {
+        self.metadata.get_mut().unwrap()
+    }
+
+    /// Criptografa um registro no formato de linha dos arquivos de dados
+    fn encode_record(&self, record_value: &serde_json::Value) -> Result<String, ChainDBError> {
+        let record_json = serde_json::to_vec(record_value)?;
         let encrypted_record = self.encryption.encrypt(&record_json)?;
+        Ok(general_purpose::STANDARD.encode(&encrypted_record))
+    }
 
-        // Calculate current file based on total records
-        let current_file = (self.metadata.total_records as usize / RECORDS_PER_FILE) as u32;
-        let file_name = format!("data_{}.cdb", current_file);
-        let file_path = self.path.join(&file_name);
+    /// Substitui o registro `doc_id` mais recente pelo valor retornado por `replace`,
+    /// reescrevendo apenas o arquivo de dados que o contém. Retorna `false` se não encontrar
+    fn replace_record<F>(&self, doc_id: &str, replace: F) -> Result<bool, ChainDBError>
+    where
+        F: FnOnce(&serde_json::Value) -> Result<serde_json::Value, ChainDBError>,
+    {
+        let liveness = self.liveness();
+        for file_index in self.data_file_indexes(true) {
+            let file_path = self.path.join(data_file_name(file_index));
+            if !file_path.exists() {
+                continue;
+            }
 
-        // Create or append to file
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(file_path)?;
+            let mut lines = BufReader::new(File::open(&file_path)?)
+                .lines()
+                .collect::<Result<Vec<_>, _>>()?;
 
-        // Convert encrypted data to base64
-        let base64_str = general_purpose::STANDARD.encode(&encrypted_record);
-        writeln!(file, "{}", base64_str)?;
+            // Do registro mais recente para o mais antigo
+            for (position, line) in lines.iter_mut().enumerate().rev() {
+                let decoded = general_purpose::STANDARD.decode(&*line)?;
+                let decrypted = self.encryption.decrypt(&decoded)?;
+                let record_value: serde_json::Value = serde_json::from_slice(&decrypted)?;
 
-        // Update metadata
-        self.metadata.current_file = current_file;
-        self.metadata.total_records += 1;
+                if record_value.get("doc_id").and_then(|id| id.as_str()) != Some(doc_id)
+                    || !liveness.is_live(Liveness::index(file_index, position), &record_value)
+                {
+                    continue;
+                }
 
-        // Save metadata
-        let metadata_json = serde_json::to_vec(&self.metadata)?;
-        let encrypted_metadata = self.encryption.encrypt(&metadata_json)?;
-        let metadata_path = self.path.join(METADATA_FILE);
-        fs::write(metadata_path, encrypted_metadata)?;
+                *line = self.encode_record(&replace(&record_value)?)?;
+                rewrite_data_file(&file_path, &lines)?;
+                return Ok(true);
+            }
+        }
 
-        // Emitir evento de persistência
+        Ok(false)
+    }
+
+    /// Emite um evento com os campos do registro (para `TableData`, o objeto `data`)
+    fn emit_record_event(&self, record: &T, emit: fn(&str, &str, Option<serde_json::Value>)) {
         if let Ok(record_value) = serde_json::to_value(record) {
             // Extrair o nome do banco de dados do caminho
             let db_name = self
@@ -161,31 +829,125 @@ where
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown");
 
-            // Extrair apenas os dados internos do campo 'data' se existir
-            let data_to_emit = if let Some(data_obj) = record_value.get("data") {
-                Some(data_obj.clone())
-            } else {
-                Some(record_value)
-            };
+            // Extrair apenas os campos do registro, conforme o layout do tipo
+            emit(
+                db_name,
+                table_name,
+                Some(T::LAYOUT.event_data(record_value)),
+            );
+        }
+    }
 
-            emit_table_persist(db_name, table_name, data_to_emit);
+    pub fn persist(&mut self, record: &T) -> Result<(), ChainDBError> {
+        let _lock = self.lock_for_write()?;
+        self.append_record(record).map(|_| ())
+    }
+
+    /// Grava o registro: atualiza o documento com o doc_id do registro (`Record::doc_id`) ou,
+    /// se o registro não tiver doc_id, acrescenta um novo documento.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(DocId)` - O doc_id do documento gravado
+    /// * `Err(ChainDBError::RecordNotFound)` - Se o doc_id do registro não existir na tabela
+    pub fn save(&mut self, record: &T) -> Result<DocId, ChainDBError> {
+        match record.doc_id() {
+            Some(doc_id) => {
+                let doc_id = doc_id.to_string();
+                self.update(record, &doc_id, None)?;
+                Ok(doc_id)
+            }
+            None => {
+                let _lock = self.lock_for_write()?;
+                self.append_record(record)
+            }
         }
+    }
 
-        Ok(())
+    /// Acrescenta o registro ao último arquivo de dados e retorna seu doc_id. Quem chama deve
+    /// manter o lock exclusivo da tabela
+    fn append_record(&mut self, record: &T) -> Result<String, ChainDBError> {
+        // Converter o registro para Value para adicionar o doc_id
+        let mut record_value = serde_json::to_value(record)?;
+        println!("Record before adding doc_id: {:?}", record_value);
+
+        // Gerar um UUID v4 único para o documento, descartando qualquer doc_id informado pelo usuário.
+        // Todo documento começa na revisão 1
+        let doc_id = Uuid::new_v4().to_string();
+        let stamp = WriteStamp::created(self.user.as_deref());
+        assign_system_fields(&mut record_value, T::LAYOUT, &doc_id, 1, &stamp);
+
+        println!("Record after adding doc_id: {:?}", record_value);
+
+        // Converter de volta para bytes
+        let record_json = serde_json::to_vec(&record_value)?;
+        let encrypted_record = self.encryption.encrypt(&record_json)?;
+
+        // Calculate current file based on total records
+        let current_file = (self.metadata_mut().total_records as usize / RECORDS_PER_FILE) as u32;
+        let file_name = format!("data_{}.cdb", current_file);
+        let file_path = self.path.join(&file_name);
+
+        // Create or append to file
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)?;
+
+        // Convert encrypted data to base64
+        let base64_str = general_purpose::STANDARD.encode(&encrypted_record);
+        writeln!(file, "{}", base64_str)?;
+
+        // Update metadata
+        let metadata = self.metadata_mut();
+        metadata.current_file = current_file;
+        metadata.total_records += 1;
+
+        // Save metadata
+        self.save_metadata()?;
+
+        // Emitir evento de persistência
+        self.emit_record_event(record, emit_table_persist);
+
+        Ok(doc_id)
     }
 
-    pub fn update(&mut self, record: &T, doc_id: &str) -> Result<(), ChainDBError> {
-        if self.metadata.total_records == 0 {
+    /// Substitui o registro `doc_id` e incrementa sua revisão.
+    ///
+    /// # Argumentos
+    ///
+    /// * `record` - O novo conteúdo do registro
+    /// * `doc_id` - O doc_id do registro a ser atualizado
+    /// * `expected_revision` - Se informado, a atualização só acontece se o registro ainda
+    ///   estiver nessa revisão (controle de concorrência otimista)
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(u64)` - A nova revisão do registro
+    /// * `Err(ChainDBError::RevisionConflict)` - Se o registro estiver em outra revisão
+    /// * `Err(ChainDBError::RecordNotFound)` - Se o registro não existir
+    pub fn update(
+        &mut self,
+        record: &T,
+        doc_id: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<u64, ChainDBError> {
+        let _lock = self.lock_for_write()?;
+        let metadata = self.metadata();
+
+        if metadata.total_records == 0 {
             return Err(ChainDBError::RecordNotFound(
                 "No records exist in this table".to_string(),
             ));
         }
 
-        // Procura o registro específico pelo doc_id
+        // Procura o registro específico pelo doc_id. Documentos expirados não podem ser alterados
+        let liveness = self.liveness();
         let mut found = false;
+        let mut new_revision = 0;
 
         // Iterar por todos os arquivos de dados, do mais recente para o mais antigo
-        for file_index in (0..=self.metadata.current_file).rev() {
+        for file_index in (0..=metadata.current_file).rev() {
             let file_name = format!("data_{}.cdb", file_index);
             let file_path = self.path.join(&file_name);
 
@@ -199,38 +961,34 @@ where
             let mut updated_lines = Vec::new();
             let mut file_modified = false;
 
-            for line in reader.lines() {
+            for (position, line) in reader.lines().enumerate() {
                 let line = line?;
                 let decoded = general_purpose::STANDARD.decode(&line)?;
                 let decrypted = self.encryption.decrypt(&decoded)?;
                 let record_value: serde_json::Value = serde_json::from_slice(&decrypted)?;
+                let live = liveness.is_live(Liveness::index(file_index, position), &record_value);
 
                 // Verificar se este é o registro que estamos procurando
                 if let Some(record_doc_id) = record_value.get("doc_id") {
                     if let Some(id_str) = record_doc_id.as_str() {
-                        if id_str == doc_id {
+                        if id_str == doc_id && live {
                             // Encontramos o registro, vamos atualizá-lo
                             // Converter o registro para Value para preservar o doc_id
+                            let current_revision = record_revision(&record_value);
+                            check_revision(doc_id, expected_revision, current_revision)?;
+                            new_revision = current_revision + 1;
+
                             let mut updated_value = serde_json::to_value(record)?;
 
-                            // Remover qualquer doc_id que o usuário tenha tentado incluir
-                            if let serde_json::Value::Object(ref mut map) = &mut updated_value {
-                                // Se houver um campo data que é um objeto, remover doc_id dele também
-                                if let Some(serde_json::Value::Object(ref mut data_map)) =
-                                    map.get_mut("data")
-                                {
-                                    data_map.remove("doc_id");
-                                }
-
-                                // Remover doc_id do objeto raiz
-                                map.remove("doc_id");
-
-                                // Preservar o doc_id original
-                                map.insert(
-                                    "doc_id".to_string(),
-                                    serde_json::Value::String(doc_id.to_string()),
-                                );
-                            }
+                            // Preservar o doc_id original, ignorando qualquer doc_id do usuário
+                            let stamp = WriteStamp::updated(&record_value, self.user.as_deref());
+                            assign_system_fields(
+                                &mut updated_value,
+                                T::LAYOUT,
+                                doc_id,
+                                new_revision,
+                                &stamp,
+                            );
 
                             // Criptografar e adicionar à lista de linhas atualizadas
                             let updated_json = serde_json::to_vec(&updated_value)?;
@@ -251,10 +1009,7 @@ where
 
             // Se o arquivo foi modificado, escrever as alterações de volta
             if file_modified {
-                let mut file = fs::File::create(&file_path)?;
-                for line in updated_lines {
-                    writeln!(file, "{}", line)?;
-                }
+                rewrite_data_file(&file_path, &updated_lines)?;
                 break;
             }
         }
@@ -268,33 +1023,315 @@ where
         }
 
         // Emitir evento de atualização
-        if let Ok(record_value) = serde_json::to_value(record) {
-            // Extrair o nome do banco de dados do caminho
-            let db_name = self
-                .path
-                .parent()
-                .and_then(|p| p.file_name())
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown");
+        self.emit_record_event(record, emit_table_update);
 
-            // Extrair o nome da tabela do caminho
-            let table_name = self
-                .path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown");
+        Ok(new_revision)
+    }
 
-            // Extrair apenas os dados internos do campo 'data' se existir
-            let data_to_emit = if let Some(data_obj) = record_value.get("data") {
-                Some(data_obj.clone())
-            } else {
-                Some(record_value)
+    /// Aplica uma alteração parcial (merge patch ou JSON Patch) ao registro `doc_id` e
+    /// incrementa sua revisão. O patch se aplica aos campos do registro conforme seu layout
+    /// (`TableData` é alterado dentro do objeto `data`); os campos de sistema (doc_id, revision)
+    /// não podem ser alterados.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok((T, u64))` - O registro alterado e sua nova revisão
+    /// * `Err(ChainDBError::PatchError)` - Se o patch falhar ou o resultado não for um `T` válido
+    /// * `Err(ChainDBError::RevisionConflict)` - Se o registro não estiver em `expected_revision`
+    /// * `Err(ChainDBError::RecordNotFound)` - Se o registro não existir
+    pub fn patch(
+        &mut self,
+        doc_id: &str,
+        patch: &Patch,
+        expected_revision: Option<u64>,
+    ) -> Result<(T, u64), ChainDBError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut patched = None;
+        let found = self.replace_record(doc_id, |stored| {
+            let revision = record_revision(stored);
+            check_revision(doc_id, expected_revision, revision)?;
+
+            let stamp = WriteStamp::updated(stored, self.user.as_deref());
+            let mut document = stored.clone();
+            if let Some(document_obj) = document.as_object_mut() {
+                for field in SYSTEM_FIELDS {
+                    document_obj.remove(*field);
+                }
+            }
+            // O patch se aplica aos campos do registro (para `TableData`, o objeto `data`)
+            let fields = match T::LAYOUT {
+                FieldLayout::Nested(key) => document.get_mut(key).filter(|f| f.is_object()),
+                FieldLayout::Root => None,
             };
+            match fields {
+                Some(fields) => patch.apply(fields)?,
+                None => patch.apply(&mut document)?,
+            }
 
-            emit_table_update(db_name, table_name, data_to_emit);
+            assign_system_fields(&mut document, T::LAYOUT, doc_id, revision + 1, &stamp);
+            let record: T = serde_json::from_value(document.clone()).map_err(|e| {
+                ChainDBError::PatchError(format!("Patched document is not a valid record: {}", e))
+            })?;
+            patched = Some((record, revision + 1));
+            Ok(document)
+        })?;
+
+        match patched {
+            Some((record, revision)) if found => {
+                self.emit_record_event(&record, emit_table_update);
+                Ok((record, revision))
+            }
+            _ => Err(ChainDBError::RecordNotFound(format!(
+                "Record with doc_id {} not found",
+                doc_id
+            ))),
         }
+    }
 
-        Ok(())
+    /// Substitui o documento que atende a `criteria` (igualdade de campos, como em
+    /// `find_where`) por `record` ou, se nenhum atender, grava `record` como um novo documento.
+    /// A busca e a gravação acontecem sob o mesmo lock exclusivo, então duas chamadas
+    /// concorrentes com a mesma chave não criam documentos duplicados. Se vários documentos
+    /// atenderem, o mais recente é atualizado.
+    ///
+    /// `record` substitui o documento encontrado sem que `criteria` seja conferido de novo: se
+    /// ele trouxer outros valores nos campos da chave, o documento passa a ter esses valores e
+    /// um próximo upsert com a chave antiga não o encontrará mais.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(UpsertResult)` - O doc_id e a revisão do documento, e se ele foi criado ou atualizado
+    /// * `Err(ChainDBError::ValidationError)` - Se `criteria` estiver vazio
+    pub fn upsert(
+        &mut self,
+        criteria: HashMap<String, serde_json::Value>,
+        record: &T,
+    ) -> Result<UpsertResult, ChainDBError> {
+        if criteria.is_empty() {
+            return Err(ChainDBError::ValidationError(
+                "Upsert requires at least one criterion".to_string(),
+            ));
+        }
+        let criteria = criteria
+            .iter()
+            .map(|(field, value)| CompiledCriterion::new(field, &ComparisonOperator::Eq, value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let _lock = self.lock_for_write()?;
+
+        let mut existing = None;
+        self.scan_files(true, |record_value, _| {
+            if matches_criteria_advanced(&record_value, T::LAYOUT, &criteria) {
+                existing = record_value
+                    .get("doc_id")
+                    .and_then(|doc_id| doc_id.as_str())
+                    .map(String::from);
+                return Ok(existing.is_none());
+            }
+            Ok(true)
+        })?;
+
+        let Some(doc_id) = existing else {
+            let doc_id = self.append_record(record)?;
+            return Ok(UpsertResult {
+                action: UpsertAction::Inserted,
+                doc_id,
+                revision: 1,
+            });
+        };
+
+        let mut revision = 0;
+        self.replace_record(&doc_id, |stored| {
+            revision = record_revision(stored) + 1;
+            let stamp = WriteStamp::updated(stored, self.user.as_deref());
+            let mut updated_value = serde_json::to_value(record)?;
+            assign_system_fields(&mut updated_value, T::LAYOUT, &doc_id, revision, &stamp);
+            Ok(updated_value)
+        })?;
+        self.emit_record_event(record, emit_table_update);
+
+        Ok(UpsertResult {
+            action: UpsertAction::Updated,
+            doc_id,
+            revision,
+        })
+    }
+
+    /// Grava vários registros de uma vez. Cada arquivo de dados é aberto uma única vez e os
+    /// metadados são salvos apenas no final.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Vec<Result<String, ChainDBError>>)` - O doc_id gerado para cada registro, na mesma
+    ///   ordem de `records`, ou o erro que impediu sua gravação
+    /// * `Err(ChainDBError)` - Se a gravação dos arquivos falhar
+    pub fn persist_many(
+        &mut self,
+        records: &[T],
+    ) -> Result<Vec<Result<String, ChainDBError>>, ChainDBError> {
+        let _lock = self.lock_for_write()?;
+
+        let stamp = WriteStamp::created(self.user.as_deref());
+        let mut results = Vec::with_capacity(records.len());
+        let mut lines = Vec::with_capacity(records.len());
+        for record in records {
+            let encoded = serde_json::to_value(record)
+                .map_err(ChainDBError::from)
+                .and_then(|mut record_value| {
+                    let doc_id = Uuid::new_v4().to_string();
+                    assign_system_fields(&mut record_value, T::LAYOUT, &doc_id, 1, &stamp);
+                    let line = self.encode_record(&record_value)?;
+                    Ok((doc_id, line))
+                });
+
+            match encoded {
+                Ok((doc_id, line)) => {
+                    lines.push(line);
+                    results.push(Ok(doc_id));
+                }
+                Err(e) => results.push(Err(e)),
+            }
+        }
+
+        self.append_lines(&lines)?;
+
+        for (record, result) in records.iter().zip(&results) {
+            if result.is_ok() {
+                self.emit_record_event(record, emit_table_persist);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Acrescenta linhas já criptografadas aos arquivos de dados, abrindo cada arquivo uma única
+    /// vez, e salva os metadados. Quem chama deve manter o lock exclusivo da tabela
+    fn append_lines(&mut self, lines: &[String]) -> Result<(), ChainDBError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        // Agrupar as linhas por arquivo de dados, respeitando RECORDS_PER_FILE
+        let mut total_records = self.metadata_mut().total_records as usize;
+        let mut remaining = lines;
+        while !remaining.is_empty() {
+            let file_index = total_records / RECORDS_PER_FILE;
+            let free = RECORDS_PER_FILE - total_records % RECORDS_PER_FILE;
+            let (chunk, rest) = remaining.split_at(free.min(remaining.len()));
+
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path.join(data_file_name(file_index as u32)))?;
+            let mut writer = BufWriter::new(file);
+            for line in chunk {
+                writeln!(writer, "{}", line)?;
+            }
+            writer.flush()?;
+
+            total_records += chunk.len();
+            remaining = rest;
+        }
+
+        let metadata = self.metadata_mut();
+        metadata.total_records = total_records as u64;
+        metadata.current_file = ((total_records - 1) / RECORDS_PER_FILE) as u32;
+        self.save_metadata()
+    }
+
+    /// Atualiza vários registros de uma vez, reescrevendo cada arquivo de dados afetado uma
+    /// única vez. Um doc_id repetido é atualizado na ordem em que aparece.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Vec<Result<u64, ChainDBError>>)` - A nova revisão de cada registro, na mesma ordem
+    ///   de `updates`, ou o erro (por exemplo `RecordNotFound`) que impediu sua atualização
+    /// * `Err(ChainDBError)` - Se a leitura ou gravação dos arquivos falhar
+    pub fn update_many(
+        &mut self,
+        updates: &[(String, T)],
+    ) -> Result<Vec<Result<u64, ChainDBError>>, ChainDBError> {
+        let _lock = self.lock_for_write()?;
+
+        let mut results: Vec<Result<u64, ChainDBError>> = Vec::with_capacity(updates.len());
+        // doc_id -> (índices em `updates`, novos valores)
+        let mut pending: HashMap<&str, Vec<(usize, serde_json::Value)>> = HashMap::new();
+        for (index, (doc_id, record)) in updates.iter().enumerate() {
+            match serde_json::to_value(record) {
+                Ok(value) => {
+                    pending
+                        .entry(doc_id.as_str())
+                        .or_default()
+                        .push((index, value));
+                    results.push(Err(ChainDBError::RecordNotFound(format!(
+                        "Record with doc_id {} not found",
+                        doc_id
+                    ))));
+                }
+                Err(e) => results.push(Err(e.into())),
+            }
+        }
+
+        // Do arquivo mais recente para o mais antigo, como em `update`
+        let liveness = self.liveness();
+        for file_index in self.data_file_indexes(true) {
+            if pending.is_empty() {
+                break;
+            }
+
+            let file_path = self.path.join(data_file_name(file_index));
+            if !file_path.exists() {
+                continue;
+            }
+
+            let mut lines = Vec::new();
+            let mut file_modified = false;
+            for (position, line) in BufReader::new(File::open(&file_path)?).lines().enumerate() {
+                let line = line?;
+                let decoded = general_purpose::STANDARD.decode(&line)?;
+                let decrypted = self.encryption.decrypt(&decoded)?;
+                let record_value: serde_json::Value = serde_json::from_slice(&decrypted)?;
+
+                if !liveness.is_live(Liveness::index(file_index, position), &record_value) {
+                    lines.push(line);
+                    continue;
+                }
+                let Some(doc_id) = record_value.get("doc_id").and_then(|id| id.as_str()) else {
+                    lines.push(line);
+                    continue;
+                };
+                let Some(values) = pending.remove(doc_id) else {
+                    lines.push(line);
+                    continue;
+                };
+
+                let mut revision = record_revision(&record_value);
+                let stamp = WriteStamp::updated(&record_value, self.user.as_deref());
+                let mut latest = None;
+                for (index, mut value) in values {
+                    revision += 1;
+                    assign_system_fields(&mut value, T::LAYOUT, doc_id, revision, &stamp);
+                    latest = Some(value);
+                    results[index] = Ok(revision);
+                }
+                // `pending` só guarda listas não vazias
+                lines.push(self.encode_record(&latest.unwrap_or(record_value))?);
+                file_modified = true;
+            }
+
+            if file_modified {
+                rewrite_data_file(&file_path, &lines)?;
+            }
+        }
+
+        for ((_, record), result) in updates.iter().zip(&results) {
+            if result.is_ok() {
+                self.emit_record_event(record, emit_table_update);
+            }
+        }
+
+        Ok(results)
     }
 
     /// Recupera um registro específico pelo índice.
@@ -309,7 +1346,9 @@ where
     /// * `Err(ChainDBError)` - Se o índice for inválido ou ocorrer um erro durante a leitura
     ///
     pub fn get(&self, index: u64) -> Result<T, ChainDBError> {
-        if index >= self.metadata.total_records {
+        let _lock = self.lock_for_read()?;
+
+        if index >= self.metadata().total_records {
             return Err(ChainDBError::SerializationError(
                 "Record not found".to_string(),
             ));
@@ -321,6 +1360,13 @@ where
         let file_name = format!("data_{}.cdb", file_index);
         let file_path = self.path.join(&file_name);
 
+        // Arquivos com todos os registros expirados são removidos pela limpeza
+        if !file_path.exists() {
+            return Err(ChainDBError::SerializationError(
+                "Record not found".to_string(),
+            ));
+        }
+
         let file = File::open(file_path)?;
         let reader = BufReader::new(file);
 
@@ -331,7 +1377,11 @@ where
                     .decode(line)
                     .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
                 let decrypted_data = self.encryption.decrypt(&encrypted_data)?;
-                return Ok(serde_json::from_slice(&decrypted_data)?);
+                let record_value: serde_json::Value = serde_json::from_slice(&decrypted_data)?;
+                if !self.liveness().is_live(index, &record_value) {
+                    break;
+                }
+                return Ok(serde_json::from_value(record_value)?);
             }
         }
 
@@ -352,117 +1402,90 @@ where
     /// * `Err(ChainDBError)` - Se ocorrer um erro durante a leitura dos registros
     ///
     /// # Notas
-    ///
-    /// * Se o número total de registros for menor que o limite, retorna todos os registros disponíveis
-    /// * Se não houver registros, retorna um vetor vazio
-    /// * Os registros são lidos de múltiplos arquivos se necessário
-    pub fn get_history(&self, limit: usize) -> Result<Vec<T>, ChainDBError> {
-        if self.metadata.total_records == 0 {
-            return Ok(Vec::new());
-        }
-
-        let mut records = Vec::new();
-        let mut remaining = limit.min(self.metadata.total_records as usize);
-        let mut current_file = self.metadata.current_file;
-
-        while remaining > 0 && current_file <= self.metadata.current_file {
-            let file_name = format!("data_{}.cdb", current_file);
-            let file_path = self.path.join(&file_name);
-
-            if !file_path.exists() {
-                current_file = current_file.wrapping_sub(1);
-                continue;
-            }
-
-            let file = File::open(&file_path)?;
-            let mut reader = BufReader::new(file);
-            let mut lines = Vec::with_capacity(remaining);
-
-            // Lê as linhas do final do arquivo
-            reader.seek(SeekFrom::End(0))?;
-            let mut position = reader.stream_position()?;
-            let mut line = String::new();
-
-            while position > 0 && remaining > 0 {
-                let mut found_newline = false;
-                while position > 0 && !found_newline {
-                    position -= 1;
-                    reader.seek(SeekFrom::Start(position))?;
-                    let byte = &mut [0];
-                    reader.read_exact(byte)?;
-                    found_newline = byte[0] == b'\n';
-                }
-
-                if position == 0 {
-                    // Estamos no início do arquivo
-                    reader.seek(SeekFrom::Start(0))?;
-                } else {
-                    // Volta uma posição antes do \n para ler a linha completa
-                    reader.seek(SeekFrom::Start(position + 1))?;
-                }
-
-                // Lê a linha atual
-                line.clear();
-                reader.read_line(&mut line)?;
-
-                // Remove caracteres de controle do final
-                if line.ends_with('\n') {
-                    line.pop();
-                }
-                if line.ends_with('\r') {
-                    line.pop();
-                }
-
-                if !line.is_empty() {
-                    lines.push(line.clone());
-                    remaining -= 1;
-                }
-
-                if position > 0 {
-                    position -= 1;
-                }
-            }
-
-            // Processa as linhas
-            for line in lines.iter() {
-                let encrypted_data = general_purpose::STANDARD
-                    .decode(line)
-                    .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
-                let decrypted_data = self.encryption.decrypt(&encrypted_data)?;
-                let record: T = serde_json::from_slice(&decrypted_data)?;
-                records.push(record);
-            }
-
-            current_file = current_file.wrapping_sub(1);
+    ///
+    /// * Se o número total de registros for menor que o limite, retorna todos os registros disponíveis
+    /// * Se não houver registros, retorna um vetor vazio
+    /// * Os registros são lidos de múltiplos arquivos se necessário
+    pub fn get_history(&self, limit: usize) -> Result<Vec<T>, ChainDBError> {
+        let mut records = Vec::new();
+        if limit == 0 {
+            return Ok(records);
         }
 
+        // Os registros expirados são ignorados pela leitura
+        self.scan(true, |record_value| {
+            records.push(serde_json::from_value(record_value)?);
+            Ok(records.len() < limit)
+        })?;
+
         Ok(records)
     }
 
-    pub fn get_table(&self) -> Result<T, ChainDBError> {
-        if self.metadata.total_records == 0 {
-            return Err(ChainDBError::SerializationError(
-                "No records found".to_string(),
-            ));
-        }
+    /// Iterador preguiçoso sobre todos os registros da tabela, do mais antigo para o mais recente.
+    ///
+    /// Os arquivos de dados são lidos um de cada vez e cada registro é decriptado somente quando
+    /// pedido, então a memória usada não depende do tamanho da tabela. Registros expirados são
+    /// ignorados e os persistidos depois da criação do iterador não são retornados.
+    pub fn iter(&self) -> TableIter<T> {
+        self.records(false)
+    }
 
-        let file_name = format!("data_{}.cdb", self.metadata.current_file);
-        let file_path = self.path.join(&file_name);
+    /// Igual a `iter`, do registro mais recente para o mais antigo
+    pub fn iter_rev(&self) -> TableIter<T> {
+        self.records(true)
+    }
 
-        let content = fs::read_to_string(&file_path)?;
-        let last_line = content
-            .lines()
-            .last()
-            .ok_or_else(|| ChainDBError::SerializationError("No records found".to_string()))?;
+    /// Versão assíncrona de `iter`: um `Stream` alimentado por uma tarefa bloqueante do Tokio,
+    /// que lê poucos registros à frente do consumidor
+    pub fn stream(&self) -> impl Stream<Item = Result<T, ChainDBError>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        self.iter().into_stream()
+    }
+
+    /// Versão assíncrona de `iter_rev`
+    pub fn stream_rev(&self) -> impl Stream<Item = Result<T, ChainDBError>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        self.iter_rev().into_stream()
+    }
 
-        let encrypted_data = general_purpose::STANDARD
-            .decode(last_line)
-            .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
-        let decrypted_data = self.encryption.decrypt(&encrypted_data)?;
+    /// Iterador sobre os registros em qualquer formato desserializável, por exemplo
+    /// `serde_json::Value`
+    pub(crate) fn records<U>(&self, reverse: bool) -> TableIter<U>
+    where
+        U: for<'de> Deserialize<'de>,
+    {
+        // Só para recarregar os metadados: se falhar, o próprio iterador reporta o erro ao
+        // ler os arquivos
+        let _ = self.lock_for_read();
+        TableIter::new(
+            self.path.clone(),
+            self.encryption.clone(),
+            self.lock_timeout,
+            &self.metadata(),
+            T::LAYOUT,
+            reverse,
+        )
+    }
 
-        // Deserializar os dados diretamente, sem modificações
+    pub fn get_table(&self) -> Result<T, ChainDBError> {
+        // O registro mais recente que ainda não expirou
         // O doc_id já está incluído nos dados serializados
-        Ok(serde_json::from_slice(&decrypted_data)?)
+        let mut latest = None;
+        self.scan(true, |record_value| {
+            latest = Some(record_value);
+            Ok(false)
+        })?;
+
+        match latest {
+            Some(record_value) => Ok(serde_json::from_value(record_value)?),
+            None => Err(ChainDBError::SerializationError(
+                "No records found".to_string(),
+            )),
+        }
     }
 
     /// Busca registros que correspondem a critérios específicos.
@@ -495,19 +1518,23 @@ where
         // let resultados = tabela.findWhere(criteria, Some(10), true)?;
         // ```
 
-        if self.metadata.total_records == 0 {
+        let _lock = self.lock_for_read()?;
+        let metadata = self.metadata();
+
+        if metadata.total_records == 0 {
             return Ok(Vec::new());
         }
 
         println!("Iniciando busca findWhere com critérios: {:?}", criteria);
         println!("Limite: {:?}, Ordem reversa: {}", limit, reverse);
 
+        let liveness = self.liveness();
         let mut matching_records = Vec::new();
         let max_records = limit.unwrap_or(usize::MAX);
 
         if reverse {
             // Busca do mais recente para o mais antigo
-            let mut current_file = self.metadata.current_file;
+            let mut current_file = metadata.current_file;
 
             while matching_records.len() < max_records {
                 let file_name = format!("data_{}.cdb", current_file);
@@ -529,7 +1556,7 @@ where
                 let lines = lines?;
 
                 // Processa as linhas em ordem reversa
-                for line in lines.iter().rev() {
+                for (position, line) in lines.iter().enumerate().rev() {
                     if matching_records.len() >= max_records {
                         break;
                     }
@@ -545,7 +1572,9 @@ where
                     println!("record_value: {:?}", record_value);
 
                     // Verifica se o registro corresponde a todos os critérios
-                    if matches_criteria(&record_value, &criteria) {
+                    if liveness.is_live(Liveness::index(current_file, position), &record_value)
+                        && matches_criteria(&record_value, T::LAYOUT, &criteria)
+                    {
                         let record: T = serde_json::from_slice(&decrypted_data)?;
                         matching_records.push(record);
                         println!("record: {:?}", decrypted_data);
@@ -559,7 +1588,7 @@ where
             }
         } else {
             // Busca do mais antigo para o mais recente (código original)
-            for file_index in 0..=self.metadata.current_file {
+            for file_index in 0..=metadata.current_file {
                 if matching_records.len() >= max_records {
                     break;
                 }
@@ -575,7 +1604,7 @@ where
                 let reader = BufReader::new(file);
 
                 // Processa cada linha do arquivo
-                for line in reader.lines() {
+                for (position, line) in reader.lines().enumerate() {
                     if matching_records.len() >= max_records {
                         break;
                     }
@@ -590,7 +1619,9 @@ where
                     let record_value: serde_json::Value = serde_json::from_slice(&decrypted_data)?;
 
                     // Verifica se o registro corresponde a todos os critérios
-                    if matches_criteria(&record_value, &criteria) {
+                    if liveness.is_live(Liveness::index(file_index, position), &record_value)
+                        && matches_criteria(&record_value, T::LAYOUT, &criteria)
+                    {
                         let record: T = serde_json::from_slice(&decrypted_data)?;
                         matching_records.push(record);
                     }
@@ -630,21 +1661,31 @@ where
         // let resultados = tabela.findWhereAdvanced(criteria, Some(10), true)?;
         // ```
 
-        if self.metadata.total_records == 0 {
+        // Valida os operandos antes de percorrer os arquivos
+        let criteria = compile_criteria(&criteria)?;
+
+        let _lock = self.lock_for_read()?;
+        let metadata = self.metadata();
+
+        if metadata.total_records == 0 {
             return Ok(Vec::new());
         }
 
         println!("Iniciando busca findWhereAdvanced");
-        for (field, (op, value)) in &criteria {
-            println!("Critério: campo '{}' {:?} valor '{:?}'", field, op, value);
+        for criterion in &criteria {
+            println!(
+                "Critério: campo '{}' {:?} valor '{:?}'",
+                criterion.field, criterion.operator, criterion.value
+            );
         }
         println!("Limite: {:?}, Ordem reversa: {}", limit, reverse);
 
         let mut matching_records = Vec::new();
         let max_records = limit.unwrap_or(usize::MAX);
 
+        let liveness = self.liveness();
         let process_record =
-            |line: &str, matching_records: &mut Vec<T>| -> Result<(), ChainDBError> {
+            |index: u64, line: &str, matching_records: &mut Vec<T>| -> Result<(), ChainDBError> {
                 let encrypted_data = general_purpose::STANDARD
                     .decode(line)
                     .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
@@ -654,7 +1695,9 @@ where
                 let record_value: serde_json::Value = serde_json::from_slice(&decrypted_data)?;
 
                 // Verifica se o registro corresponde a todos os critérios
-                if matches_criteria_advanced(&record_value, &criteria) {
+                if liveness.is_live(index, &record_value)
+                    && matches_criteria_advanced(&record_value, T::LAYOUT, &criteria)
+                {
                     let record: T = serde_json::from_slice(&decrypted_data)?;
                     matching_records.push(record);
                 }
@@ -663,7 +1706,7 @@ where
 
         if reverse {
             // Busca do mais recente para o mais antigo
-            let mut current_file = self.metadata.current_file;
+            let mut current_file = metadata.current_file;
 
             while matching_records.len() < max_records {
                 let file_name = format!("data_{}.cdb", current_file);
@@ -685,11 +1728,12 @@ where
                 let lines = lines?;
 
                 // Processa as linhas em ordem reversa
-                for line in lines.iter().rev() {
+                for (position, line) in lines.iter().enumerate().rev() {
                     if matching_records.len() >= max_records {
                         break;
                     }
-                    process_record(line, &mut matching_records)?;
+                    let index = Liveness::index(current_file, position);
+                    process_record(index, line, &mut matching_records)?;
                 }
 
                 if current_file == 0 {
@@ -699,7 +1743,7 @@ where
             }
         } else {
             // Busca do mais antigo para o mais recente
-            for file_index in 0..=self.metadata.current_file {
+            for file_index in 0..=metadata.current_file {
                 if matching_records.len() >= max_records {
                     break;
                 }
@@ -715,68 +1759,525 @@ where
                 let reader = BufReader::new(file);
 
                 // Processa cada linha do arquivo
-                for line_result in reader.lines() {
+                for (position, line_result) in reader.lines().enumerate() {
                     if matching_records.len() >= max_records {
                         break;
                     }
                     let line = line_result?;
-                    process_record(&line, &mut matching_records)?;
+                    let index = Liveness::index(file_index, position);
+                    process_record(index, &line, &mut matching_records)?;
                 }
             }
         }
 
         Ok(matching_records)
     }
+
+    /// Busca paginada com ordenação e projeção de campos.
+    ///
+    /// # Argumentos
+    ///
+    /// * `criteria` - Critérios avançados (vazio para todos os registros), no mesmo formato de
+    ///   `find_where_advanced`.
+    /// * `options` - Ordenação, projeção, limite, offset/cursor, direção de leitura e contagem do total.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(QueryPage<T>)` - Os registros da página, o cursor da próxima página (se houver) e,
+    ///   com `options.count_total`, o total de registros encontrados
+    /// * `Err(ChainDBError)` - Se os critérios ou o cursor forem inválidos ou ocorrer um erro de leitura
+    ///
+    /// # Notas
+    ///
+    /// * O cursor guarda as chaves de ordenação e a posição do último registro da página, então
+    ///   registros gravados entre uma página e outra não deslocam a próxima. Ele só é aceito
+    ///   pela mesma consulta (critérios, ordenação e direção) que o gerou
+    /// * Sem ordenação e sem `count_total`, a leitura para assim que a página é preenchida e os
+    ///   registros antes do cursor não são decriptados
+    /// * Com ordenação ou `count_total`, a tabela é lida por completo
+    /// * Com projeção (`fields`), `T` precisa aceitar registros com campos ausentes (como `TableData`)
+    /// * Com `options.explain`, a página inclui o plano escolhido e as estatísticas de execução
+    pub fn query(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        options: &QueryOptions,
+    ) -> Result<QueryPage<T>, ChainDBError> {
+        let query = query_hash(&criteria, options);
+        let criteria = compile_criteria(&criteria)?;
+        let cursor = options
+            .cursor
+            .as_deref()
+            .map(|cursor| Cursor::decode(cursor, &query))
+            .transpose()?;
+        let skip = match cursor {
+            Some(_) => 0,
+            None => options.offset.unwrap_or(0),
+        };
+        // Um registro a mais indica se existe uma próxima página
+        let wanted = options
+            .limit
+            .map(|limit| skip.saturating_add(limit).saturating_add(1))
+            .unwrap_or(usize::MAX);
+        let sorted = !options.sort.is_empty();
+        let started_at = Instant::now();
+
+        let mut total = 0;
+        let mut matching_records = Vec::new();
+
+        // Sem ordenação, a posição do cursor já define o que vem depois dele
+        let after = match (&cursor, sorted || options.count_total) {
+            (Some(cursor), false) => Some(cursor.index()),
+            _ => None,
+        };
+        let mut stats =
+            self.scan_indexed(options.reverse, after, |index, record_value, file_stats| {
+                if !matches_criteria_advanced(&record_value, T::LAYOUT, &criteria) {
+                    return Ok(true);
+                }
+                total += 1;
+                file_stats.records_matched += 1;
+
+                let after_cursor = cursor.as_ref().is_none_or(|cursor| {
+                    cursor.follows(
+                        &record_value,
+                        index,
+                        T::LAYOUT,
+                        &options.sort,
+                        options.reverse,
+                    )
+                });
+                if after_cursor && (sorted || matching_records.len() < wanted) {
+                    matching_records.push((index, record_value));
+                }
+
+                Ok(sorted || options.count_total || matching_records.len() < wanted)
+            })?;
+
+        sort_indexed(&mut matching_records, T::LAYOUT, &options.sort);
+        let mut page: Vec<(u64, serde_json::Value)> = matching_records
+            .into_iter()
+            .skip(skip)
+            .take(wanted - skip)
+            .collect();
+
+        let has_more = options.limit.is_some_and(|limit| page.len() > limit);
+        if has_more {
+            page.pop();
+        }
+        let next_cursor = match page.last() {
+            Some((index, record_value)) if has_more => {
+                Some(Cursor::after(record_value, *index, T::LAYOUT, &options.sort, &query).encode())
+            }
+            _ => None,
+        };
+
+        let records = page
+            .into_iter()
+            .map(|(_, record_value)| {
+                let record_value = match &options.fields {
+                    Some(fields) => project_record(record_value, T::LAYOUT, fields),
+                    None => record_value,
+                };
+                serde_json::from_value(record_value)
+            })
+            .collect::<Result<Vec<T>, _>>()?;
+
+        let explain = if options.explain {
+            stats.elapsed_ms = duration_ms(started_at.elapsed());
+            Some(Explain {
+                plan: self.plan(options.reverse, criteria.len(), &options.sort),
+                stats,
+            })
+        } else {
+            None
+        };
+
+        Ok(QueryPage {
+            records,
+            total: options.count_total.then_some(total),
+            next_cursor,
+            explain,
+        })
+    }
+
+    /// Busca um registro pelo doc_id.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Some((T, u64)))` - O registro e sua revisão atual
+    /// * `Ok(None)` - Se não existir registro com esse doc_id
+    /// * `Err(ChainDBError)` - Se ocorrer um erro durante a leitura
+    pub fn get_by_doc_id(&self, doc_id: &str) -> Result<Option<(T, u64)>, ChainDBError> {
+        let mut found = None;
+
+        // Registros recentes costumam ser os mais consultados
+        self.scan(true, |record_value| {
+            if record_value.get("doc_id").and_then(|id| id.as_str()) == Some(doc_id) {
+                found = Some(record_value);
+                return Ok(false);
+            }
+            Ok(true)
+        })?;
+
+        match found {
+            Some(record_value) => {
+                let revision = record_revision(&record_value);
+                Ok(Some((serde_json::from_value(record_value)?, revision)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Busca um registro pelo doc_id, retornando-o com seu doc_id, como `find`.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Some((DocId, T)))` - O doc_id e o registro
+    /// * `Ok(None)` - Se não existir registro com esse doc_id
+    /// * `Err(ChainDBError)` - Se ocorrer um erro durante a leitura
+    pub fn get_doc(&self, doc_id: &str) -> Result<Option<(DocId, T)>, ChainDBError> {
+        Ok(self
+            .get_by_doc_id(doc_id)?
+            .map(|(record, _)| (doc_id.to_string(), record)))
+    }
+
+    /// Busca registros com critérios avançados, do mais recente para o mais antigo, retornando
+    /// cada um com seu doc_id. Os campos são procurados conforme o layout de `T`
+    /// (`Record::LAYOUT`), então structs simples são consultadas pelos próprios campos.
+    ///
+    /// # Argumentos
+    ///
+    /// * `criteria` - Critérios no mesmo formato de `find_where_advanced`
+    /// * `limit` - Número máximo de registros a serem retornados (opcional)
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Vec<(DocId, T)>)` - O doc_id e o registro de cada documento encontrado
+    /// * `Err(ChainDBError)` - Se os critérios forem inválidos ou ocorrer um erro de leitura
+    pub fn find(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(DocId, T)>, ChainDBError> {
+        let criteria = compile_criteria(&criteria)?;
+        let max_records = limit.unwrap_or(usize::MAX);
+        let mut found = Vec::new();
+
+        if max_records == 0 {
+            return Ok(found);
+        }
+
+        self.scan(true, |record_value| {
+            if matches_criteria_advanced(&record_value, T::LAYOUT, &criteria) {
+                let doc_id = record_value
+                    .get("doc_id")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                found.push((doc_id, serde_json::from_value(record_value)?));
+            }
+            Ok(found.len() < max_records)
+        })?;
+
+        Ok(found)
+    }
+
+    /// Calcula agregações (count, sum, avg, min, max, count distinct) em uma única leitura da tabela.
+    ///
+    /// # Argumentos
+    ///
+    /// * `criteria` - Critérios avançados para filtrar os registros (vazio para todos os registros).
+    /// * `group_by` - Campos usados para agrupar os registros (vazio para um único grupo).
+    /// * `aggregations` - As agregações calculadas para cada grupo.
+    ///
+    /// # Retornos
+    ///
+    /// * `Ok(Vec<AggregateGroup>)` - Um resultado por grupo, na ordem em que os grupos foram encontrados
+    /// * `Err(ChainDBError)` - Se os critérios ou as agregações forem inválidos ou ocorrer um erro de leitura
+    pub fn aggregate(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        group_by: &[String],
+        aggregations: &[Aggregation],
+    ) -> Result<Vec<AggregateGroup>, ChainDBError> {
+        self.aggregate_explain(criteria, group_by, aggregations)
+            .map(|(groups, _)| groups)
+    }
+
+    /// Igual a `aggregate`, retornando também o plano e as estatísticas de execução
+    pub fn aggregate_explain(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        group_by: &[String],
+        aggregations: &[Aggregation],
+    ) -> Result<(Vec<AggregateGroup>, Explain), ChainDBError> {
+        let criteria = compile_criteria(&criteria)?;
+        let mut aggregator = Aggregator::new(T::LAYOUT, group_by, aggregations)?;
+        let started_at = Instant::now();
+
+        let mut stats = self.scan_with_stats(false, |record_value, file_stats| {
+            if matches_criteria_advanced(&record_value, T::LAYOUT, &criteria) {
+                aggregator.add(&record_value);
+                file_stats.records_matched += 1;
+            }
+            Ok(true)
+        })?;
+
+        let groups = aggregator.finish();
+        stats.elapsed_ms = duration_ms(started_at.elapsed());
+
+        let explain = Explain {
+            plan: self.plan(false, criteria.len(), &[]),
+            stats,
+        };
+        Ok((groups, explain))
+    }
+
+    /// Descreve como uma consulta será executada: leitura completa dos arquivos de dados na
+    /// direção pedida, seguida de ordenação em memória quando `sort` não estiver vazio
+    pub fn plan(&self, reverse: bool, criteria: usize, sort: &[SortKey]) -> QueryPlan {
+        let files = self
+            .data_file_indexes(reverse)
+            .into_iter()
+            .map(|file_index| format!("data_{}.cdb", file_index))
+            .filter(|file_name| self.path.join(file_name).exists())
+            .collect();
+
+        QueryPlan {
+            access: AccessPath::FullScan,
+            direction: ScanDirection::from_reverse(reverse),
+            files,
+            criteria,
+            sort: sort.to_vec(),
+        }
+    }
+
+    /// Igual a `query`, mas produz os registros um a um a partir da leitura dos arquivos, sem
+    /// montar a página em memória. Aceita o cursor de `query`, mas não calcula o total nem o
+    /// cursor da próxima página.
+    ///
+    /// Com `sort`, todos os registros encontrados precisam ser lidos e ordenados antes do
+    /// primeiro ser retornado.
+    pub fn query_iter(
+        &self,
+        criteria: HashMap<String, (ComparisonOperator, serde_json::Value)>,
+        options: &QueryOptions,
+    ) -> Result<RecordIter<T>, ChainDBError>
+    where
+        T: Send + 'static,
+    {
+        let query = query_hash(&criteria, options);
+        let criteria = compile_criteria(&criteria)?;
+        let cursor = options
+            .cursor
+            .as_deref()
+            .map(|cursor| Cursor::decode(cursor, &query))
+            .transpose()?;
+        let skip = match cursor {
+            Some(_) => 0,
+            None => options.offset.unwrap_or(0),
+        };
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let fields = options.fields.clone();
+        let sort = options.sort.clone();
+        let reverse = options.reverse;
+
+        // Erros de leitura passam pelo filtro para chegar a quem consome o iterador
+        let mut records = self.records::<serde_json::Value>(options.reverse);
+        let matching =
+            std::iter::from_fn(move || records.next_indexed()).filter(move |record| match record {
+                Ok((index, record_value)) => {
+                    matches_criteria_advanced(record_value, T::LAYOUT, &criteria)
+                        && cursor.as_ref().is_none_or(|cursor| {
+                            cursor.follows(record_value, *index, T::LAYOUT, &sort, reverse)
+                        })
+                }
+                Err(_) => true,
+            });
+
+        let ordered: RecordIter<(u64, serde_json::Value)> = if options.sort.is_empty() {
+            Box::new(matching)
+        } else {
+            let mut records = matching.collect::<Result<Vec<_>, _>>()?;
+            sort_indexed(&mut records, T::LAYOUT, &options.sort);
+            Box::new(records.into_iter().map(Ok))
+        };
+
+        Ok(Box::new(ordered.skip(skip).take(limit).map(
+            move |record| {
+                let (_, record_value) = record?;
+                let record_value = match &fields {
+                    Some(fields) => project_record(record_value, T::LAYOUT, fields),
+                    None => record_value,
+                };
+                Ok(serde_json::from_value(record_value)?)
+            },
+        )))
+    }
+
+    /// Percorre todos os registros da tabela, arquivo por arquivo.
+    ///
+    /// `visit` recebe cada registro já decriptado e retorna `false` para interromper a leitura.
+    pub(crate) fn scan<F>(&self, reverse: bool, mut visit: F) -> Result<(), ChainDBError>
+    where
+        F: FnMut(serde_json::Value) -> Result<bool, ChainDBError>,
+    {
+        self.scan_with_stats(reverse, |record_value, _| visit(record_value))
+            .map(|_| ())
+    }
+
+    /// Percorre todos os registros da tabela, arquivo por arquivo, coletando estatísticas de
+    /// leitura por arquivo de dados.
+    ///
+    /// `visit` recebe cada registro já decriptado e as estatísticas do arquivo atual (para contar
+    /// os registros encontrados em `records_matched`), e retorna `false` para interromper a leitura.
+    pub(crate) fn scan_with_stats<F>(
+        &self,
+        reverse: bool,
+        visit: F,
+    ) -> Result<ExecutionStats, ChainDBError>
+    where
+        F: FnMut(serde_json::Value, &mut FileStats) -> Result<bool, ChainDBError>,
+    {
+        let _lock = self.lock_for_read()?;
+        self.scan_files(reverse, visit)
+    }
+
+    /// Igual a `scan_with_stats`, sem adquirir o lock da tabela (quem chama já o mantém)
+    fn scan_files<F>(&self, reverse: bool, mut visit: F) -> Result<ExecutionStats, ChainDBError>
+    where
+        F: FnMut(serde_json::Value, &mut FileStats) -> Result<bool, ChainDBError>,
+    {
+        self.scan_indexed(reverse, None, |_, record_value, file_stats| {
+            visit(record_value, file_stats)
+        })
+    }
+
+    /// Igual a `scan_files`, passando também a posição de cada registro na tabela.
+    ///
+    /// Com `after`, só os registros depois dessa posição na direção de leitura são visitados;
+    /// os anteriores são pulados sem serem decriptados.
+    fn scan_indexed<F>(
+        &self,
+        reverse: bool,
+        after: Option<u64>,
+        mut visit: F,
+    ) -> Result<ExecutionStats, ChainDBError>
+    where
+        F: FnMut(u64, serde_json::Value, &mut FileStats) -> Result<bool, ChainDBError>,
+    {
+        let liveness = self.liveness();
+        let mut stats = ExecutionStats::default();
+        let is_after = |index: u64| match after {
+            Some(after) if reverse => index < after,
+            Some(after) => index > after,
+            None => true,
+        };
+
+        for file_index in self.data_file_indexes(reverse) {
+            let file_name = format!("data_{}.cdb", file_index);
+            let file_path = self.path.join(&file_name);
+
+            if !file_path.exists() {
+                continue;
+            }
+
+            // Arquivos inteiros antes da posição pedida nem são lidos
+            let first_index = Liveness::index(file_index, 0);
+            let last_index = Liveness::index(file_index, RECORDS_PER_FILE - 1);
+            if !is_after(first_index) && !is_after(last_index) {
+                continue;
+            }
+
+            let started_at = Instant::now();
+            let mut file_stats = FileStats::new(&file_name);
+
+            let file = File::open(&file_path)?;
+            let reader = BufReader::new(file);
+            let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+            file_stats.bytes_read = lines.iter().map(|line| line.len() as u64 + 1).sum();
+            let mut positions: Vec<usize> = (0..lines.len()).collect();
+            if reverse {
+                positions.reverse();
+            }
+
+            let mut stop = false;
+            for position in positions {
+                let line = &lines[position];
+                let index = Liveness::index(file_index, position);
+                if line.is_empty() || !is_after(index) {
+                    continue;
+                }
+
+                let encrypted_data = general_purpose::STANDARD
+                    .decode(line)
+                    .map_err(|e| ChainDBError::SerializationError(e.to_string()))?;
+                let decrypted_data = self.encryption.decrypt(&encrypted_data)?;
+                let record_value: serde_json::Value = serde_json::from_slice(&decrypted_data)?;
+                file_stats.records_decrypted += 1;
+
+                // Documentos expirados ficam ocultos mesmo antes da limpeza
+                if !liveness.is_live(index, &record_value) {
+                    continue;
+                }
+
+                if !visit(index, record_value, &mut file_stats)? {
+                    stop = true;
+                    break;
+                }
+            }
+
+            file_stats.elapsed_ms = duration_ms(started_at.elapsed());
+            stats.add_file(file_stats);
+            if stop {
+                break;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Índices dos arquivos de dados na ordem de leitura
+    fn data_file_indexes(&self, reverse: bool) -> Vec<u32> {
+        let metadata = self.metadata();
+        if metadata.total_records == 0 {
+            return Vec::new();
+        }
+
+        if reverse {
+            (0..=metadata.current_file).rev().collect()
+        } else {
+            (0..=metadata.current_file).collect()
+        }
+    }
 }
 
 /// Função auxiliar para verificar se um registro corresponde aos critérios especificados
 fn matches_criteria(
     record: &serde_json::Value,
+    layout: FieldLayout,
     criteria: &HashMap<String, serde_json::Value>,
 ) -> bool {
     if let serde_json::Value::Object(record_obj) = record {
         // Verifica cada critério
         for (field, expected_value) in criteria {
-            // Primeiro verifica se o campo existe no objeto raiz (para doc_id)
-            if let Some(actual_value) = record_obj.get(field) {
-                // Se o valor do campo não corresponde ao critério, retorna false
-                if actual_value != expected_value {
-                    println!(
-                        "Campo '{}' no objeto raiz não corresponde. Esperado: {:?}, Atual: {:?}",
-                        field, expected_value, actual_value
-                    );
-                    return false;
-                }
-                // Se encontrou e corresponde, continua para o próximo critério
-                continue;
-            }
-
-            // Se não encontrou no objeto raiz, verifica no objeto data
-            if let Some(serde_json::Value::Object(data_obj)) = record_obj.get("data") {
-                match data_obj.get(field) {
-                    Some(actual_value) => {
-                        // Se o valor do campo não corresponde ao critério, retorna false
-                        if actual_value != expected_value {
-                            println!(
-                                "Campo '{}' no objeto data não corresponde. Esperado: {:?}, Atual: {:?}",
-                                field, expected_value, actual_value
-                            );
-                            return false;
-                        }
-                    }
-                    None => {
-                        // Se o campo não existe no registro, retorna false
-                        println!("Campo '{}' não encontrado no registro", field);
+            // Procura o campo na raiz (para doc_id) e depois nos campos do registro
+            match layout.resolve(record_obj, field) {
+                Some(actual_value) => {
+                    // Se o valor do campo não corresponde ao critério, retorna false
+                    if actual_value != expected_value {
+                        println!(
+                            "Campo '{}' não corresponde. Esperado: {:?}, Atual: {:?}",
+                            field, expected_value, actual_value
+                        );
                         return false;
                     }
                 }
-            } else {
-                // O registro não tem um campo "data" válido e o campo não foi encontrado no objeto raiz
-                println!(
-                    "Campo '{}' não encontrado no registro e não há objeto 'data' válido",
-                    field
-                );
-                return false;
+                None => {
+                    // Se o campo não existe no registro, retorna false
+                    println!("Campo '{}' não encontrado no registro", field);
+                    return false;
+                }
             }
         }
         // Todos os critérios foram atendidos
@@ -789,48 +2290,32 @@ fn matches_criteria(
     }
 }
 
+/// Valida e prepara os critérios avançados antes da leitura dos registros
+fn compile_criteria(
+    criteria: &HashMap<String, (ComparisonOperator, serde_json::Value)>,
+) -> Result<Vec<CompiledCriterion>, ChainDBError> {
+    criteria
+        .iter()
+        .map(|(field, (operator, value))| CompiledCriterion::new(field, operator, value))
+        .collect()
+}
+
 /// Função auxiliar para verificar se um registro corresponde aos critérios avançados especificados
 fn matches_criteria_advanced(
     record: &serde_json::Value,
-    criteria: &HashMap<String, (ComparisonOperator, serde_json::Value)>,
+    layout: FieldLayout,
+    criteria: &[CompiledCriterion],
 ) -> bool {
     if let serde_json::Value::Object(record_obj) = record {
         // Verifica cada critério
-        for (field, (operator, expected_value)) in criteria {
-            // Primeiro verifica se o campo existe no objeto raiz (para doc_id)
-            if let Some(actual_value) = record_obj.get(field) {
-                // Verifica se o valor do campo corresponde ao critério com o operador especificado
-                if !compare_values(actual_value, expected_value, operator) {
-                    println!("Campo '{}' no objeto raiz não corresponde com operador {:?}. Esperado: {:?}, Atual: {:?}", 
-                        field, operator, expected_value, actual_value);
-                    return false;
-                }
-                // Se encontrou e corresponde, continua para o próximo critério
-                continue;
-            }
+        for criterion in criteria {
+            let actual_value = layout.resolve(record_obj, &criterion.field);
 
-            // Se não encontrou no objeto raiz, verifica no objeto data
-            if let Some(serde_json::Value::Object(data_obj)) = record_obj.get("data") {
-                match data_obj.get(field) {
-                    Some(actual_value) => {
-                        // Verifica se o valor do campo corresponde ao critério com o operador especificado
-                        if !compare_values(actual_value, expected_value, operator) {
-                            println!("Campo '{}' no objeto data não corresponde com operador {:?}. Esperado: {:?}, Atual: {:?}", 
-                                field, operator, expected_value, actual_value);
-                            return false;
-                        }
-                    }
-                    None => {
-                        // Se o campo não existe no registro, retorna false
-                        println!("Campo '{}' não encontrado no registro", field);
-                        return false;
-                    }
-                }
-            } else {
-                // O registro não tem um campo "data" válido e o campo não foi encontrado no objeto raiz
+            // Verifica se o valor do campo corresponde ao critério com o operador especificado
+            if !criterion.matches(actual_value) {
                 println!(
-                    "Campo '{}' não encontrado no registro e não há objeto 'data' válido",
-                    field
+                    "Campo '{}' não corresponde com operador {:?}. Esperado: {:?}, Atual: {:?}",
+                    criterion.field, criterion.operator, criterion.value, actual_value
                 );
                 return false;
             }
@@ -845,6 +2330,43 @@ fn matches_criteria_advanced(
     }
 }
 
+/// Ordena dois valores JSON do mesmo tipo.
+///
+/// Números são comparados como `f64`. Strings que são datas RFC 3339 válidas são comparadas
+/// cronologicamente (respeitando o fuso horário); as demais strings são comparadas lexicograficamente.
+/// Retorna `None` para tipos diferentes ou não comparáveis.
+pub(crate) fn compare_ordering(a: &serde_json::Value, b: &serde_json::Value) -> Option<Ordering> {
+    match (a, b) {
+        (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
+            a.as_f64()?.partial_cmp(&b.as_f64()?)
+        }
+        (serde_json::Value::String(a), serde_json::Value::String(b)) => {
+            match (
+                DateTime::parse_from_rfc3339(a),
+                DateTime::parse_from_rfc3339(b),
+            ) {
+                (Ok(a_date), Ok(b_date)) => Some(a_date.cmp(&b_date)),
+                _ => Some(a.cmp(b)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Aplica uma operação de string ignorando maiúsculas/minúsculas
+fn compare_ignore_case(
+    actual: &serde_json::Value,
+    expected: &serde_json::Value,
+    op: impl Fn(&str, &str) -> bool,
+) -> bool {
+    match (actual, expected) {
+        (serde_json::Value::String(a), serde_json::Value::String(b)) => {
+            op(&a.to_lowercase(), &b.to_lowercase())
+        }
+        _ => false,
+    }
+}
+
 /// Compara dois valores JSON usando o operador especificado
 fn compare_values(
     actual: &serde_json::Value,
@@ -852,58 +2374,18 @@ fn compare_values(
     operator: &ComparisonOperator,
 ) -> bool {
     match operator {
-        ComparisonOperator::Eq => actual == expected,
-        ComparisonOperator::Ne => actual != expected,
-        ComparisonOperator::Gt => {
-            match (actual, expected) {
-                (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
-                    // Compara números
-                    if let (Some(a_f64), Some(b_f64)) = (a.as_f64(), b.as_f64()) {
-                        a_f64 > b_f64
-                    } else {
-                        false
-                    }
-                }
-                (serde_json::Value::String(a), serde_json::Value::String(b)) => {
-                    // Compara strings
-                    a > b
-                }
-                _ => false,
-            }
-        }
-        ComparisonOperator::Ge => match (actual, expected) {
-            (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
-                if let (Some(a_f64), Some(b_f64)) = (a.as_f64(), b.as_f64()) {
-                    a_f64 >= b_f64
-                } else {
-                    false
-                }
-            }
-            (serde_json::Value::String(a), serde_json::Value::String(b)) => a >= b,
-            _ => false,
-        },
-        ComparisonOperator::Lt => match (actual, expected) {
-            (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
-                if let (Some(a_f64), Some(b_f64)) = (a.as_f64(), b.as_f64()) {
-                    a_f64 < b_f64
-                } else {
-                    false
-                }
-            }
-            (serde_json::Value::String(a), serde_json::Value::String(b)) => a < b,
-            _ => false,
-        },
-        ComparisonOperator::Le => match (actual, expected) {
-            (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
-                if let (Some(a_f64), Some(b_f64)) = (a.as_f64(), b.as_f64()) {
-                    a_f64 <= b_f64
-                } else {
-                    false
-                }
-            }
-            (serde_json::Value::String(a), serde_json::Value::String(b)) => a <= b,
-            _ => false,
-        },
+        ComparisonOperator::Eq => values_equal(actual, expected),
+        ComparisonOperator::Ne => !values_equal(actual, expected),
+        ComparisonOperator::Gt => compare_ordering(actual, expected) == Some(Ordering::Greater),
+        ComparisonOperator::Ge => matches!(
+            compare_ordering(actual, expected),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+        ComparisonOperator::Lt => compare_ordering(actual, expected) == Some(Ordering::Less),
+        ComparisonOperator::Le => matches!(
+            compare_ordering(actual, expected),
+            Some(Ordering::Less | Ordering::Equal)
+        ),
         ComparisonOperator::Contains => {
             match (actual, expected) {
                 (serde_json::Value::String(a), serde_json::Value::String(b)) => {
@@ -912,7 +2394,7 @@ fn compare_values(
                 }
                 (serde_json::Value::Array(a), _) => {
                     // Verifica se o array contém o valor
-                    a.contains(expected)
+                    a.iter().any(|item| values_equal(item, expected))
                 }
                 _ => false,
             }
@@ -935,5 +2417,58 @@ fn compare_values(
                 _ => false,
             }
         }
+        ComparisonOperator::EqIgnoreCase => compare_ignore_case(actual, expected, |a, b| a == b),
+        ComparisonOperator::ContainsIgnoreCase => {
+            compare_ignore_case(actual, expected, |a, b| a.contains(b))
+        }
+        ComparisonOperator::StartsWithIgnoreCase => {
+            compare_ignore_case(actual, expected, |a, b| a.starts_with(b))
+        }
+        ComparisonOperator::EndsWithIgnoreCase => {
+            compare_ignore_case(actual, expected, |a, b| a.ends_with(b))
+        }
+        ComparisonOperator::In => match expected {
+            serde_json::Value::Array(options) => {
+                options.iter().any(|option| values_equal(actual, option))
+            }
+            _ => false,
+        },
+        ComparisonOperator::NotIn => match expected {
+            serde_json::Value::Array(options) => {
+                !options.iter().any(|option| values_equal(actual, option))
+            }
+            _ => false,
+        },
+        ComparisonOperator::Between => match expected.as_array().map(Vec::as_slice) {
+            Some([min, max]) => {
+                matches!(
+                    compare_ordering(actual, min),
+                    Some(Ordering::Greater | Ordering::Equal)
+                ) && matches!(
+                    compare_ordering(actual, max),
+                    Some(Ordering::Less | Ordering::Equal)
+                )
+            }
+            _ => false,
+        },
+        // Avaliados em `CompiledCriterion::matches`, que tem a expressão compilada e sabe se o
+        // campo existe
+        ComparisonOperator::Regex
+        | ComparisonOperator::Exists
+        | ComparisonOperator::NotExists
+        | ComparisonOperator::IsNull => false,
+    }
+}
+
+/// Igualdade entre valores JSON em que números são comparados pelo valor, então `50` e
+/// `50.0` são iguais. Inteiros continuam sendo comparados sem conversão para `f64`
+fn values_equal(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    match (a, b) {
+        (serde_json::Value::Number(a), serde_json::Value::Number(b))
+            if a.is_f64() || b.is_f64() =>
+        {
+            a.as_f64() == b.as_f64()
+        }
+        _ => a == b,
     }
 }
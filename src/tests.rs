@@ -57,7 +57,7 @@ mod tests {
         new_data
             .data
             .insert("test_key".to_string(), serde_json::json!("updated_value"));
-        assert!(table.update(&new_data, &doc_id).is_ok());
+        assert!(table.update(&new_data, &doc_id, None).is_ok());
 
         // Testa get_history
         let history = table.get_history(50).unwrap();
@@ -71,4 +71,2614 @@ mod tests {
         // Limpa os arquivos de teste
         std::fs::remove_dir_all(".chain_db_data/test_db").unwrap();
     }
+
+    #[test]
+    fn test_find_where_advanced_operators() {
+        use crate::table::ComparisonOperator;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_operators_db");
+        ChainDB::create_database("test_operators_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_operators_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("orders").unwrap();
+
+        for (name, total, ordered_at) in [
+            ("Alice Smith", 50, "2025-01-10T10:00:00Z"),
+            ("bob jones", 150, "2025-02-10T10:00:00+02:00"),
+            ("Carol SMITH", 300, "2025-03-10T10:00:00Z"),
+        ] {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("name".to_string(), serde_json::json!(name));
+            record
+                .data
+                .insert("total".to_string(), serde_json::json!(total));
+            record
+                .data
+                .insert("ordered_at".to_string(), serde_json::json!(ordered_at));
+            table.persist(&record).unwrap();
+        }
+        let mut record = TableData::new();
+        record
+            .data
+            .insert("name".to_string(), serde_json::Value::Null);
+        table.persist(&record).unwrap();
+
+        let find = |field: &str, operator: ComparisonOperator, value: serde_json::Value| {
+            let criteria = HashMap::from([(field.to_string(), (operator, value))]);
+            table.find_where_advanced(criteria, None, false)
+        };
+
+        let found = find(
+            "total",
+            ComparisonOperator::In,
+            serde_json::json!([50, 300]),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = find("total", ComparisonOperator::NotIn, serde_json::json!([50])).unwrap();
+        assert_eq!(found.len(), 2);
+
+        // Numbers are compared by value, so 50 and 50.0 are equal
+        let found = find("total", ComparisonOperator::Eq, serde_json::json!(50.0)).unwrap();
+        assert_eq!(found.len(), 1);
+        let found = find("total", ComparisonOperator::In, serde_json::json!([150.0])).unwrap();
+        assert_eq!(found.len(), 1);
+        let found = find(
+            "total",
+            ComparisonOperator::NotIn,
+            serde_json::json!([50.0]),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        // Regex only matches text values
+        let found = find("total", ComparisonOperator::Regex, serde_json::json!("5")).unwrap();
+        assert!(found.is_empty());
+
+        let found = find(
+            "total",
+            ComparisonOperator::Between,
+            serde_json::json!([100, 300]),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = find(
+            "ordered_at",
+            ComparisonOperator::Between,
+            serde_json::json!(["2025-02-01T00:00:00Z", "2025-02-28T00:00:00Z"]),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data.get("total").unwrap(), 150);
+
+        // 10:00+02:00 is 08:00Z, so it is earlier than 09:00Z on the same day
+        let found = find(
+            "ordered_at",
+            ComparisonOperator::Lt,
+            serde_json::json!("2025-02-10T09:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = find(
+            "name",
+            ComparisonOperator::Regex,
+            serde_json::json!("^[A-Z]"),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = find(
+            "name",
+            ComparisonOperator::EndsWithIgnoreCase,
+            serde_json::json!("smith"),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = find("total", ComparisonOperator::Exists, serde_json::Value::Null).unwrap();
+        assert_eq!(found.len(), 3);
+
+        let found = find(
+            "total",
+            ComparisonOperator::NotExists,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = find("name", ComparisonOperator::IsNull, serde_json::Value::Null).unwrap();
+        assert_eq!(found.len(), 1);
+
+        // Invalid operands are rejected before scanning the table
+        assert!(find("total", ComparisonOperator::In, serde_json::json!(50)).is_err());
+        assert!(find("total", ComparisonOperator::Between, serde_json::json!([1])).is_err());
+        assert!(find("name", ComparisonOperator::Regex, serde_json::json!("([")).is_err());
+
+        std::fs::remove_dir_all(".chain_db_data/test_operators_db").unwrap();
+    }
+
+    #[test]
+    fn test_query_sort_projection_and_pagination() {
+        use crate::query::{QueryOptions, SortKey};
+        use crate::table::ComparisonOperator;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_query_db");
+        ChainDB::create_database("test_query_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_query_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("orders").unwrap();
+
+        for (customer, total) in [
+            ("ana", 30),
+            ("bia", 90),
+            ("caio", 10),
+            ("duda", 90),
+            ("eva", 50),
+        ] {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("customer".to_string(), serde_json::json!(customer));
+            record
+                .data
+                .insert("total".to_string(), serde_json::json!(total));
+            record
+                .data
+                .insert("status".to_string(), serde_json::json!("paid"));
+            table.persist(&record).unwrap();
+        }
+
+        let criteria = HashMap::from([(
+            "total".to_string(),
+            (ComparisonOperator::Ge, serde_json::json!(30)),
+        )]);
+        let mut options = QueryOptions {
+            sort: vec![SortKey::desc("total"), SortKey::asc("customer")],
+            fields: Some(vec!["customer".to_string(), "total".to_string()]),
+            limit: Some(2),
+            count_total: true,
+            ..QueryOptions::default()
+        };
+
+        let first_page = table.query(criteria.clone(), &options).unwrap();
+        assert_eq!(first_page.total, Some(4));
+        let customers: Vec<_> = first_page
+            .records
+            .iter()
+            .map(|record| record.data.get("customer").unwrap().clone())
+            .collect();
+        assert_eq!(customers, vec!["bia", "duda"]);
+        assert!(first_page.records[0].doc_id.is_some());
+        assert!(!first_page.records[0].data.contains_key("status"));
+
+        // Um registro que entra antes do cursor não desloca a próxima página
+        let mut record = TableData::new();
+        record
+            .data
+            .insert("customer".to_string(), serde_json::json!("abel"));
+        record
+            .data
+            .insert("total".to_string(), serde_json::json!(95));
+        table.persist(&record).unwrap();
+
+        options.cursor = first_page.next_cursor.clone();
+        options.count_total = false;
+        let second_page = table.query(criteria.clone(), &options).unwrap();
+        assert_eq!(second_page.total, None);
+        let customers: Vec<_> = second_page
+            .records
+            .iter()
+            .map(|record| record.data.get("customer").unwrap().clone())
+            .collect();
+        assert_eq!(customers, vec!["eva", "ana"]);
+        assert!(second_page.next_cursor.is_none());
+
+        // O cursor só vale para a consulta que o gerou
+        let other_criteria = HashMap::from([(
+            "total".to_string(),
+            (ComparisonOperator::Ge, serde_json::json!(10)),
+        )]);
+        assert!(table.query(other_criteria, &options).is_err());
+
+        // Without sort, records come in scan order (most recent first)
+        let mut unsorted = QueryOptions {
+            offset: Some(1),
+            limit: Some(2),
+            ..QueryOptions::default()
+        };
+        let page = table.query(HashMap::new(), &unsorted).unwrap();
+        assert_eq!(page.total, None);
+        let customers: Vec<_> = page
+            .records
+            .iter()
+            .map(|record| record.data.get("customer").unwrap().clone())
+            .collect();
+        assert_eq!(customers, vec!["eva", "duda"]);
+
+        unsorted.cursor = page.next_cursor.clone();
+        let page = table.query(HashMap::new(), &unsorted).unwrap();
+        let customers: Vec<_> = page
+            .records
+            .iter()
+            .map(|record| record.data.get("customer").unwrap().clone())
+            .collect();
+        assert_eq!(customers, vec!["caio", "bia"]);
+        let customers: Vec<_> = table
+            .query_iter(HashMap::new(), &unsorted)
+            .unwrap()
+            .map(|record| record.unwrap().data.get("customer").unwrap().clone())
+            .collect();
+        assert_eq!(customers, vec!["caio", "bia"]);
+
+        options.cursor = Some("not-a-cursor".to_string());
+        assert!(table.query(criteria, &options).is_err());
+
+        std::fs::remove_dir_all(".chain_db_data/test_query_db").unwrap();
+    }
+
+    #[test]
+    fn test_aggregate() {
+        use crate::aggregate::{AggregateFunction, Aggregation};
+        use crate::table::ComparisonOperator;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_aggregate_db");
+        ChainDB::create_database("test_aggregate_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_aggregate_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("orders").unwrap();
+
+        for (status, city, total) in [
+            ("paid", "Recife", 10),
+            ("paid", "Natal", 30),
+            ("shipped", "Recife", 20),
+            ("cancelled", "Natal", 99),
+        ] {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("status".to_string(), serde_json::json!(status));
+            record
+                .data
+                .insert("customer".to_string(), serde_json::json!({ "city": city }));
+            record
+                .data
+                .insert("total".to_string(), serde_json::json!(total));
+            table.persist(&record).unwrap();
+        }
+
+        let criteria = HashMap::from([(
+            "status".to_string(),
+            (ComparisonOperator::Ne, serde_json::json!("cancelled")),
+        )]);
+        let aggregations = vec![
+            Aggregation::new(AggregateFunction::Count, None),
+            Aggregation::new(AggregateFunction::Sum, Some("total")),
+            Aggregation::new(AggregateFunction::Avg, Some("total")),
+            Aggregation::new(AggregateFunction::Max, Some("total")),
+            Aggregation::new(AggregateFunction::CountDistinct, Some("customer.city")),
+        ];
+
+        let groups = table
+            .aggregate(criteria.clone(), &[], &aggregations)
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].values["count"], 3);
+        assert_eq!(groups[0].values["sum_total"], 60);
+        assert_eq!(groups[0].values["avg_total"], 20.0);
+        assert_eq!(groups[0].values["max_total"], 30);
+        assert_eq!(groups[0].values["count_distinct_customer.city"], 2);
+
+        let groups = table
+            .aggregate(criteria, &["customer.city".to_string()], &aggregations[..2])
+            .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].key["customer.city"], "Recife");
+        assert_eq!(groups[0].values["sum_total"], 30);
+        assert_eq!(groups[1].key["customer.city"], "Natal");
+        assert_eq!(groups[1].values["count"], 1);
+
+        let missing_field = vec![Aggregation::new(AggregateFunction::Sum, None)];
+        assert!(table
+            .aggregate(HashMap::new(), &[], &missing_field)
+            .is_err());
+
+        std::fs::remove_dir_all(".chain_db_data/test_aggregate_db").unwrap();
+    }
+
+    #[test]
+    fn test_sql_query() {
+        use crate::errors::ChainDBError;
+        use crate::sql;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_sql_db");
+        ChainDB::create_database("test_sql_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_sql_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("orders").unwrap();
+
+        for (name, status, total) in [
+            ("Ana", "paid", 120),
+            ("Bia", "shipped", 300),
+            ("Caio", "cancelled", 500),
+            ("Duda", "paid", 80),
+            ("Eva", "paid", 210),
+        ] {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("name".to_string(), serde_json::json!(name));
+            record
+                .data
+                .insert("status".to_string(), serde_json::json!(status));
+            record
+                .data
+                .insert("total".to_string(), serde_json::json!(total));
+            table.persist(&record).unwrap();
+        }
+
+        let result = sql::execute(
+        &connection.db,
+        "SELECT name, total FROM orders WHERE total > 100 AND status IN ('paid','shipped') ORDER BY total DESC LIMIT 2",
+    )
+    .unwrap();
+        assert_eq!(result.total, 3);
+        assert_eq!(
+            result.rows,
+            vec![
+                serde_json::json!({ "name": "Bia", "total": 300 }),
+                serde_json::json!({ "name": "Eva", "total": 210 }),
+            ]
+        );
+
+        let result = sql::execute(
+        &connection.db,
+        "select status, count(*) as orders, sum(total) from orders where not (name like 'C%' or total < 100) group by status order by status",
+    )
+    .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                serde_json::json!({ "status": "paid", "orders": 2, "sum_total": 330 }),
+                serde_json::json!({ "status": "shipped", "orders": 1, "sum_total": 300 }),
+            ]
+        );
+
+        let result = sql::execute(
+            &connection.db,
+            "SELECT * FROM orders WHERE missing IS NULL AND name ILIKE 'ana'",
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0]["name"], "Ana");
+        assert!(result.rows[0]["doc_id"].is_string());
+
+        match sql::execute(&connection.db, "SELECT name FROM orders\nWHERE total >") {
+            Err(ChainDBError::QueryParseError { line, column, .. }) => {
+                assert_eq!((line, column), (2, 14));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match sql::execute(
+            &connection.db,
+            "SELECT name FROM orders WHERE total BETWEEN 1",
+        ) {
+            Err(ChainDBError::QueryParseError { column, .. }) => assert_eq!(column, 46),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(matches!(
+            sql::execute(&connection.db, "SELECT * FROM missing_table"),
+            Err(ChainDBError::TableNotFound(_))
+        ));
+
+        std::fs::remove_dir_all(".chain_db_data/test_sql_db").unwrap();
+    }
+
+    #[test]
+    fn test_query_explain() {
+        use crate::aggregate::{AggregateFunction, Aggregation};
+        use crate::explain::{AccessPath, ScanDirection};
+        use crate::query::{QueryOptions, SortKey};
+        use crate::sql;
+        use crate::table::ComparisonOperator;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_explain_db");
+        ChainDB::create_database("test_explain_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_explain_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("orders").unwrap();
+
+        for total in [10, 20, 30, 40, 50] {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("total".to_string(), serde_json::json!(total));
+            table.persist(&record).unwrap();
+        }
+
+        let criteria = HashMap::from([(
+            "total".to_string(),
+            (ComparisonOperator::Gt, serde_json::json!(25)),
+        )]);
+
+        let page = table
+            .query(criteria.clone(), &QueryOptions::default())
+            .unwrap();
+        assert!(page.explain.is_none());
+
+        let options = QueryOptions {
+            sort: vec![SortKey::asc("total")],
+            limit: Some(1),
+            explain: true,
+            ..QueryOptions::default()
+        };
+        let page = table.query(criteria.clone(), &options).unwrap();
+        let explain = page.explain.unwrap();
+        let file_size = std::fs::metadata(".chain_db_data/test_explain_db/orders/data_0.cdb")
+            .unwrap()
+            .len();
+
+        assert_eq!(explain.plan.access, AccessPath::FullScan);
+        assert_eq!(explain.plan.direction, ScanDirection::NewestFirst);
+        assert_eq!(explain.plan.files, vec!["data_0.cdb".to_string()]);
+        assert_eq!(explain.plan.criteria, 1);
+        assert_eq!(explain.plan.sort, vec![SortKey::asc("total")]);
+        assert_eq!(explain.stats.records_decrypted, 5);
+        assert_eq!(explain.stats.records_matched, 3);
+        assert_eq!(explain.stats.bytes_read, file_size);
+        assert_eq!(explain.stats.files.len(), 1);
+        assert_eq!(explain.stats.files[0].file, "data_0.cdb");
+        assert_eq!(explain.stats.files[0].records_matched, 3);
+
+        let (groups, explain) = table
+            .aggregate_explain(
+                criteria,
+                &[],
+                &[Aggregation::new(AggregateFunction::Sum, Some("total"))],
+            )
+            .unwrap();
+        assert_eq!(groups[0].values["sum_total"], 120);
+        assert_eq!(explain.plan.direction, ScanDirection::OldestFirst);
+        assert_eq!(explain.stats.records_matched, 3);
+
+        let result = sql::execute(
+            &connection.db,
+            "EXPLAIN SELECT total FROM orders WHERE total <= 20 OR total = 50",
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 3);
+        let explain = result.explain.unwrap();
+        assert_eq!(explain.plan.criteria, 2);
+        assert_eq!(explain.stats.records_decrypted, 5);
+        assert_eq!(explain.stats.records_matched, 3);
+
+        let result = sql::execute(&connection.db, "SELECT total FROM orders").unwrap();
+        assert!(result.explain.is_none());
+
+        std::fs::remove_dir_all(".chain_db_data/test_explain_db").unwrap();
+    }
+
+    #[test]
+    fn test_transaction() {
+        use crate::errors::ChainDBError;
+        use crate::transaction::JOURNAL_FILE;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_transaction_db");
+        ChainDB::create_database("test_transaction_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_transaction_db", "test_user", "test_pass").unwrap();
+        let db = connection.db;
+
+        let mut inventory = db.create_table::<TableData>("inventory").unwrap();
+        db.create_table::<TableData>("orders").unwrap();
+        let mut item = TableData::new();
+        item.data.insert("sku".to_string(), serde_json::json!("A1"));
+        item.data.insert("stock".to_string(), serde_json::json!(10));
+        inventory.persist(&item).unwrap();
+        let item_id = inventory.get_table().unwrap().doc_id.unwrap();
+
+        // Pedido + baixa no estoque em uma única transação
+        let mut order = TableData::new();
+        order
+            .data
+            .insert("sku".to_string(), serde_json::json!("A1"));
+        order
+            .data
+            .insert("quantity".to_string(), serde_json::json!(3));
+        item.data.insert("stock".to_string(), serde_json::json!(7));
+
+        let mut transaction = db.transaction();
+        let order_id = transaction.persist("orders", &order).unwrap();
+        transaction
+            .update("inventory", &item_id, &item, None)
+            .unwrap();
+        let results = transaction.commit().unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].op, "persist");
+        assert_eq!(results[0].doc_id, order_id);
+        assert_eq!(results[1].table, "inventory");
+        assert!(!std::path::Path::new(".chain_db_data/test_transaction_db")
+            .join(JOURNAL_FILE)
+            .exists());
+
+        let orders = db.create_table::<TableData>("orders").unwrap();
+        let saved_order = orders.get_table().unwrap();
+        assert_eq!(saved_order.doc_id.as_deref(), Some(order_id.as_str()));
+        assert_eq!(saved_order.data["quantity"], 3);
+        let inventory = db.create_table::<TableData>("inventory").unwrap();
+        assert_eq!(inventory.get_table().unwrap().data["stock"], 7);
+
+        // Um registro persistido na própria transação pode ser atualizado por ela
+        let mut transaction = db.transaction();
+        let second_id = transaction.persist("orders", &order).unwrap();
+        order
+            .data
+            .insert("quantity".to_string(), serde_json::json!(5));
+        transaction
+            .update("orders", &second_id, &order, None)
+            .unwrap();
+        transaction.commit().unwrap();
+        let orders = db.create_table::<TableData>("orders").unwrap();
+        assert_eq!(orders.get_table().unwrap().data["quantity"], 5);
+
+        // Se uma operação falhar, nada é gravado
+        let mut transaction = db.transaction();
+        transaction.persist("orders", &order).unwrap();
+        transaction
+            .update("inventory", "missing-id", &item, None)
+            .unwrap();
+        match transaction.commit() {
+            Err(ChainDBError::TransactionError(message)) => {
+                assert!(message.starts_with("Operation 1 (update on table 'inventory')"));
+            }
+            other => panic!("expected a transaction error, got {:?}", other),
+        }
+        let orders = db.create_table::<TableData>("orders").unwrap();
+        assert_eq!(orders.get_history(10).unwrap().len(), 2);
+
+        // As tabelas precisam existir, e nomes que sairiam do diretório do banco são recusados
+        for table in [
+            "payments",
+            "../escape",
+            "/tmp/chain_db_escape",
+            ".hidden",
+            "a/b",
+        ] {
+            let mut transaction = db.transaction();
+            transaction.persist("orders", &order).unwrap();
+            transaction.persist(table, &order).unwrap();
+            match transaction.commit() {
+                Err(ChainDBError::TransactionError(message)) => {
+                    assert!(message.starts_with("Operation 1 (persist"), "{}", message);
+                }
+                other => panic!("{}: expected a transaction error, got {:?}", table, other),
+            }
+        }
+        assert!(!std::path::Path::new(".chain_db_data/escape").exists());
+        assert!(!std::path::Path::new("/tmp/chain_db_escape").exists());
+        assert!(!db.list_tables().unwrap().contains(&"payments".to_string()));
+        assert_eq!(orders.get_history(10).unwrap().len(), 2);
+
+        // Um journal que ficou pendente com o banco aberto (a substituição de um arquivo falhou)
+        // é concluído antes do próximo commit, em vez de ser sobrescrito
+        let blocker =
+            std::path::Path::new(".chain_db_data/test_transaction_db/orders/data_0.cdb.tmp");
+        std::fs::create_dir(blocker).unwrap();
+        let mut transaction = db.transaction();
+        transaction.persist("orders", &order).unwrap();
+        assert!(transaction.commit().is_err());
+        assert!(std::path::Path::new(".chain_db_data/test_transaction_db")
+            .join(JOURNAL_FILE)
+            .exists());
+        std::fs::remove_dir(blocker).unwrap();
+
+        let mut transaction = db.transaction();
+        transaction.persist("orders", &order).unwrap();
+        transaction.commit().unwrap();
+        let orders = db.create_table::<TableData>("orders").unwrap();
+        assert_eq!(orders.get_history(10).unwrap().len(), 4);
+
+        // Um journal que não chegou ao commit é descartado ao conectar
+        std::fs::write(
+            ".chain_db_data/test_transaction_db/journal.cdb.tmp",
+            b"partial",
+        )
+        .unwrap();
+        ChainDB::connect("test_transaction_db", "test_user", "test_pass").unwrap();
+        assert!(
+            !std::path::Path::new(".chain_db_data/test_transaction_db/journal.cdb.tmp").exists()
+        );
+
+        std::fs::remove_dir_all(".chain_db_data/test_transaction_db").unwrap();
+    }
+
+    #[test]
+    fn test_optimistic_concurrency() {
+        use crate::errors::ChainDBError;
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::{Header, Status};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_revision_db");
+        ChainDB::create_database("test_revision_db", "test_user", "test_pass").unwrap();
+        let connection = ChainDB::connect("test_revision_db", "test_user", "test_pass").unwrap();
+        let mut table = connection.db.create_table::<TableData>("docs").unwrap();
+
+        let mut record = TableData::new();
+        record
+            .data
+            .insert("title".to_string(), serde_json::json!("v1"));
+        table.persist(&record).unwrap();
+        let doc_id = table.get_table().unwrap().doc_id.unwrap();
+        assert_eq!(table.get_by_doc_id(&doc_id).unwrap().unwrap().1, 1);
+
+        // Dois clientes leem a revisão 1; só o primeiro consegue gravar
+        record
+            .data
+            .insert("title".to_string(), serde_json::json!("v2"));
+        assert_eq!(table.update(&record, &doc_id, Some(1)).unwrap(), 2);
+        match table.update(&record, &doc_id, Some(1)) {
+            Err(ChainDBError::RevisionConflict {
+                expected, actual, ..
+            }) => assert_eq!((expected, actual), (1, 2)),
+            other => panic!("expected a revision conflict, got {:?}", other),
+        }
+        assert_eq!(table.update(&record, &doc_id, None).unwrap(), 3);
+
+        // A revisão não pode ser definida pelo usuário
+        record
+            .data
+            .insert("revision".to_string(), serde_json::json!(100));
+        assert_eq!(table.update(&record, &doc_id, None).unwrap(), 4);
+        let (stored, revision) = table.get_by_doc_id(&doc_id).unwrap().unwrap();
+        assert_eq!(revision, 4);
+        assert!(!stored.data.contains_key("revision"));
+
+        // Se o arquivo temporário não puder ser criado, o arquivo de dados fica intacto
+        let blocker = std::path::Path::new(".chain_db_data/test_revision_db/docs/data_0.cdb.tmp");
+        std::fs::create_dir(blocker).unwrap();
+        assert!(table.update(&record, &doc_id, Some(4)).is_err());
+        std::fs::remove_dir(blocker).unwrap();
+        assert_eq!(table.get_by_doc_id(&doc_id).unwrap().unwrap().1, 4);
+
+        // API: ETag no get-by-id, If-Match no /update
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_revision_db:test_user:test_pass")
+        );
+
+        let response = client
+            .get(format!("/api/v1/table/docs/doc/{}", doc_id))
+            .header(Header::new("Authorization", authorization.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"4\""));
+
+        let body = serde_json::json!({ "doc_id": doc_id, "data": { "title": "v5" } }).to_string();
+        let response = client
+            .post("/api/v1/table/docs/update")
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(Header::new("If-Match", "\"4\""))
+            .body(body.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"5\""));
+
+        let response = client
+            .post("/api/v1/table/docs/update")
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(Header::new("If-Match", "\"4\""))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let response: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(response["success"], false);
+
+        // Documento inexistente, com ou sem If-Match: 404, como no get-by-id
+        let body = serde_json::json!({ "doc_id": "missing", "data": { "title": "x" } }).to_string();
+        for if_match in [None, Some("\"1\"")] {
+            let mut request = client
+                .post("/api/v1/table/docs/update")
+                .header(Header::new("Authorization", authorization.clone()))
+                .body(body.clone());
+            if let Some(if_match) = if_match {
+                request = request.header(Header::new("If-Match", if_match));
+            }
+            let response = request.dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+            let response: serde_json::Value = response.into_json().unwrap();
+            assert_eq!(response["success"], false);
+        }
+
+        std::fs::remove_dir_all(".chain_db_data/test_revision_db").unwrap();
+    }
+
+    #[test]
+    fn test_file_locking() {
+        use crate::lock::{FileLock, LockMode};
+        use crate::ChainDBError;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_lock_db");
+        ChainDB::create_database("test_lock_db", "test_user", "test_pass").unwrap();
+        let mut db = ChainDB::connect("test_lock_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        db.set_lock_timeout(Duration::from_millis(50));
+
+        let mut writer = db.create_table::<TableData>("items").unwrap();
+        let mut reader = db.create_table::<TableData>("items").unwrap();
+        let table_path = std::path::Path::new(".chain_db_data/test_lock_db/items");
+
+        let mut record = TableData::new();
+        record
+            .data
+            .insert("name".to_string(), serde_json::json!("first"));
+        writer.persist(&record).unwrap();
+
+        // Vários leitores ao mesmo tempo
+        let shared = FileLock::table(table_path, LockMode::Shared, Duration::ZERO).unwrap();
+        let other_shared = FileLock::table(table_path, LockMode::Shared, Duration::ZERO).unwrap();
+        assert_eq!(shared.mode(), LockMode::Shared);
+
+        // Um leitor bloqueia os escritores até o timeout
+        match writer.persist(&record) {
+            Err(ChainDBError::LockTimeout { resource, mode, .. }) => {
+                assert_eq!(resource, "table 'items'");
+                assert_eq!(mode, LockMode::Exclusive);
+            }
+            other => panic!("expected a lock timeout, got {:?}", other),
+        }
+        drop(shared);
+        drop(other_shared);
+
+        // Um escritor bloqueia os leitores
+        let exclusive = FileLock::table(table_path, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert!(matches!(
+            reader.get_table(),
+            Err(ChainDBError::LockTimeout { .. })
+        ));
+        drop(exclusive);
+
+        // Duas instâncias da mesma tabela escrevendo sem perder registros
+        record
+            .data
+            .insert("name".to_string(), serde_json::json!("second"));
+        reader.persist(&record).unwrap();
+        record
+            .data
+            .insert("name".to_string(), serde_json::json!("third"));
+        writer.persist(&record).unwrap();
+
+        reader.refresh().unwrap();
+        let names: Vec<String> = reader
+            .get_history(10)
+            .unwrap()
+            .iter()
+            .map(|record| record.data["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["third", "second", "first"]);
+
+        std::fs::remove_dir_all(".chain_db_data/test_lock_db").unwrap();
+    }
+
+    #[test]
+    fn test_registry_shared_tables() {
+        use crate::api::registry::Registry;
+        use crate::retention::RetentionPolicy;
+        use crate::ChainDBError;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_registry_db");
+        let _ = std::fs::remove_dir_all(crate::backup::backups_dir("test_registry_db"));
+        ChainDB::create_database("test_registry_db", "test_user", "test_pass").unwrap();
+        let registry = Arc::new(Registry::new());
+
+        // O banco e as tabelas são abertos uma única vez
+        let database = registry
+            .open("test_registry_db", "test_user", "test_pass")
+            .unwrap();
+        let again = registry
+            .open("test_registry_db", "test_user", "test_pass")
+            .unwrap();
+        assert!(Arc::ptr_eq(&database, &again));
+        assert!(Arc::ptr_eq(
+            &database.table("items").unwrap(),
+            &again.table("items").unwrap()
+        ));
+        assert_eq!(registry.open_databases(), vec!["test_registry_db"]);
+        assert_eq!(database.open_tables(), vec!["items"]);
+
+        // As credenciais continuam sendo conferidas
+        assert!(matches!(
+            registry.open("test_registry_db", "test_user", "wrong_pass"),
+            Err(ChainDBError::InvalidCredentials(_))
+        ));
+        assert!(matches!(
+            database.existing_table("missing"),
+            Err(ChainDBError::TableNotFound(_))
+        ));
+
+        // Escritas concorrentes na mesma tabela não perdem registros
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    let database = registry
+                        .open("test_registry_db", "test_user", "test_pass")
+                        .unwrap();
+                    let table = database.table("items").unwrap();
+                    for i in 0..10 {
+                        let mut record = TableData::new();
+                        record
+                            .data
+                            .insert("writer".to_string(), serde_json::json!(writer));
+                        record.data.insert("i".to_string(), serde_json::json!(i));
+                        table.write().unwrap().persist(&record).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let table = database.table("items").unwrap();
+        let history = table.read().unwrap().get_history(100).unwrap();
+        assert_eq!(history.len(), 40);
+
+        // Escritas feitas fora do registro aparecem nas leituras, sem refresh
+        let mut outside = database.db().create_table::<TableData>("items").unwrap();
+        let mut record = TableData::new();
+        record
+            .data
+            .insert("writer".to_string(), serde_json::json!("outside"));
+        outside.persist(&record).unwrap();
+        let table = table.read().unwrap();
+        assert_eq!(table.get_history(100).unwrap().len(), 41);
+        assert_eq!(table.get_table().unwrap().data["writer"], "outside");
+        let criteria = HashMap::from([("writer".to_string(), serde_json::json!("outside"))]);
+        assert_eq!(table.find_where(criteria, None, false).unwrap().len(), 1);
+        drop(table);
+
+        // Uma falha na limpeza de uma tabela não impede a limpeza das outras
+        for name in ["logs_a", "logs_b"] {
+            let table = database.table(name).unwrap();
+            let mut table = table.write().unwrap();
+            for i in 0..3 {
+                let mut record = TableData::new();
+                record.data.insert("seq".to_string(), serde_json::json!(i));
+                table.persist(&record).unwrap();
+            }
+            table
+                .set_retention(Some(RetentionPolicy {
+                    max_records: Some(1),
+                    ..Default::default()
+                }))
+                .unwrap();
+        }
+        let blocker = std::path::Path::new(".chain_db_data/test_registry_db/logs_a/data_0.cdb.tmp");
+        std::fs::create_dir(blocker).unwrap();
+        assert_eq!(database.sweep().expired, 2);
+        std::fs::remove_dir(blocker).unwrap();
+        assert_eq!(database.sweep().expired, 2);
+
+        // Uma troca de senha feita fora do registro invalida o banco aberto
+        let mut outside = ChainDB::connect("test_registry_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        outside.change_password("new_pass").unwrap();
+        assert!(registry
+            .open("test_registry_db", "test_user", "test_pass")
+            .is_err());
+        let reopened = registry
+            .open("test_registry_db", "test_user", "new_pass")
+            .unwrap();
+        assert!(!Arc::ptr_eq(&database, &reopened));
+
+        assert!(registry.close("test_registry_db"));
+        assert!(registry.open_databases().is_empty());
+
+        std::fs::remove_dir_all(".chain_db_data/test_registry_db").unwrap();
+        let _ = std::fs::remove_dir_all(crate::backup::backups_dir("test_registry_db"));
+    }
+
+    #[test]
+    fn test_batch_persist_and_update() {
+        use crate::table::RECORDS_PER_FILE;
+        use crate::ChainDBError;
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::{Header, Status};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_batch_db");
+        ChainDB::create_database("test_batch_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_batch_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("items").unwrap();
+
+        // O lote atravessa a divisa entre dois arquivos de dados
+        let records: Vec<TableData> = (0..RECORDS_PER_FILE + 5)
+            .map(|i| {
+                let mut record = TableData::new();
+                record.data.insert("i".to_string(), serde_json::json!(i));
+                record
+            })
+            .collect();
+        let doc_ids: Vec<String> = table
+            .persist_many(&records)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(doc_ids.len(), RECORDS_PER_FILE + 5);
+        assert!(std::path::Path::new(".chain_db_data/test_batch_db/items/data_1.cdb").exists());
+
+        let reopened = db.create_table::<TableData>("items").unwrap();
+        let latest = reopened.get_table().unwrap();
+        assert_eq!(latest.data["i"], serde_json::json!(RECORDS_PER_FILE + 4));
+
+        // Registros de arquivos diferentes, um doc_id repetido e um inexistente
+        let mut update = TableData::new();
+        update
+            .data
+            .insert("status".to_string(), serde_json::json!("done"));
+        let results = table
+            .update_many(&[
+                (doc_ids[0].clone(), update.clone()),
+                (doc_ids[RECORDS_PER_FILE + 1].clone(), update.clone()),
+                (doc_ids[0].clone(), update.clone()),
+                ("missing".to_string(), update.clone()),
+            ])
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &2);
+        assert_eq!(results[1].as_ref().unwrap(), &2);
+        assert_eq!(results[2].as_ref().unwrap(), &3);
+        assert!(matches!(results[3], Err(ChainDBError::RecordNotFound(_))));
+        let (first, revision) = table.get_by_doc_id(&doc_ids[0]).unwrap().unwrap();
+        assert_eq!(revision, 3);
+        assert_eq!(first.data["status"], "done");
+
+        // Uma falha ao reescrever um arquivo não o deixa truncado
+        let blocker = std::path::Path::new(".chain_db_data/test_batch_db/items/data_1.cdb.tmp");
+        std::fs::create_dir(blocker).unwrap();
+        assert!(table
+            .update_many(&[(doc_ids[RECORDS_PER_FILE + 2].clone(), update.clone())])
+            .is_err());
+        std::fs::remove_dir(blocker).unwrap();
+        assert_eq!(table.get_history(10).unwrap().len(), 10);
+        let (record, revision) = table
+            .get_by_doc_id(&doc_ids[RECORDS_PER_FILE + 2])
+            .unwrap()
+            .unwrap();
+        assert_eq!(revision, 1);
+        assert!(!record.data.contains_key("status"));
+
+        // API
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_batch_db:test_user:test_pass")
+        );
+
+        let response = client
+            .post("/api/v1/table/orders/persist-batch")
+            .header(Header::new("Authorization", authorization.clone()))
+            .body(r#"{"records":[{"data":{"total":10}},{"data":{"total":20}}]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(response["success"], true);
+        let order_id = response["data"][1]["doc_id"].as_str().unwrap().to_string();
+
+        let body = serde_json::json!({
+            "records": [
+                { "doc_id": order_id, "data": { "total": 25 } },
+                { "doc_id": "missing", "data": { "total": 0 } }
+            ]
+        });
+        let response = client
+            .post("/api/v1/table/orders/update-batch")
+            .header(Header::new("Authorization", authorization))
+            .body(body.to_string())
+            .dispatch();
+        let response: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(response["data"][0]["success"], true);
+        assert_eq!(response["data"][0]["revision"], 2);
+        assert_eq!(response["data"][1]["success"], false);
+        assert_eq!(response["data"][1]["doc_id"], "missing");
+        assert!(response["data"][1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("not found"));
+
+        std::fs::remove_dir_all(".chain_db_data/test_batch_db").unwrap();
+    }
+
+    #[test]
+    fn test_patch() {
+        use crate::patch::Patch;
+        use crate::ChainDBError;
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::{ContentType, Header, Status};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_patch_db");
+        ChainDB::create_database("test_patch_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_patch_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("orders").unwrap();
+
+        let mut order = TableData::new();
+        order
+            .data
+            .insert("status".to_string(), serde_json::json!("pending"));
+        order
+            .data
+            .insert("items".to_string(), serde_json::json!(["a", "b"]));
+        order
+            .data
+            .insert("note".to_string(), serde_json::json!("call first"));
+        table.persist(&order).unwrap();
+        let doc_id = table.get_table().unwrap().doc_id.unwrap();
+
+        // Merge patch: altera um campo, remove outro com null e preserva o restante
+        let merge = Patch::merge(serde_json::json!({ "status": "paid", "note": null }));
+        let (patched, revision) = table.patch(&doc_id, &merge, Some(1)).unwrap();
+        assert_eq!(revision, 2);
+        assert_eq!(patched.doc_id.as_deref(), Some(doc_id.as_str()));
+        assert_eq!(patched.data["status"], "paid");
+        assert_eq!(patched.data["items"], serde_json::json!(["a", "b"]));
+        assert!(!patched.data.contains_key("note"));
+
+        // JSON Patch
+        let json_patch = Patch::json_patch(serde_json::json!([
+            { "op": "test", "path": "/status", "value": "paid" },
+            { "op": "add", "path": "/items/-", "value": "c" }
+        ]))
+        .unwrap();
+        let (patched, revision) = table.patch(&doc_id, &json_patch, None).unwrap();
+        assert_eq!(revision, 3);
+        assert_eq!(patched.data["items"], serde_json::json!(["a", "b", "c"]));
+
+        // Um `test` que falha não altera nada
+        let failing = Patch::json_patch(serde_json::json!([
+            { "op": "replace", "path": "/status", "value": "shipped" },
+            { "op": "test", "path": "/status", "value": "pending" }
+        ]))
+        .unwrap();
+        assert!(matches!(
+            table.patch(&doc_id, &failing, None),
+            Err(ChainDBError::PatchError(_))
+        ));
+        assert!(matches!(
+            table.patch(&doc_id, &merge, Some(1)),
+            Err(ChainDBError::RevisionConflict { .. })
+        ));
+        assert!(matches!(
+            table.patch("missing", &merge, None),
+            Err(ChainDBError::RecordNotFound(_))
+        ));
+        let (stored, revision) = table.get_by_doc_id(&doc_id).unwrap().unwrap();
+        assert_eq!(revision, 3);
+        assert_eq!(stored.data["status"], "paid");
+
+        // O arquivo de dados é reescrito por um temporário: se ele não puder ser criado, o
+        // original fica intacto
+        let blocker = std::path::Path::new(".chain_db_data/test_patch_db/orders/data_0.cdb.tmp");
+        std::fs::create_dir(blocker).unwrap();
+        assert!(table.patch(&doc_id, &merge, None).is_err());
+        std::fs::remove_dir(blocker).unwrap();
+        let (stored, revision) = table.get_by_doc_id(&doc_id).unwrap().unwrap();
+        assert_eq!(revision, 3);
+        assert_eq!(stored.data["items"], serde_json::json!(["a", "b", "c"]));
+
+        // API
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_patch_db:test_user:test_pass")
+        );
+        let url = format!("/api/v1/table/orders/doc/{}", doc_id);
+
+        let response = client
+            .patch(&url)
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(ContentType::new("application", "merge-patch+json"))
+            .header(Header::new("If-Match", "\"3\""))
+            .body(r#"{"status":"shipped"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"4\""));
+        let response: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(response["data"]["status"], "shipped");
+        assert_eq!(response["data"]["doc_id"], doc_id);
+
+        let response = client
+            .patch(&url)
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(ContentType::JSON)
+            .body(r#"[{"op":"remove","path":"/missing"}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client
+            .patch("/api/v1/table/orders/doc/missing")
+            .header(Header::new("Authorization", authorization))
+            .header(Header::new("If-Match", "\"1\""))
+            .body(r#"{"status":"shipped"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        std::fs::remove_dir_all(".chain_db_data/test_patch_db").unwrap();
+    }
+
+    #[test]
+    fn test_upsert() {
+        use crate::api::registry::Registry;
+        use crate::table::UpsertAction;
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::Header;
+        use rocket::local::blocking::Client;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_upsert_db");
+        ChainDB::create_database("test_upsert_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_upsert_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("customers").unwrap();
+
+        let customer = |external_id: &str, name: &str| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("external_id".to_string(), serde_json::json!(external_id));
+            record
+                .data
+                .insert("name".to_string(), serde_json::json!(name));
+            record
+        };
+        let key = |external_id: &str| {
+            HashMap::from([("external_id".to_string(), serde_json::json!(external_id))])
+        };
+
+        let inserted = table.upsert(key("c-1"), &customer("c-1", "Ana")).unwrap();
+        assert_eq!(inserted.action, UpsertAction::Inserted);
+        assert_eq!(inserted.revision, 1);
+        table.persist(&customer("c-2", "Bruno")).unwrap();
+
+        let updated = table
+            .upsert(key("c-1"), &customer("c-1", "Ana Maria"))
+            .unwrap();
+        assert_eq!(updated.action, UpsertAction::Updated);
+        assert_eq!(updated.doc_id, inserted.doc_id);
+        assert_eq!(updated.revision, 2);
+
+        let (stored, _) = table.get_by_doc_id(&inserted.doc_id).unwrap().unwrap();
+        assert_eq!(stored.data["name"], "Ana Maria");
+        assert_eq!(table.get_history(10).unwrap().len(), 2);
+        assert!(table
+            .upsert(HashMap::new(), &customer("c-3", "Caio"))
+            .is_err());
+
+        // Upserts concorrentes da mesma chave criam um único documento
+        let registry = Arc::new(Registry::new());
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    let database = registry
+                        .open("test_upsert_db", "test_user", "test_pass")
+                        .unwrap();
+                    let table = database.table("customers").unwrap();
+                    let mut record = TableData::new();
+                    record
+                        .data
+                        .insert("external_id".to_string(), serde_json::json!("c-9"));
+                    record
+                        .data
+                        .insert("worker".to_string(), serde_json::json!(i));
+                    let criteria =
+                        HashMap::from([("external_id".to_string(), serde_json::json!("c-9"))]);
+                    let result = table.write().unwrap().upsert(criteria, &record).unwrap();
+                    result
+                })
+            })
+            .collect();
+        let results: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(
+            results
+                .iter()
+                .filter(|result| result.action == UpsertAction::Inserted)
+                .count(),
+            1
+        );
+
+        // API
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_upsert_db:test_user:test_pass")
+        );
+        let body = r#"{"criteria":{"external_id":"c-2"},"data":{"external_id":"c-2","name":"Bruno Lima"}}"#;
+        let response: serde_json::Value = client
+            .post("/api/v1/table/customers/upsert")
+            .header(Header::new("Authorization", authorization))
+            .body(body)
+            .dispatch()
+            .into_json()
+            .unwrap();
+        assert_eq!(response["success"], true);
+        assert_eq!(response["data"]["action"], "updated");
+        assert_eq!(response["data"]["revision"], 2);
+
+        std::fs::remove_dir_all(".chain_db_data/test_upsert_db").unwrap();
+    }
+
+    #[test]
+    fn test_system_fields() {
+        use crate::table::ComparisonOperator;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_system_fields_db");
+        ChainDB::create_database("test_system_fields_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_system_fields_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("notes").unwrap();
+
+        // Campos de sistema informados pelo usuário são descartados
+        let mut note = TableData::new();
+        note.data
+            .insert("text".to_string(), serde_json::json!("first"));
+        note.data
+            .insert("created_by".to_string(), serde_json::json!("mallory"));
+        note.data.insert(
+            "created_at".to_string(),
+            serde_json::json!("2000-01-01T00:00:00Z"),
+        );
+        note.created_by = Some("mallory".to_string());
+        table.persist(&note).unwrap();
+
+        let stored = table.get_table().unwrap();
+        let doc_id = stored.doc_id.clone().unwrap();
+        let created_at = stored.created_at.clone().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&created_at).is_ok());
+        assert!(created_at.as_str() > "2000-01-01T00:00:00Z");
+        assert_eq!(stored.updated_at.as_deref(), Some(created_at.as_str()));
+        assert_eq!(stored.created_by.as_deref(), Some("test_user"));
+        assert_eq!(stored.updated_by.as_deref(), Some("test_user"));
+        assert_eq!(stored.revision, Some(1));
+        assert!(!stored.data.contains_key("created_by"));
+        assert!(!stored.data.contains_key("created_at"));
+
+        // A atualização mantém created_* e renova updated_*
+        thread::sleep(Duration::from_millis(5));
+        let mut changed = TableData::new();
+        changed
+            .data
+            .insert("text".to_string(), serde_json::json!("second"));
+        table.update(&changed, &doc_id, None).unwrap();
+        let (stored, _) = table.get_by_doc_id(&doc_id).unwrap().unwrap();
+        assert_eq!(stored.created_at.as_deref(), Some(created_at.as_str()));
+        assert!(stored.updated_at.clone().unwrap() > created_at);
+        assert_eq!(stored.revision, Some(2));
+
+        let json = stored.to_json();
+        assert_eq!(json["created_by"], "test_user");
+        assert_eq!(json["revision"], 2);
+
+        // Os campos de sistema podem ser consultados com os operadores existentes
+        let found = table
+            .find_where_advanced(
+                HashMap::from([
+                    (
+                        "created_at".to_string(),
+                        (
+                            ComparisonOperator::Gt,
+                            serde_json::json!("2020-01-01T00:00:00Z"),
+                        ),
+                    ),
+                    (
+                        "created_by".to_string(),
+                        (ComparisonOperator::Eq, serde_json::json!("test_user")),
+                    ),
+                ]),
+                None,
+                true,
+            )
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        std::fs::remove_dir_all(".chain_db_data/test_system_fields_db").unwrap();
+    }
+
+    #[test]
+    fn test_retention() {
+        use crate::errors::ChainDBError;
+        use crate::retention::RetentionPolicy;
+        use crate::table::RECORDS_PER_FILE;
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::Header;
+        use rocket::local::blocking::Client;
+        use std::collections::HashMap;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_retention_db");
+        ChainDB::create_database("test_retention_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_retention_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+
+        // TTL por documento
+        let mut sessions = db.create_table::<TableData>("sessions").unwrap();
+        sessions
+            .set_retention(Some(RetentionPolicy {
+                ttl_field: Some("expires_at".to_string()),
+                ..Default::default()
+            }))
+            .unwrap();
+
+        let session = |user: &str, expires_at: serde_json::Value| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("user".to_string(), serde_json::json!(user));
+            if !expires_at.is_null() {
+                record.data.insert("expires_at".to_string(), expires_at);
+            }
+            record
+        };
+        let expired_id = sessions
+            .persist_many(&[session("ana", serde_json::json!("2000-01-01T00:00:00Z"))])
+            .unwrap()
+            .remove(0)
+            .unwrap();
+        sessions
+            .persist(&session("bruno", serde_json::json!(32503680000u64)))
+            .unwrap();
+        sessions
+            .persist(&session("caio", serde_json::Value::Null))
+            .unwrap();
+
+        // O documento expirado fica oculto imediatamente, antes de qualquer limpeza
+        assert_eq!(sessions.get_history(10).unwrap().len(), 2);
+        assert!(sessions.get(0).is_err());
+        assert!(sessions.get_by_doc_id(&expired_id).unwrap().is_none());
+        let criteria = HashMap::from([("user".to_string(), serde_json::json!("ana"))]);
+        assert!(sessions
+            .find_where(criteria, None, false)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            sessions.update(&session("ana", serde_json::Value::Null), &expired_id, None),
+            Err(ChainDBError::RecordNotFound(_))
+        ));
+
+        let stats = sessions.sweep().unwrap();
+        assert_eq!((stats.expired, stats.files_dropped), (1, 0));
+        assert_eq!(sessions.sweep().unwrap().expired, 0);
+        assert_eq!(sessions.get(1).unwrap().data["user"], "bruno");
+
+        // Retenção pelos N documentos mais recentes, removendo um arquivo de dados inteiro
+        let mut logs = db.create_table::<TableData>("logs").unwrap();
+        let records: Vec<TableData> = (0..RECORDS_PER_FILE + 5)
+            .map(|i| {
+                let mut record = TableData::new();
+                record.data.insert("seq".to_string(), serde_json::json!(i));
+                record
+            })
+            .collect();
+        logs.persist_many(&records).unwrap();
+        logs.set_retention(Some(RetentionPolicy {
+            max_records: Some(5),
+            ..Default::default()
+        }))
+        .unwrap();
+
+        let history = logs.get_history(10).unwrap();
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[4].data["seq"], RECORDS_PER_FILE);
+
+        let stats = logs.sweep().unwrap();
+        assert_eq!((stats.expired, stats.files_dropped), (RECORDS_PER_FILE, 1));
+        let logs_path = std::path::Path::new(".chain_db_data/test_retention_db/logs");
+        assert!(!logs_path.join("data_0.cdb").exists());
+        assert!(logs.get(0).is_err());
+        assert_eq!(logs.get_history(10).unwrap().len(), 5);
+
+        // A política é salva nos metadados
+        let reopened = db.create_table::<TableData>("logs").unwrap();
+        assert_eq!(reopened.retention().unwrap().max_records, Some(5));
+
+        // max_age fora do intervalo de uma duração é rejeitado; um max_age que ultrapassa a maior
+        // data possível apenas nunca expira
+        assert!(matches!(
+            logs.set_retention(Some(RetentionPolicy {
+                max_age_secs: Some(u64::MAX),
+                ..Default::default()
+            })),
+            Err(ChainDBError::ValidationError(_))
+        ));
+        assert_eq!(logs.retention().unwrap().max_records, Some(5));
+        logs.set_retention(Some(RetentionPolicy {
+            max_age_secs: Some(9_000_000_000_000_000),
+            ..Default::default()
+        }))
+        .unwrap();
+        assert_eq!(logs.get_history(10).unwrap().len(), 5);
+
+        // API
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_retention_db:test_user:test_pass")
+        );
+
+        let response = client
+            .put("/api/v1/table/metrics/retention")
+            .header(Header::new("Authorization", authorization.clone()))
+            .body(r#"{"max_age_secs": 3600}"#)
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["success"], true);
+
+        let response = client
+            .get("/api/v1/table/metrics/retention")
+            .header(Header::new("Authorization", authorization))
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["data"]["max_age_secs"], 3600);
+
+        std::fs::remove_dir_all(".chain_db_data/test_retention_db").unwrap();
+    }
+
+    #[test]
+    fn test_table_iterators() {
+        use crate::table::RECORDS_PER_FILE;
+        use futures::StreamExt;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_iter_db");
+        ChainDB::create_database("test_iter_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_iter_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("events").unwrap();
+        assert_eq!(table.iter().count(), 0);
+
+        let total = RECORDS_PER_FILE + 10;
+        let records: Vec<TableData> = (0..total)
+            .map(|i| {
+                let mut record = TableData::new();
+                record.data.insert("seq".to_string(), serde_json::json!(i));
+                record
+            })
+            .collect();
+        table.persist_many(&records).unwrap();
+
+        // Percorre os dois arquivos de dados, em ordem
+        let mut iter = table.iter();
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(first.data["seq"], 0);
+        assert!(first.doc_id.is_some());
+
+        // Registros persistidos depois da criação do iterador não aparecem
+        table.persist(&records[0]).unwrap();
+        let seqs: Vec<u64> = iter
+            .map(|record| record.unwrap().data["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, (1..total as u64).collect::<Vec<_>>());
+
+        let newest: Vec<TableData> = table.iter_rev().take(2).map(|r| r.unwrap()).collect();
+        assert_eq!(newest[0].data["seq"], 0);
+        assert_eq!(newest[1].data["seq"], total - 1);
+
+        // Stream assíncrono
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let count = runtime.block_on(async {
+            let stream = table.stream();
+            drop(table);
+            stream
+                .filter(|record| futures::future::ready(record.is_ok()))
+                .count()
+                .await
+        });
+        assert_eq!(count, total + 1);
+
+        let table = db.create_table::<TableData>("events").unwrap();
+        let latest = runtime.block_on(async { table.stream_rev().next().await });
+        assert_eq!(latest.unwrap().unwrap().data["seq"], 0);
+
+        std::fs::remove_dir_all(".chain_db_data/test_iter_db").unwrap();
+    }
+
+    #[test]
+    fn test_ndjson_streaming() {
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::{Header, Status};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_ndjson_db");
+        ChainDB::create_database("test_ndjson_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_ndjson_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut table = db.create_table::<TableData>("orders").unwrap();
+        let records: Vec<TableData> = (0..60)
+            .map(|i| {
+                let mut record = TableData::new();
+                record.data.insert("seq".to_string(), serde_json::json!(i));
+                record.data.insert(
+                    "status".to_string(),
+                    serde_json::json!(if i % 2 == 0 { "paid" } else { "open" }),
+                );
+                record
+            })
+            .collect();
+        table.persist_many(&records).unwrap();
+
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_ndjson_db:test_user:test_pass")
+        );
+        let ndjson_lines = |body: String| -> Vec<serde_json::Value> {
+            body.lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+
+        // O histórico em NDJSON não tem o limite padrão de 50 registros
+        let response = client
+            .get("/api/v1/table/orders/history")
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(Header::new("Accept", "application/x-ndjson"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response
+                .content_type()
+                .map(|content_type| content_type.to_string()),
+            Some("application/x-ndjson".to_string())
+        );
+        let lines = ndjson_lines(response.into_string().unwrap());
+        assert_eq!(lines.len(), 60);
+        assert_eq!(lines[0]["seq"], 59);
+        assert!(lines[0]["doc_id"].is_string());
+
+        let body = serde_json::json!({
+            "criteria": { "status": "paid" },
+            "reverse": false,
+            "offset": 1,
+            "limit": 3,
+            "fields": ["seq"]
+        })
+        .to_string();
+        let response = client
+            .post("/api/v1/table/orders/find")
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(Header::new("Accept", "application/x-ndjson"))
+            .body(body.clone())
+            .dispatch();
+        let lines = ndjson_lines(response.into_string().unwrap());
+        let seqs: Vec<u64> = lines
+            .iter()
+            .map(|line| line["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, vec![2, 4, 6]);
+        assert!(lines[0].get("status").is_none());
+
+        // Sem o cabeçalho, a resposta continua sendo a página JSON
+        let response = client
+            .post("/api/v1/table/orders/find")
+            .header(Header::new("Authorization", authorization.clone()))
+            .body(body)
+            .dispatch();
+        let page: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(page["data"].as_array().unwrap().len(), 3);
+        assert!(page["page"].get("total").is_none());
+        assert!(page["page"]["next_cursor"].is_string());
+
+        // O total só é contado quando pedido
+        let body = serde_json::json!({
+            "criteria": { "status": "paid" },
+            "limit": 3,
+            "count_total": true
+        })
+        .to_string();
+        let response = client
+            .post("/api/v1/table/orders/find")
+            .header(Header::new("Authorization", authorization))
+            .body(body)
+            .dispatch();
+        let page: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(page["page"]["total"], 30);
+
+        std::fs::remove_dir_all(".chain_db_data/test_ndjson_db").unwrap();
+    }
+
+    #[test]
+    fn test_export_import() {
+        use crate::transfer::{CsvColumn, DocIdMode, ExportOptions, ImportOptions, TransferFormat};
+        use base64::{engine::general_purpose, Engine as _};
+        use rocket::http::{ContentType, Header};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_transfer_db");
+        ChainDB::create_database("test_transfer_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_transfer_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut products = db.create_table::<TableData>("products").unwrap();
+        let product = |name: &str, price: f64, city: &str| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("name".to_string(), serde_json::json!(name));
+            record
+                .data
+                .insert("price".to_string(), serde_json::json!(price));
+            record
+                .data
+                .insert("origin".to_string(), serde_json::json!({ "city": city }));
+            record
+        };
+        products
+            .persist_many(&[
+                product("Café", 12.5, "Santos"),
+                product("Chá", 8.0, "Curitiba"),
+                product("Mate, gelado", 6.25, "Porto Alegre"),
+            ])
+            .unwrap();
+        let doc_ids: Vec<String> = products
+            .iter()
+            .map(|record| record.unwrap().doc_id.unwrap())
+            .collect();
+
+        // NDJSON preservando os doc_ids
+        let mut ndjson = Vec::new();
+        assert_eq!(
+            products
+                .export(&mut ndjson, TransferFormat::Ndjson)
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            String::from_utf8(ndjson.clone()).unwrap().lines().count(),
+            3
+        );
+
+        let mut copy = db.create_table::<TableData>("products_copy").unwrap();
+        let report = copy
+            .import(ndjson.as_slice(), TransferFormat::Ndjson)
+            .unwrap();
+        assert_eq!((report.imported, report.failed), (3, 0));
+        let copied: Vec<TableData> = copy.iter().map(|record| record.unwrap()).collect();
+        assert_eq!(copied[0].doc_id.as_ref(), Some(&doc_ids[0]));
+        assert_eq!(copied[2].data["origin"]["city"], "Porto Alegre");
+
+        // Importar de novo os mesmos doc_ids falha registro a registro
+        let report = copy
+            .import(ndjson.as_slice(), TransferFormat::Ndjson)
+            .unwrap();
+        assert_eq!((report.imported, report.failed), (0, 3));
+
+        // Array JSON com novos doc_ids; linhas inválidas são relatadas
+        let mut json = Vec::new();
+        products.export(&mut json, TransferFormat::Json).unwrap();
+        let options = ImportOptions {
+            format: TransferFormat::Json,
+            doc_ids: DocIdMode::Regenerate,
+            ..Default::default()
+        };
+        let report = copy.import(json.as_slice(), options).unwrap();
+        assert_eq!(report.imported, 3);
+        assert_eq!(copy.iter().count(), 6);
+        assert_ne!(
+            copy.iter_rev().next().unwrap().unwrap().doc_id.as_ref(),
+            Some(&doc_ids[2])
+        );
+
+        let bad_input = "{\"data\":{\"name\":\"ok\"}}\nnot json\n[1]\n";
+        let report = copy
+            .import(bad_input.as_bytes(), TransferFormat::Ndjson)
+            .unwrap();
+        assert_eq!((report.imported, report.failed), (1, 2));
+        assert_eq!(report.errors[0].record, 2);
+        assert!(copy
+            .import("{\"not\": \"an array\"}".as_bytes(), TransferFormat::Json)
+            .is_err());
+
+        // CSV com mapeamento de colunas
+        let mut csv = Vec::new();
+        let options = ExportOptions {
+            format: TransferFormat::Csv,
+            columns: Some(vec![
+                CsvColumn::new("name", "name"),
+                CsvColumn::new("price", "price"),
+                CsvColumn::new("city", "origin.city"),
+            ]),
+        };
+        products.export(&mut csv, options).unwrap();
+        let csv_text = String::from_utf8(csv.clone()).unwrap();
+        assert!(csv_text.starts_with("name,price,city\n"));
+        assert!(csv_text.contains("\"Mate, gelado\",6.25,Porto Alegre"));
+
+        let mut from_csv = db.create_table::<TableData>("from_csv").unwrap();
+        let options = ImportOptions {
+            format: TransferFormat::Csv,
+            columns: Some(vec![
+                CsvColumn::new("name", "data.name"),
+                CsvColumn::new("price", "data.price"),
+                CsvColumn::new("city", "data.origin.city"),
+            ]),
+            ..Default::default()
+        };
+        let report = from_csv.import(csv.as_slice(), options).unwrap();
+        assert_eq!(report.imported, 3);
+        let first = from_csv.iter().next().unwrap().unwrap();
+        assert_eq!(first.data["name"], "Café");
+        assert_eq!(first.data["price"], 12.5);
+        assert_eq!(first.data["origin"]["city"], "Santos");
+        assert!(first.doc_id.is_some());
+
+        // API
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+        let authorization = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("test_transfer_db:test_user:test_pass")
+        );
+
+        let response = client
+            .get("/api/v1/table/products/export?format=csv&columns=name,city=origin.city")
+            .header(Header::new("Authorization", authorization.clone()))
+            .dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::CSV));
+        let body = response.into_string().unwrap();
+        assert_eq!(body.lines().next(), Some("name,city"));
+        assert_eq!(body.lines().count(), 4);
+
+        let response = client
+            .post("/api/v1/table/from_api/import?format=ndjson")
+            .header(Header::new("Authorization", authorization.clone()))
+            .body(&ndjson)
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["success"], true);
+        assert_eq!(body["data"]["imported"], 3);
+
+        // Um corpo do tamanho exato do limite é aceito; com um limite um byte menor, ele é cortado
+        for (limit, success) in [(ndjson.len(), true), (ndjson.len() - 1, false)] {
+            let limits = rocket::data::Limits::default().limit("import", limit.into());
+            let figment = rocket::Config::figment().merge(("limits", limits));
+            let limited_client =
+                Client::tracked(rocket::custom(figment).mount("/api/v1", crate::api::routes()))
+                    .unwrap();
+            let response = limited_client
+                .post("/api/v1/table/limited/import?format=ndjson")
+                .header(Header::new("Authorization", authorization.clone()))
+                .body(&ndjson)
+                .dispatch();
+            let body: serde_json::Value = response.into_json().unwrap();
+            assert_eq!(body["success"], success, "{}", body);
+        }
+
+        let response = client
+            .get("/api/v1/table/from_api/export")
+            .header(Header::new("Authorization", authorization))
+            .dispatch();
+        let body = response.into_string().unwrap();
+        assert!(body.contains(&doc_ids[1]));
+
+        std::fs::remove_dir_all(".chain_db_data/test_transfer_db").unwrap();
+    }
+
+    #[test]
+    fn test_backup_restore() {
+        use crate::backup;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_backup_db");
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_backup_restored_db");
+        let _ = std::fs::remove_dir_all(backup::backups_dir("test_backup_db"));
+        ChainDB::create_database("test_backup_db", "test_user", "test_pass").unwrap();
+        let mut db = ChainDB::connect("test_backup_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+
+        let order = |number: u64| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("number".to_string(), serde_json::json!(number));
+            record
+        };
+        let mut orders = db.create_table::<TableData>("orders").unwrap();
+        orders
+            .persist_many(&(0..1500).map(order).collect::<Vec<_>>())
+            .unwrap();
+        let mut customers = db.create_table::<TableData>("customers").unwrap();
+        customers.persist(&order(1)).unwrap();
+
+        // Backup enquanto outra conexão continua gravando
+        let writer = thread::spawn(move || {
+            let db = ChainDB::connect("test_backup_db", "test_user", "test_pass")
+                .unwrap()
+                .db;
+            let mut orders = db.create_table::<TableData>("orders").unwrap();
+            for number in 1500..1600 {
+                orders.persist(&order(number)).unwrap();
+            }
+        });
+        let archive = backup::backups_dir("test_backup_db").join("manual.cdbak");
+        let manifest = db.backup(&archive).unwrap();
+        writer.join().unwrap();
+
+        let snapshot = &manifest.tables["orders"];
+        assert!(snapshot.total_records >= 1500 && snapshot.total_records <= 1600);
+        assert_eq!(manifest.tables["customers"].total_records, 1);
+        assert!(manifest.files.iter().any(|file| file.path == "config.cdb"));
+        assert!(manifest
+            .files
+            .iter()
+            .any(|file| file.path == "orders/data_1.cdb"));
+        assert_eq!(
+            ChainDB::backup_manifest(&archive, "test_pass").unwrap(),
+            manifest
+        );
+
+        // Restaurar com outro nome traz o estado do momento do backup
+        ChainDB::restore(
+            &archive,
+            "test_backup_restored_db",
+            "test_user",
+            "test_pass",
+        )
+        .unwrap();
+        let restored = ChainDB::connect("test_backup_restored_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let restored_orders = restored.create_table::<TableData>("orders").unwrap();
+        assert_eq!(
+            restored_orders.iter().count() as u64,
+            snapshot.total_records
+        );
+        let mut tables = restored.list_tables().unwrap();
+        tables.sort();
+        assert_eq!(tables, vec!["customers", "orders"]);
+
+        // Senha errada ou arquivo corrompido não alteram o banco existente
+        assert!(
+            ChainDB::restore(&archive, "test_backup_restored_db", "test_user", "wrong").is_err()
+        );
+        let mut bytes = std::fs::read(&archive).unwrap();
+        bytes[100] ^= 0xff;
+        let corrupted = backup::backups_dir("test_backup_db").join("corrupted.bin");
+        std::fs::write(&corrupted, &bytes).unwrap();
+        assert!(ChainDB::restore(
+            &corrupted,
+            "test_backup_restored_db",
+            "test_user",
+            "test_pass"
+        )
+        .is_err());
+        assert_eq!(
+            restored_orders.iter().count() as u64,
+            snapshot.total_records
+        );
+        std::fs::remove_file(&corrupted).unwrap();
+
+        // Uma escrita em andamento (lock exclusivo da tabela) impede a troca do diretório
+        let target = std::path::Path::new(".chain_db_data/test_backup_restored_db");
+        let writing = crate::lock::FileLock::table(
+            &target.join("customers"),
+            crate::lock::LockMode::Exclusive,
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert!(matches!(
+            backup::restore_backup(
+                std::slice::from_ref(&archive),
+                target,
+                "test_backup_restored_db",
+                "test_user",
+                "test_pass",
+                &crate::encryption::DataEncryption::new("test_pass"),
+                Duration::from_millis(50),
+            ),
+            Err(crate::ChainDBError::LockTimeout { .. })
+        ));
+        drop(writing);
+        assert_eq!(
+            restored_orders.iter().count() as u64,
+            snapshot.total_records
+        );
+
+        // Retenção: só os backups mais recentes ficam
+        for _ in 0..3 {
+            db.create_backup().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let backups = db.list_backups().unwrap();
+        assert_eq!(backups.len(), 4);
+        assert_eq!(db.prune_backups(2).unwrap().len(), 2);
+        let remaining: Vec<String> = db
+            .list_backups()
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.file_name)
+            .collect();
+        assert_eq!(
+            remaining,
+            backups[2..]
+                .iter()
+                .map(|backup| backup.file_name.clone())
+                .collect::<Vec<_>>()
+        );
+
+        // O backup feito pela troca de senha não apaga os backups antigos
+        for _ in 0..crate::backup::backup_keep() {
+            db.create_backup().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let before = db.list_backups().unwrap();
+        db.change_password("new_pass").unwrap();
+        let after = db.list_backups().unwrap();
+        assert_eq!(after.len(), before.len() + 1);
+        assert_eq!(after[..before.len()], before[..]);
+
+        std::fs::remove_dir_all(".chain_db_data/test_backup_db").unwrap();
+        std::fs::remove_dir_all(".chain_db_data/test_backup_restored_db").unwrap();
+        std::fs::remove_dir_all(backup::backups_dir("test_backup_db")).unwrap();
+    }
+
+    #[test]
+    fn test_incremental_backup() {
+        use crate::backup::{self, BackupKind};
+        use std::collections::BTreeSet;
+
+        let dir = std::path::PathBuf::from(".chain_db_data/test_incremental_archives");
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_incremental_db");
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_incremental_restored_db");
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(backup::backups_dir("test_incremental_db"));
+        ChainDB::create_database("test_incremental_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_incremental_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+
+        let order = |number: u64| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("number".to_string(), serde_json::json!(number));
+            record
+        };
+        let mut orders = db.create_table::<TableData>("orders").unwrap();
+        orders
+            .persist_many(&(0..1500).map(order).collect::<Vec<_>>())
+            .unwrap();
+        let mut customers = db.create_table::<TableData>("customers").unwrap();
+        customers.persist(&order(1)).unwrap();
+
+        let full = dir.join("full.cdbak");
+        db.backup(&full).unwrap();
+
+        // Só o segmento que recebeu registros e os metadados da tabela mudaram
+        orders
+            .persist_many(&(1500..1510).map(order).collect::<Vec<_>>())
+            .unwrap();
+        let first = dir.join("first.inc.cdbak");
+        let manifest = db.backup_incremental(&full, &first).unwrap();
+        assert_eq!(manifest.kind, BackupKind::Incremental);
+        let stored: BTreeSet<&str> = manifest
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(
+            stored,
+            BTreeSet::from(["orders/data_1.cdb", "orders/metadata.cdb"])
+        );
+        assert_eq!(manifest.tables["orders"].total_records, 1510);
+        assert!(manifest.checksums.contains_key("customers/data_0.cdb"));
+
+        // Tabelas criadas e removidas depois da base
+        let mut invoices = db.create_table::<TableData>("invoices").unwrap();
+        invoices.persist(&order(7)).unwrap();
+        std::fs::remove_dir_all(".chain_db_data/test_incremental_db/customers").unwrap();
+        let second = dir.join("second.inc.cdbak");
+        let manifest = db.backup_incremental(&first, &second).unwrap();
+        assert!(manifest
+            .files
+            .iter()
+            .all(|file| file.path.starts_with("invoices/")));
+        assert!(!manifest.checksums.contains_key("customers/data_0.cdb"));
+
+        // A restauração de um incremental encontra a cadeia no mesmo diretório
+        ChainDB::restore(
+            &second,
+            "test_incremental_restored_db",
+            "test_user",
+            "test_pass",
+        )
+        .unwrap();
+        let restored = ChainDB::connect("test_incremental_restored_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut tables = restored.list_tables().unwrap();
+        tables.sort();
+        assert_eq!(tables, vec!["invoices", "orders"]);
+        let restored_orders = restored.create_table::<TableData>("orders").unwrap();
+        assert_eq!(restored_orders.iter().count(), 1510);
+
+        ChainDB::restore_chain(
+            &[&full, &first],
+            "test_incremental_restored_db",
+            "test_user",
+            "test_pass",
+        )
+        .unwrap();
+        let mut tables = restored.list_tables().unwrap();
+        tables.sort();
+        assert_eq!(tables, vec!["customers", "orders"]);
+
+        // Cadeias inválidas
+        assert!(ChainDB::restore_chain(
+            &[&first],
+            "test_incremental_restored_db",
+            "test_user",
+            "test_pass"
+        )
+        .is_err());
+        assert!(ChainDB::restore_chain(
+            &[&full, &second],
+            "test_incremental_restored_db",
+            "test_user",
+            "test_pass"
+        )
+        .is_err());
+
+        // A retenção mantém as bases dos incrementais mais recentes
+        db.create_backup().unwrap();
+        thread::sleep(Duration::from_millis(5));
+        db.create_incremental_backup().unwrap();
+        thread::sleep(Duration::from_millis(5));
+        let latest = db.create_incremental_backup().unwrap();
+        assert!(latest.file_name.ends_with(".inc.cdbak"));
+        assert!(db.prune_backups(1).unwrap().is_empty());
+        thread::sleep(Duration::from_millis(5));
+        db.create_backup().unwrap();
+        assert_eq!(db.prune_backups(1).unwrap().len(), 3);
+
+        std::fs::remove_dir_all(".chain_db_data/test_incremental_db").unwrap();
+        std::fs::remove_dir_all(".chain_db_data/test_incremental_restored_db").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(backup::backups_dir("test_incremental_db")).unwrap();
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        use crate::check::QUARANTINE_FILE;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_check_db");
+        ChainDB::create_database("test_check_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_check_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+
+        let record = |number: u64| {
+            let mut record = TableData::new();
+            record
+                .data
+                .insert("number".to_string(), serde_json::json!(number));
+            record
+        };
+        let mut orders = db.create_table::<TableData>("orders").unwrap();
+        orders
+            .persist_many(&(0..5).map(record).collect::<Vec<_>>())
+            .unwrap();
+        let mut customers = db.create_table::<TableData>("customers").unwrap();
+        customers.persist(&record(1)).unwrap();
+        assert!(db.check().unwrap().is_ok());
+
+        // Base64 inválido, linha truncada, doc_id repetido e escrita interrompida sem quebra de linha
+        let data_path = ".chain_db_data/test_check_db/orders/data_0.cdb";
+        let original = std::fs::read_to_string(data_path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+        let corrupted = [
+            lines[0],
+            "%%% not base64 %%%",
+            lines[2],
+            &lines[3][..lines[3].len() / 2],
+            lines[4],
+            lines[0],
+        ];
+        std::fs::write(data_path, format!("{}\nQUJD", corrupted.join("\n"))).unwrap();
+        std::fs::write(
+            ".chain_db_data/test_check_db/customers/metadata.cdb",
+            "garbage",
+        )
+        .unwrap();
+        assert!(orders.iter().any(|record| record.is_err()));
+
+        let report = db.check().unwrap();
+        assert!(!report.is_ok());
+        let (customers_report, orders_report) = (&report.tables[0], &report.tables[1]);
+        assert_eq!(orders_report.table, "orders");
+        assert_eq!(
+            orders_report
+                .bad_lines
+                .iter()
+                .map(|bad_line| bad_line.line)
+                .collect::<Vec<_>>(),
+            vec![2, 4, 7]
+        );
+        assert!(orders_report.bad_lines[0]
+            .error
+            .starts_with("Invalid base64"));
+        assert_eq!(orders_report.records, 4);
+        assert_eq!(orders_report.duplicate_doc_ids.len(), 1);
+        assert_eq!(orders_report.duplicate_doc_ids[0].records, vec![0, 5]);
+        assert!(orders_report.metadata_issues[0].contains("total_records is 5"));
+        assert!(customers_report.metadata_issues[0].contains("metadata.cdb"));
+        assert_eq!(orders_report.quarantined, 0);
+
+        // O reparo isola as linhas ruins e reescreve os metadados
+        let report = db.repair().unwrap();
+        assert_eq!(report.tables[1].quarantined, 3);
+        assert!(report.tables[1].metadata_rewritten);
+        assert!(report.tables[0].metadata_rewritten);
+
+        orders.refresh().unwrap();
+        let numbers: Vec<serde_json::Value> = orders
+            .iter()
+            .map(|record| record.unwrap().data["number"].clone())
+            .collect();
+        assert_eq!(numbers, vec![0, 2, 4, 0]);
+        let quarantined = std::fs::read_to_string(format!(
+            ".chain_db_data/test_check_db/orders/{}",
+            QUARANTINE_FILE
+        ))
+        .unwrap();
+        assert_eq!(quarantined.lines().count(), 3);
+        assert!(quarantined.contains("not base64"));
+
+        let report = db.check().unwrap();
+        assert!(report.tables[1].bad_lines.is_empty());
+        assert!(report.tables[1].metadata_issues.is_empty());
+        assert_eq!(report.tables[1].duplicate_doc_ids.len(), 1);
+        assert!(report.tables[0].is_ok());
+        let customers = db.create_table::<TableData>("customers").unwrap();
+        assert_eq!(customers.iter().count(), 1);
+
+        std::fs::remove_dir_all(".chain_db_data/test_check_db").unwrap();
+    }
+
+    #[test]
+    fn test_list_databases_and_metadata() {
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_admin_db");
+        ChainDB::create_database("test_admin_db", "test_user", "test_pass").unwrap();
+        let databases = ChainDB::list_databases().unwrap();
+        assert!(databases.contains(&"test_admin_db".to_string()));
+        assert!(databases.iter().all(|name| !name.starts_with('.')));
+
+        let db = ChainDB::connect("test_admin_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+        let mut orders = db.create_table::<TableData>("orders").unwrap();
+        orders.persist(&TableData::new()).unwrap();
+        let metadata = orders.metadata();
+        assert_eq!((metadata.total_records, metadata.current_file), (1, 0));
+
+        std::fs::remove_dir_all(".chain_db_data/test_admin_db").unwrap();
+    }
+
+    #[test]
+    fn test_shell_parse_command() {
+        use crate::errors::ChainDBError;
+        use crate::shell::{keep_in_history, parse_command, ShellCommand};
+        use crate::table::ComparisonOperator;
+        use serde_json::json;
+
+        let command = parse_command(r#"find orders where status = "paid" and total between [10, 20.5] and note not exists limit 10"#)
+        .unwrap()
+        .unwrap();
+        let ShellCommand::Find {
+            table,
+            criteria,
+            limit,
+        } = command
+        else {
+            panic!("expected find, got {:?}", command);
+        };
+        assert_eq!((table.as_str(), limit), ("orders", Some(10)));
+        assert_eq!(criteria["status"], (ComparisonOperator::Eq, json!("paid")));
+        assert_eq!(
+            criteria["total"],
+            (ComparisonOperator::Between, json!([10, 20.5]))
+        );
+        assert_eq!(criteria["note"].0, ComparisonOperator::NotExists);
+
+        // Palavras sem aspas viram JSON quando possível; entre aspas são sempre texto
+        let Some(ShellCommand::Find { criteria, .. }) =
+            parse_command("find orders where qty >= 3 and code = '3'").unwrap()
+        else {
+            panic!("expected find");
+        };
+        assert_eq!(criteria["qty"], (ComparisonOperator::Ge, json!(3)));
+        assert_eq!(criteria["code"], (ComparisonOperator::Eq, json!("3")));
+
+        assert_eq!(
+            parse_command("history orders 5").unwrap(),
+            Some(ShellCommand::History {
+                table: "orders".to_string(),
+                limit: 5
+            })
+        );
+        assert_eq!(
+            parse_command(r#"persist orders {"status": "paid", "items": [1, 2]}"#).unwrap(),
+            Some(ShellCommand::Persist {
+                table: "orders".to_string(),
+                data: json!({"status": "paid", "items": [1, 2]})
+            })
+        );
+        assert_eq!(parse_command("   ").unwrap(), None);
+
+        // Erros apontam a coluna do problema
+        for (line, column) in [
+            ("find orders where total ~ 3", 25),
+            ("find orders where a = 1 and a = 2", 29),
+            ("find orders where tags in 3", 19),
+            ("get orders", 11),
+            ("persist orders [1]", 16),
+        ] {
+            match parse_command(line) {
+                Err(ChainDBError::QueryParseError { column: found, .. }) => {
+                    assert_eq!(found, column, "{}", line)
+                }
+                Err(ChainDBError::ValidationError(_)) if line.contains(" in ") => {}
+                other => panic!("{}: unexpected {:?}", line, other),
+            }
+        }
+
+        // Linhas com senha não vão para o arquivo de histórico
+        assert!(keep_in_history("use shop"));
+        assert!(keep_in_history("find orders"));
+        assert!(!keep_in_history("use shop admin secret"));
+        assert!(!keep_in_history("USE shop admin"));
+        assert!(!keep_in_history("  "));
+    }
+
+    #[rocket::async_test]
+    async fn test_client() {
+        use crate::client::Client;
+        use crate::errors::ChainDBError;
+        use crate::events::EventType;
+        use crate::table::ComparisonOperator;
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Order {
+            status: String,
+            total: u64,
+        }
+
+        // Sobe o servidor em uma porta livre
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let figment = rocket::Config::figment()
+            .merge(("port", port))
+            .merge(("address", "127.0.0.1"))
+            .merge(("log_level", rocket::config::LogLevel::Off));
+        let server = rocket::custom(figment).mount("/api/v1", crate::api::routes());
+        rocket::tokio::spawn(server.launch());
+        let url = format!("http://127.0.0.1:{}", port);
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_client_db");
+        let mut created =
+            Client::create_database(&url, "test_client_db", "test_user", "test_pass").await;
+        for _ in 0..50 {
+            if created.is_ok() {
+                break;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+            created =
+                Client::create_database(&url, "test_client_db", "test_user", "test_pass").await;
+        }
+        created.unwrap();
+
+        assert!(matches!(
+            Client::connect(&url, "test_client_db", "test_user", "wrong").await,
+            Err(ChainDBError::InvalidCredentials(_))
+        ));
+        let client = Client::connect(&url, "test_client_db", "test_user", "test_pass")
+            .await
+            .unwrap();
+        let orders = client.table::<Order>("orders");
+        let mut events = orders.subscribe().await.unwrap();
+
+        let open = Order {
+            status: "open".to_string(),
+            total: 10,
+        };
+        let stored = orders.persist(&open).await.unwrap();
+        assert_eq!(stored.data, open);
+        assert_eq!(stored.revision, Some(1));
+        assert_eq!(stored.created_by.as_deref(), Some("test_user"));
+
+        let event = rocket::tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.event_type, EventType::TablePersist);
+        assert_eq!(
+            (event.table.as_str(), event.data),
+            ("orders", Some(open.clone()))
+        );
+        events.close().await.unwrap();
+
+        let paid = Order {
+            status: "paid".to_string(),
+            total: 10,
+        };
+        let updated = orders.update_if(&stored.doc_id, &paid, 1).await.unwrap();
+        assert_eq!(updated.revision, Some(2));
+        match orders.update_if(&stored.doc_id, &open, 1).await {
+            Err(ChainDBError::RevisionConflict {
+                doc_id,
+                expected,
+                actual,
+            }) => assert_eq!((doc_id, expected, actual), (stored.doc_id.clone(), 1, 2)),
+            other => panic!("expected a revision conflict, got {:?}", other),
+        }
+
+        orders
+            .persist(&Order {
+                status: "open".to_string(),
+                total: 30,
+            })
+            .await
+            .unwrap();
+        let document = orders.get(&stored.doc_id).await.unwrap().unwrap();
+        assert_eq!(document.data, paid);
+        assert!(orders.get("missing").await.unwrap().is_none());
+
+        let found = orders
+            .find(
+                HashMap::from([("status".to_string(), serde_json::json!("paid"))]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        let found = orders
+            .find_advanced(
+                HashMap::from([(
+                    "total".to_string(),
+                    (ComparisonOperator::Gt, serde_json::json!(20)),
+                )]),
+                Some(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(found[0].data.total, 30);
+        let (found, total) = orders
+            .find_advanced_with_total(HashMap::new(), Some(1))
+            .await
+            .unwrap();
+        assert_eq!((found.len(), total), (1, 2));
+        assert_eq!(
+            found[0].to_json().unwrap()["doc_id"],
+            found[0].doc_id.as_str()
+        );
+        let history = orders.history(10).await.unwrap();
+        assert_eq!(
+            history.iter().map(|d| d.data.total).collect::<Vec<_>>(),
+            vec![30, 10]
+        );
+        assert_eq!(client.tables().await.unwrap(), vec!["orders".to_string()]);
+
+        // Nomes de tabela e doc_ids são codificados na URL
+        assert!(orders.get("missing/../x?y#z").await.unwrap().is_none());
+        let monthly = client.table::<Order>("monthly orders");
+        let stored = monthly.persist(&open).await.unwrap();
+        let document = monthly.get(&stored.doc_id).await.unwrap().unwrap();
+        assert_eq!(document.data, open);
+        assert_eq!(monthly.history(10).await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(".chain_db_data/test_client_db").unwrap();
+    }
+
+    #[test]
+    fn test_typed_records() {
+        use crate::query::{QueryOptions, SortKey};
+        use crate::record::Record;
+        use crate::table::ComparisonOperator;
+        use serde::{Deserialize, Serialize};
+        use serde_json::json;
+        use std::collections::HashMap;
+
+        // Structs simples, sem o objeto `data` de `TableData`
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Order {
+            customer: String,
+            total: f64,
+        }
+
+        impl Record for Order {}
+
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        struct Customer {
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            doc_id: Option<String>,
+            name: String,
+        }
+
+        impl Record for Customer {
+            fn doc_id(&self) -> Option<&str> {
+                self.doc_id.as_deref()
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_typed_db");
+        ChainDB::create_database("test_typed_db", "test_user", "test_pass").unwrap();
+        let db = ChainDB::connect("test_typed_db", "test_user", "test_pass")
+            .unwrap()
+            .db;
+
+        let mut orders = db.create_table::<Order>("orders").unwrap();
+        for (customer, total) in [("ana", 30.0), ("bruno", 120.0), ("carla", 75.5)] {
+            let order = Order {
+                customer: customer.to_string(),
+                total,
+            };
+            let doc_id = orders.save(&order).unwrap();
+            assert_eq!(orders.get_doc(&doc_id).unwrap(), Some((doc_id, order)));
+        }
+
+        // Os campos são procurados na raiz do documento, onde a struct os guarda
+        let mut criteria = HashMap::new();
+        criteria.insert("total".to_string(), (ComparisonOperator::Gt, json!(50)));
+        let found = orders.find(criteria, None).unwrap();
+        let customers: Vec<&str> = found
+            .iter()
+            .map(|(_, order)| order.customer.as_str())
+            .collect();
+        assert_eq!(customers, vec!["carla", "bruno"]);
+        assert!(found.iter().all(|(doc_id, _)| doc_id.len() == 36));
+        assert_eq!(orders.find(HashMap::new(), Some(1)).unwrap().len(), 1);
+
+        let page = orders
+            .query(
+                HashMap::new(),
+                &QueryOptions {
+                    sort: vec![SortKey::desc("total")],
+                    ..QueryOptions::default()
+                },
+            )
+            .unwrap();
+        let totals: Vec<f64> = page.records.iter().map(|order| order.total).collect();
+        assert_eq!(totals, vec![120.0, 75.5, 30.0]);
+
+        let stored = db.create_table::<serde_json::Value>("orders").unwrap();
+        let document = stored.get_table().unwrap();
+        assert_eq!(document["customer"], "carla");
+        assert!(document.get("data").is_none());
+        assert_eq!(document["revision"], 1);
+
+        // Um registro com doc_id é atualizado por `save` em vez de gerar um novo documento
+        let mut customers = db.create_table::<Customer>("customers").unwrap();
+        let mut customer = Customer {
+            doc_id: None,
+            name: "Ana".to_string(),
+        };
+        let doc_id = customers.save(&customer).unwrap();
+        let (_, mut saved) = customers.get_doc(&doc_id).unwrap().unwrap();
+        assert_eq!(saved.doc_id.as_deref(), Some(doc_id.as_str()));
+        saved.name = "Ana Maria".to_string();
+        assert_eq!(customers.save(&saved).unwrap(), doc_id);
+        assert_eq!(customers.metadata().total_records, 1);
+        let (_, revision) = customers.get_by_doc_id(&doc_id).unwrap().unwrap();
+        assert_eq!(revision, 2);
+        customer.doc_id = Some("missing".to_string());
+        assert!(customers.save(&customer).is_err());
+
+        std::fs::remove_dir_all(".chain_db_data/test_typed_db").unwrap();
+    }
+
+    #[test]
+    fn test_health_ready_and_info() {
+        use crate::api::health::{check_readiness, ServerInfo};
+        use crate::table::STORAGE_FORMAT_VERSION;
+        use rocket::http::{Header, Status};
+        use rocket::local::blocking::Client;
+
+        let _ = std::fs::remove_dir_all(".chain_db_data/test_health_db");
+        ChainDB::create_database("test_health_db", "test_user", "test_pass").unwrap();
+        let token = ChainDB::connect("test_health_db", "test_user", "test_pass")
+            .unwrap()
+            .auth_token;
+
+        let client =
+            Client::tracked(rocket::build().mount("/api/v1", crate::api::routes())).unwrap();
+
+        // /health e /ready não exigem autenticação
+        let response = client.get("/api/v1/health").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["success"], true);
+
+        let response = client.get("/api/v1/ready").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["data"]["ready"], true);
+        assert_eq!(body["data"]["checks"].as_array().unwrap().len(), 3);
+
+        // Sem espaço livre suficiente, a verificação de espaço falha e as outras continuam ok
+        let dir = tempfile::tempdir().unwrap();
+        let readiness = check_readiness(dir.path(), u64::MAX);
+        assert!(!readiness.ready);
+        let failed: Vec<&str> = readiness
+            .checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failed, vec!["free_space"]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // Um diretório inexistente não fica pronto, e não é criado pela verificação
+        let missing = dir.path().join("missing");
+        let readiness = check_readiness(&missing, 0);
+        let failed: Vec<&str> = readiness
+            .checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failed, vec!["readable", "writable", "free_space"]);
+        assert!(!missing.exists());
+
+        // /info exige as credenciais de um banco
+        let response = client.get("/api/v1/info").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let database = crate::api::registry::get_registry()
+            .open("test_health_db", "test_user", "test_pass")
+            .unwrap();
+        database.table("orders").unwrap();
+        let response = client
+            .get("/api/v1/info")
+            .header(Header::new("Authorization", format!("Basic {}", token)))
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        let info: ServerInfo = serde_json::from_value(body["data"].clone()).unwrap();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.database, "test_health_db");
+        assert_eq!(info.open_tables, vec!["orders".to_string()]);
+        assert_eq!(info.storage_format_version, STORAGE_FORMAT_VERSION);
+        assert!(info.data_dir.ends_with(".chain_db_data"));
+
+        crate::api::registry::get_registry().close("test_health_db");
+        std::fs::remove_dir_all(".chain_db_data/test_health_db").unwrap();
+    }
 }
@@ -0,0 +1,613 @@
+//! Multi-record, multi-table transactions.
+//!
+//! Writes are buffered in a [`Transaction`] and only touch the disk on [`Transaction::commit`]:
+//!
+//! 1. Every operation is applied in memory to the affected data files and table metadata.
+//!    If any operation fails (e.g. an update of a missing `doc_id`, or a table that doesn't
+//!    exist), nothing is written.
+//! 2. The new contents of every affected file are written to the database journal
+//!    (`journal.cdb`, encrypted like any other file). Renaming the journal into place is the
+//!    commit point.
+//! 3. The files are replaced one by one and the journal is removed.
+//!
+//! If the process stops, or replacing a file fails, between steps 2 and 3, the journal is
+//! replayed the next time the database is opened with `ChainDB::connect`, or before the next
+//! commit, so either all operations are visible or none.
+//!
+//! While committing, the transaction holds the database lock (one journal at a time) and an
+//! exclusive lock on every table it writes, taken in name order.
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::chaindb::ChainDB;
+use crate::encryption::DataEncryption;
+use crate::errors::ChainDBError;
+use crate::events::{emit_table_persist, emit_table_update};
+use crate::lock::{FileLock, LockMode};
+use crate::record::{FieldLayout, Record};
+use crate::retention::Liveness;
+use crate::table::{
+    assign_system_fields, check_revision, data_file_name, record_revision, TableMetadata,
+    WriteStamp, METADATA_FILE, RECORDS_PER_FILE,
+};
+
+pub const JOURNAL_FILE: &str = "journal.cdb";
+const JOURNAL_TEMP_FILE: &str = "journal.cdb.tmp";
+
+/// A buffered write of a transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Appends a new record. The `doc_id` is generated when the operation is added
+    Persist {
+        table: String,
+        doc_id: String,
+        record: serde_json::Value,
+        /// Where the fields of the record are, from its type (not part of the serialized form)
+        #[serde(skip)]
+        layout: FieldLayout,
+    },
+    /// Replaces the record with the given `doc_id`
+    Update {
+        table: String,
+        doc_id: String,
+        record: serde_json::Value,
+        #[serde(skip)]
+        layout: FieldLayout,
+        /// Fails the transaction if the record is not at this revision
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expected_revision: Option<u64>,
+    },
+}
+
+impl Operation {
+    pub fn table(&self) -> &str {
+        match self {
+            Operation::Persist { table, .. } | Operation::Update { table, .. } => table,
+        }
+    }
+
+    pub fn doc_id(&self) -> &str {
+        match self {
+            Operation::Persist { doc_id, .. } | Operation::Update { doc_id, .. } => doc_id,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Persist { .. } => "persist",
+            Operation::Update { .. } => "update",
+        }
+    }
+}
+
+/// Result of a committed operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationResult {
+    /// `persist` or `update`
+    pub op: String,
+    pub table: String,
+    pub doc_id: String,
+    /// Revision of the record after the transaction
+    pub revision: u64,
+}
+
+/// A set of writes across tables that is committed all-or-nothing.
+///
+/// Dropping a transaction without calling `commit` discards its operations.
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    db: &'a ChainDB,
+    operations: Vec<Operation>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(db: &'a ChainDB) -> Self {
+        Self {
+            db,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Buffers a new record for `table` and returns the `doc_id` it will receive
+    pub fn persist<T: Record>(&mut self, table: &str, record: &T) -> Result<String, ChainDBError> {
+        let doc_id = Uuid::new_v4().to_string();
+        self.operations.push(Operation::Persist {
+            table: table.to_string(),
+            doc_id: doc_id.clone(),
+            record: serde_json::to_value(record)?,
+            layout: T::LAYOUT,
+        });
+        Ok(doc_id)
+    }
+
+    /// Buffers the replacement of the record `doc_id` of `table`, optionally only if it is at
+    /// `expected_revision`. The record may also be one persisted earlier in this transaction
+    pub fn update<T: Record>(
+        &mut self,
+        table: &str,
+        doc_id: &str,
+        record: &T,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ChainDBError> {
+        self.operations.push(Operation::Update {
+            table: table.to_string(),
+            doc_id: doc_id.to_string(),
+            record: serde_json::to_value(record)?,
+            layout: T::LAYOUT,
+            expected_revision,
+        });
+        Ok(())
+    }
+
+    /// Adds an already built operation
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    /// Operations buffered so far
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Applies every buffered operation, or none of them.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<OperationResult>)` - One result per operation, in order
+    /// * `Err(ChainDBError::TransactionError)` - If an operation failed, including operations on
+    ///   tables that don't exist or names that aren't a plain table name. Nothing was written
+    /// * `Err(ChainDBError)` - If writing the journal failed (nothing was written), or if
+    ///   replacing the files failed after the journal was written (the transaction is then
+    ///   completed the next time the database is opened or before the next commit)
+    pub fn commit(self) -> Result<Vec<OperationResult>, ChainDBError> {
+        let base_path = &self.db.base_path;
+        let encryption = &self.db.encryption;
+        let lock_timeout = self.db.lock_timeout;
+
+        // Os nomes viram caminhos dentro do banco, então são validados antes de qualquer escrita
+        let existing_tables = self.db.list_tables()?;
+        for (index, operation) in self.operations.iter().enumerate() {
+            check_table_name(operation.table(), &existing_tables)
+                .map_err(|e| operation_error(index, operation, e))?;
+        }
+
+        // Os locks das tabelas são adquiridos sempre na mesma ordem, evitando deadlocks
+        let _db_lock = FileLock::database(base_path, LockMode::Exclusive, lock_timeout)?;
+
+        // Um journal de uma transação anterior que não terminou de ser aplicada (o servidor mantém
+        // os bancos abertos, então `recover` não roda de novo) é concluído antes deste commit,
+        // para não ser sobrescrito
+        replay_journal(base_path, encryption, lock_timeout)?;
+
+        let table_names: BTreeSet<&str> = self.operations.iter().map(Operation::table).collect();
+        let mut table_locks = Vec::with_capacity(table_names.len());
+        for table_name in table_names {
+            let table_path = base_path.join(table_name);
+            fs::create_dir_all(&table_path)?;
+            table_locks.push(FileLock::table(
+                &table_path,
+                LockMode::Exclusive,
+                lock_timeout,
+            )?);
+        }
+
+        let mut tables: BTreeMap<String, StagedTable> = BTreeMap::new();
+        let mut revisions = Vec::with_capacity(self.operations.len());
+        for (index, operation) in self.operations.iter().enumerate() {
+            let table_name = operation.table();
+            let staged = match tables.get_mut(table_name) {
+                Some(staged) => staged,
+                None => {
+                    let staged = StagedTable::open(base_path, table_name, encryption)
+                        .map_err(|e| operation_error(index, operation, e))?;
+                    tables.entry(table_name.to_string()).or_insert(staged)
+                }
+            };
+
+            let revision = staged
+                .apply(operation, encryption, self.db.user())
+                .map_err(|e| operation_error(index, operation, e))?;
+            revisions.push(revision);
+        }
+
+        let mut entries = Vec::new();
+        for staged in tables.values() {
+            entries.extend(staged.journal_entries(encryption)?);
+        }
+
+        if !entries.is_empty() {
+            let journal = Journal {
+                id: Uuid::new_v4().to_string(),
+                entries,
+            };
+            write_journal(base_path, encryption, &journal)?;
+            apply_journal(base_path, &journal)?;
+            fs::remove_file(base_path.join(JOURNAL_FILE))?;
+        }
+
+        let db_name = base_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+
+        let results = self
+            .operations
+            .into_iter()
+            .zip(revisions)
+            .map(|(operation, revision)| {
+                let result = OperationResult {
+                    op: operation.name().to_string(),
+                    table: operation.table().to_string(),
+                    doc_id: operation.doc_id().to_string(),
+                    revision,
+                };
+
+                match operation {
+                    Operation::Persist {
+                        table,
+                        record,
+                        layout,
+                        ..
+                    } => emit_table_persist(db_name, &table, Some(layout.event_data(record))),
+                    Operation::Update {
+                        table,
+                        record,
+                        layout,
+                        ..
+                    } => emit_table_update(db_name, &table, Some(layout.event_data(record))),
+                }
+
+                result
+            })
+            .collect();
+
+        Ok(results)
+    }
+}
+
+fn operation_error(index: usize, operation: &Operation, error: ChainDBError) -> ChainDBError {
+    ChainDBError::TransactionError(format!(
+        "Operation {} ({} on table '{}') failed: {}",
+        index,
+        operation.name(),
+        operation.table(),
+        error
+    ))
+}
+
+/// In-memory copy of the parts of a table touched by a transaction
+struct StagedTable {
+    name: String,
+    path: PathBuf,
+    metadata: TableMetadata,
+    /// Lines of the data files read so far, by file index
+    files: BTreeMap<u32, Vec<String>>,
+    modified_files: Vec<u32>,
+    metadata_modified: bool,
+}
+
+impl StagedTable {
+    fn open(
+        base_path: &Path,
+        name: &str,
+        encryption: &DataEncryption,
+    ) -> Result<Self, ChainDBError> {
+        let path = base_path.join(name);
+        let metadata = TableMetadata::load(&path, encryption)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            // Tabelas novas são criadas no commit
+            metadata_modified: metadata.is_none(),
+            metadata: metadata.unwrap_or_else(TableMetadata::new),
+            files: BTreeMap::new(),
+            modified_files: Vec::new(),
+        })
+    }
+
+    fn apply(
+        &mut self,
+        operation: &Operation,
+        encryption: &DataEncryption,
+        user: &str,
+    ) -> Result<u64, ChainDBError> {
+        match operation {
+            Operation::Persist {
+                doc_id,
+                record,
+                layout,
+                ..
+            } => {
+                let mut record_value = record.clone();
+                let stamp = WriteStamp::created(Some(user));
+                assign_system_fields(&mut record_value, *layout, doc_id, 1, &stamp);
+                let line = encrypt_line(&record_value, encryption)?;
+
+                let current_file = (self.metadata.total_records as usize / RECORDS_PER_FILE) as u32;
+                self.lines_mut(current_file)?.push(line);
+                self.mark_modified(current_file);
+
+                self.metadata.current_file = current_file;
+                self.metadata.total_records += 1;
+                self.metadata_modified = true;
+                Ok(1)
+            }
+            Operation::Update {
+                doc_id,
+                record,
+                layout,
+                expected_revision,
+                ..
+            } => {
+                if self.metadata.total_records > 0 {
+                    for file_index in (0..=self.metadata.current_file).rev() {
+                        if let Some((position, current)) =
+                            self.find_line(file_index, doc_id, *layout, encryption)?
+                        {
+                            let current_revision = record_revision(&current);
+                            check_revision(doc_id, *expected_revision, current_revision)?;
+
+                            let mut record_value = record.clone();
+                            let stamp = WriteStamp::updated(&current, Some(user));
+                            assign_system_fields(
+                                &mut record_value,
+                                *layout,
+                                doc_id,
+                                current_revision + 1,
+                                &stamp,
+                            );
+                            let line = encrypt_line(&record_value, encryption)?;
+
+                            self.lines_mut(file_index)?[position] = line;
+                            self.mark_modified(file_index);
+                            return Ok(current_revision + 1);
+                        }
+                    }
+                }
+
+                Err(ChainDBError::RecordNotFound(format!(
+                    "Record with doc_id {} not found in table '{}'",
+                    doc_id, self.name
+                )))
+            }
+        }
+    }
+
+    fn lines_mut(&mut self, file_index: u32) -> Result<&mut Vec<String>, ChainDBError> {
+        if !self.files.contains_key(&file_index) {
+            let file_path = self.path.join(data_file_name(file_index));
+            let lines = if file_path.exists() {
+                BufReader::new(File::open(&file_path)?)
+                    .lines()
+                    .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+                    .collect::<Result<Vec<String>, _>>()?
+            } else {
+                Vec::new()
+            };
+            self.files.insert(file_index, lines);
+        }
+
+        Ok(self.files.entry(file_index).or_default())
+    }
+
+    /// Position and current value of the record `doc_id` in a data file, if present
+    fn find_line(
+        &mut self,
+        file_index: u32,
+        doc_id: &str,
+        layout: FieldLayout,
+        encryption: &DataEncryption,
+    ) -> Result<Option<(usize, serde_json::Value)>, ChainDBError> {
+        // Documentos expirados não podem ser alterados, como em `Table::update`
+        let liveness = Liveness::new(&self.metadata, layout);
+        for (position, line) in self.lines_mut(file_index)?.iter().enumerate() {
+            let decoded = general_purpose::STANDARD.decode(line)?;
+            let decrypted = encryption.decrypt(&decoded)?;
+            let record_value: serde_json::Value = serde_json::from_slice(&decrypted)?;
+
+            if record_value.get("doc_id").and_then(|id| id.as_str()) == Some(doc_id)
+                && liveness.is_live(Liveness::index(file_index, position), &record_value)
+            {
+                return Ok(Some((position, record_value)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn mark_modified(&mut self, file_index: u32) {
+        if !self.modified_files.contains(&file_index) {
+            self.modified_files.push(file_index);
+        }
+    }
+
+    /// New contents of every file modified by the transaction
+    fn journal_entries(
+        &self,
+        encryption: &DataEncryption,
+    ) -> Result<Vec<JournalEntry>, ChainDBError> {
+        let mut entries = Vec::new();
+
+        for file_index in &self.modified_files {
+            let mut contents = String::new();
+            for line in &self.files[file_index] {
+                contents.push_str(line);
+                contents.push('\n');
+            }
+            entries.push(JournalEntry::new(
+                &self.name,
+                &data_file_name(*file_index),
+                contents.as_bytes(),
+            ));
+        }
+
+        if self.metadata_modified {
+            entries.push(JournalEntry::new(
+                &self.name,
+                METADATA_FILE,
+                &self.metadata.encrypt(encryption)?,
+            ));
+        }
+
+        Ok(entries)
+    }
+}
+
+fn encrypt_line(
+    record_value: &serde_json::Value,
+    encryption: &DataEncryption,
+) -> Result<String, ChainDBError> {
+    let record_json = serde_json::to_vec(record_value)?;
+    let encrypted_record = encryption.encrypt(&record_json)?;
+    Ok(general_purpose::STANDARD.encode(encrypted_record))
+}
+
+/// Committed transaction waiting to be applied to the table files
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    id: String,
+    entries: Vec<JournalEntry>,
+}
+
+/// New contents of a single table file
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    table: String,
+    file: String,
+    /// File contents, base64 encoded
+    contents: String,
+}
+
+impl JournalEntry {
+    fn new(table: &str, file: &str, contents: &[u8]) -> Self {
+        Self {
+            table: table.to_string(),
+            file: file.to_string(),
+            contents: general_purpose::STANDARD.encode(contents),
+        }
+    }
+}
+
+/// Writes the journal and makes it durable. Once this returns, the transaction is committed
+fn write_journal(
+    base_path: &Path,
+    encryption: &DataEncryption,
+    journal: &Journal,
+) -> Result<(), ChainDBError> {
+    let journal_json = serde_json::to_vec(journal)?;
+    let encrypted_journal = encryption.encrypt(&journal_json)?;
+
+    let temp_path = base_path.join(JOURNAL_TEMP_FILE);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&encrypted_journal)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, base_path.join(JOURNAL_FILE))?;
+    sync_dir(base_path);
+    Ok(())
+}
+
+/// Replaces every file listed in the journal. Safe to run more than once
+fn apply_journal(base_path: &Path, journal: &Journal) -> Result<(), ChainDBError> {
+    for entry in &journal.entries {
+        let table_path = base_path.join(&entry.table);
+        fs::create_dir_all(&table_path)?;
+
+        let contents = general_purpose::STANDARD.decode(&entry.contents)?;
+        let temp_path = table_path.join(format!("{}.tmp", entry.file));
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, table_path.join(&entry.file))?;
+    }
+
+    for table in journal.entries.iter().map(|entry| &entry.table) {
+        sync_dir(&base_path.join(table));
+    }
+    Ok(())
+}
+
+/// Finishes a transaction interrupted after its commit point, and discards journals that were
+/// never committed. Called when a database is opened
+pub(crate) fn recover(
+    base_path: &Path,
+    encryption: &DataEncryption,
+    lock_timeout: Duration,
+) -> Result<(), ChainDBError> {
+    // Uma transação em andamento em outro processo mantém o lock do banco até remover o journal
+    let _db_lock = FileLock::database(base_path, LockMode::Exclusive, lock_timeout)?;
+    replay_journal(base_path, encryption, lock_timeout)
+}
+
+/// Does the work of `recover`. The caller must hold the exclusive database lock
+fn replay_journal(
+    base_path: &Path,
+    encryption: &DataEncryption,
+    lock_timeout: Duration,
+) -> Result<(), ChainDBError> {
+    let temp_path = base_path.join(JOURNAL_TEMP_FILE);
+    if temp_path.exists() {
+        println!("Discarding uncommitted transaction journal");
+        fs::remove_file(&temp_path)?;
+    }
+
+    let journal_path = base_path.join(JOURNAL_FILE);
+    if !journal_path.exists() {
+        return Ok(());
+    }
+
+    let encrypted_journal = fs::read(&journal_path)?;
+    let journal_json = encryption.decrypt(&encrypted_journal)?;
+    let journal: Journal = serde_json::from_slice(&journal_json)?;
+
+    println!("Replaying committed transaction {}", journal.id);
+    let table_names: BTreeSet<&str> = journal.entries.iter().map(|e| e.table.as_str()).collect();
+    let mut table_locks = Vec::with_capacity(table_names.len());
+    for table_name in table_names {
+        let table_path = base_path.join(table_name);
+        fs::create_dir_all(&table_path)?;
+        table_locks.push(FileLock::table(
+            &table_path,
+            LockMode::Exclusive,
+            lock_timeout,
+        )?);
+    }
+    apply_journal(base_path, &journal)?;
+    fs::remove_file(&journal_path)?;
+    Ok(())
+}
+
+/// Accepts only the name of an existing table, as a single path segment not starting with a dot
+fn check_table_name(name: &str, existing_tables: &[String]) -> Result<(), ChainDBError> {
+    let mut components = Path::new(name).components();
+    let single_segment = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    if !single_segment || name.starts_with('.') {
+        return Err(ChainDBError::ValidationError(format!(
+            "Invalid table name: {}",
+            name
+        )));
+    }
+
+    if !existing_tables.iter().any(|table| table == name) {
+        return Err(ChainDBError::TableNotFound(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Makes renames inside `dir` durable. Not supported on every platform, so errors are ignored
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
@@ -0,0 +1,442 @@
+//! Export and import of table records as NDJSON, JSON arrays or CSV.
+//!
+//! Records are exported in their stored form (`doc_id`, `revision` and the other system fields
+//! next to the user data) one at a time, and imported in batches while the input is read, so
+//! neither side keeps the whole table in memory.
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+
+use crate::errors::ChainDBError;
+use crate::record::FieldLayout;
+
+/// Records written to the table at a time during an import
+pub(crate) const IMPORT_BATCH_SIZE: usize = 500;
+/// Errors kept in an `ImportReport`; later ones are only counted
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Format of exported and imported data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferFormat {
+    /// One JSON record per line
+    #[default]
+    Ndjson,
+    /// A single JSON array of records
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+impl TransferFormat {
+    /// MIME type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            TransferFormat::Ndjson => "application/x-ndjson",
+            TransferFormat::Json => "application/json",
+            TransferFormat::Csv => "text/csv",
+        }
+    }
+}
+
+impl FromStr for TransferFormat {
+    type Err = ChainDBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(TransferFormat::Ndjson),
+            "json" => Ok(TransferFormat::Json),
+            "csv" => Ok(TransferFormat::Csv),
+            other => Err(ChainDBError::ValidationError(format!(
+                "Unknown format '{}': expected ndjson, json or csv",
+                other
+            ))),
+        }
+    }
+}
+
+/// A CSV column and the record field it holds. Fields are dotted paths, e.g. `data.name`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvColumn {
+    pub header: String,
+    pub field: String,
+}
+
+impl CsvColumn {
+    pub fn new(header: &str, field: &str) -> Self {
+        Self {
+            header: header.to_string(),
+            field: field.to_string(),
+        }
+    }
+
+    /// Parses a comma separated list of columns, each either `field` or `header=field`
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(|column| match column.split_once('=') {
+                Some((header, field)) => Self::new(header.trim(), field.trim()),
+                None => Self::new(column, column),
+            })
+            .collect()
+    }
+}
+
+/// How `Table::export` writes the records
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub format: TransferFormat,
+    /// CSV columns. Defaults to every field of the first exported record
+    #[serde(default)]
+    pub columns: Option<Vec<CsvColumn>>,
+}
+
+impl From<TransferFormat> for ExportOptions {
+    fn from(format: TransferFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+}
+
+/// What to do with the `doc_id` of imported records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocIdMode {
+    /// Keep the imported `doc_id`, revision and write metadata. Records without a `doc_id` get
+    /// a new one, and records whose `doc_id` is already in the table are rejected
+    #[default]
+    Preserve,
+    /// Store every record as a new document, as `persist` does
+    Regenerate,
+}
+
+impl FromStr for DocIdMode {
+    type Err = ChainDBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "preserve" => Ok(DocIdMode::Preserve),
+            "regenerate" => Ok(DocIdMode::Regenerate),
+            other => Err(ChainDBError::ValidationError(format!(
+                "Unknown doc_id mode '{}': expected preserve or regenerate",
+                other
+            ))),
+        }
+    }
+}
+
+/// How `Table::import` reads the records
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub format: TransferFormat,
+    /// Fields of the CSV columns, by header. Defaults to the header itself
+    #[serde(default)]
+    pub columns: Option<Vec<CsvColumn>>,
+    #[serde(default)]
+    pub doc_ids: DocIdMode,
+}
+
+impl From<TransferFormat> for ImportOptions {
+    fn from(format: TransferFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+}
+
+/// A record that could not be imported
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportError {
+    /// Position of the record in the input, starting at 1
+    pub record: u64,
+    pub message: String,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub failed: u64,
+    /// The first errors found
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    pub(crate) fn fail(&mut self, record: u64, error: &ChainDBError) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(ImportError {
+                record,
+                message: error.to_string(),
+            });
+        }
+    }
+}
+
+/// Writes `records` to `writer` in the format of `options`, returning how many were written
+pub(crate) fn export_records<I, W>(
+    records: I,
+    layout: FieldLayout,
+    writer: W,
+    options: &ExportOptions,
+) -> Result<u64, ChainDBError>
+where
+    I: Iterator<Item = Result<serde_json::Value, ChainDBError>>,
+    W: Write,
+{
+    let mut count = 0;
+    match options.format {
+        TransferFormat::Ndjson => {
+            let mut writer = writer;
+            for record in records {
+                serde_json::to_writer(&mut writer, &record?)?;
+                writer.write_all(b"\n")?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+        TransferFormat::Json => {
+            let mut writer = writer;
+            writer.write_all(b"[")?;
+            for record in records {
+                writer.write_all(if count == 0 { b"\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut writer, &record?)?;
+                count += 1;
+            }
+            writer.write_all(b"\n]\n")?;
+            writer.flush()?;
+        }
+        TransferFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            let mut columns = options.columns.clone();
+            for record in records {
+                let record = record?;
+                let columns = columns.get_or_insert_with(|| {
+                    let mut fields = Vec::new();
+                    flatten_fields(&record, "", &mut fields);
+                    fields
+                        .iter()
+                        .map(|field| CsvColumn::new(field, field))
+                        .collect()
+                });
+                if count == 0 {
+                    csv_writer.write_record(columns.iter().map(|column| &column.header))?;
+                }
+                csv_writer.write_record(
+                    columns
+                        .iter()
+                        .map(|column| csv_cell(&record, layout, column)),
+                )?;
+                count += 1;
+            }
+            if count == 0 {
+                if let Some(columns) = &columns {
+                    csv_writer.write_record(columns.iter().map(|column| &column.header))?;
+                }
+            }
+            csv_writer.flush()?;
+        }
+    }
+    Ok(count)
+}
+
+/// Reads the records of `reader`, handing them to `write_batch` in batches of
+/// `IMPORT_BATCH_SIZE`, together with their position in the input.
+///
+/// A record that can't be parsed is reported and skipped; a malformed file (e.g. a JSON
+/// document that isn't an array) stops the import with an error.
+pub(crate) fn import_records<R, F>(
+    reader: R,
+    options: &ImportOptions,
+    mut write_batch: F,
+) -> Result<ImportReport, ChainDBError>
+where
+    R: Read,
+    F: FnMut(Vec<(u64, serde_json::Value)>, &mut ImportReport) -> Result<(), ChainDBError>,
+{
+    let mut report = ImportReport::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut position = 0;
+
+    let mut visit = |record: Result<serde_json::Value, ChainDBError>,
+                     report: &mut ImportReport|
+     -> Result<(), ChainDBError> {
+        position += 1;
+        match record {
+            Ok(record) if record.is_object() => batch.push((position, record)),
+            Ok(_) => report.fail(
+                position,
+                &ChainDBError::ValidationError("Record is not a JSON object".to_string()),
+            ),
+            Err(e) => report.fail(position, &e),
+        }
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            write_batch(std::mem::take(&mut batch), report)?;
+        }
+        Ok(())
+    };
+
+    match options.format {
+        TransferFormat::Ndjson => {
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                visit(serde_json::from_str(&line).map_err(Into::into), &mut report)?;
+            }
+        }
+        TransferFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+            let mut failure = None;
+            let result = ArrayVisitor {
+                visit: |record| visit(Ok(record), &mut report),
+                failure: &mut failure,
+            }
+            .deserialize(&mut deserializer);
+            // Um erro de gravação tem precedência sobre o erro de leitura que ele provocou
+            if let Some(e) = failure {
+                return Err(e);
+            }
+            result?;
+            deserializer.end()?;
+        }
+        TransferFormat::Csv => {
+            let mut csv_reader = csv::Reader::from_reader(reader);
+            let fields: Vec<Option<String>> = csv_reader
+                .headers()?
+                .iter()
+                .map(|header| match &options.columns {
+                    Some(columns) => columns
+                        .iter()
+                        .find(|column| column.header == header)
+                        .map(|column| column.field.clone()),
+                    None => Some(header.to_string()),
+                })
+                .collect();
+
+            for row in csv_reader.records() {
+                let record = row.map_err(ChainDBError::from).map(|row| {
+                    let mut record = serde_json::Value::Object(serde_json::Map::new());
+                    for (field, cell) in fields.iter().zip(row.iter()) {
+                        if let (Some(field), false) = (field, cell.is_empty()) {
+                            set_path(&mut record, field, parse_cell(cell));
+                        }
+                    }
+                    record
+                });
+                visit(record, &mut report)?;
+            }
+        }
+    }
+
+    if !batch.is_empty() {
+        write_batch(batch, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Deserializes a JSON array one element at a time, handing each to `visit`
+struct ArrayVisitor<'a, F> {
+    visit: F,
+    failure: &'a mut Option<ChainDBError>,
+}
+
+impl<'de, F> DeserializeSeed<'de> for ArrayVisitor<'_, F>
+where
+    F: FnMut(serde_json::Value) -> Result<(), ChainDBError>,
+{
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for ArrayVisitor<'_, F>
+where
+    F: FnMut(serde_json::Value) -> Result<(), ChainDBError>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON array of records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element::<serde_json::Value>()? {
+            if let Err(e) = (self.visit)(record) {
+                let message = e.to_string();
+                *self.failure = Some(e);
+                return Err(de::Error::custom(message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Dotted paths of the leaf values of `value`
+fn flatten_fields(value: &serde_json::Value, prefix: &str, fields: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() || prefix.is_empty() => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_fields(child, &path, fields);
+            }
+        }
+        _ => fields.push(prefix.to_string()),
+    }
+}
+
+/// Text of a CSV cell: strings as they are, other values as JSON and missing values empty
+fn csv_cell(record: &serde_json::Value, layout: FieldLayout, column: &CsvColumn) -> String {
+    let value = record
+        .as_object()
+        .and_then(|record_obj| layout.resolve(record_obj, &column.field));
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Value of a CSV cell: numbers, booleans, `null`, arrays and objects are read as JSON and
+/// anything else as a string
+fn parse_cell(cell: &str) -> serde_json::Value {
+    match serde_json::from_str::<serde_json::Value>(cell) {
+        Ok(serde_json::Value::String(_)) | Err(_) => serde_json::Value::String(cell.to_string()),
+        Ok(value) => value,
+    }
+}
+
+/// Sets the field at the dotted `path`, creating the intermediate objects
+fn set_path(record: &mut serde_json::Value, path: &str, value: serde_json::Value) {
+    let mut current = record;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let serde_json::Value::Object(map) = current else {
+            return;
+        };
+        if segments.peek().is_none() {
+            map.insert(segment.to_string(), value);
+            return;
+        }
+        current = map
+            .entry(segment.to_string())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    }
+}